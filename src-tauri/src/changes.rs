@@ -0,0 +1,273 @@
+/// The `ConnectionId` type.
+use crate::connections::ConnectionId;
+/// The SQL lexer, which recognizes the statements that open and close savepoints.
+use crate::db_manager::lexer::{self, SavepointStatement};
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `Arc` type, which shares a listener between the writer and the file watcher.
+use std::sync::Arc;
+/// The `Duration` type.
+use std::time::Duration;
+
+/// The number of changed rows reported for one transaction. Past it only the changed tables are
+/// reported, since a view of the table has to be reloaded anyway.
+pub const MAX_ROW_CHANGES: usize = 1000;
+
+/// How long the file watcher waits for writes to the database file to settle before it checks
+/// whether another process committed.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// `ChangeOperation` is an enum that tells how a row was changed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// `ChangedTable` is a struct that names a table whose rows were changed.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangedTable {
+    /// The schema of the table, `main`, `temp` or the name of an attached database.
+    pub schema: String,
+    pub table_name: String,
+}
+
+/// `RowChange` is a struct that describes a row that was inserted, updated or deleted.
+#[derive(Serialize, Debug, Clone)]
+pub struct RowChange {
+    pub schema: String,
+    pub table_name: String,
+    pub operation: ChangeOperation,
+    pub rowid: i64,
+}
+
+/// `CommittedChanges` is a struct that holds the rows a transaction changed.
+#[derive(Debug, Clone, Default)]
+pub struct CommittedChanges {
+    /// Every table whose rows were changed, in the order they were first changed.
+    pub tables: Vec<ChangedTable>,
+    /// The changed rows, in order, up to `MAX_ROW_CHANGES`.
+    pub rows: Vec<RowChange>,
+    /// Whether more rows were changed than `rows` holds.
+    pub truncated: bool,
+}
+
+/// `CommittedChanges` implementation.
+impl CommittedChanges {
+    /// Records a changed row.
+    pub fn record(
+        &mut self,
+        schema: &str,
+        table_name: &str,
+        operation: ChangeOperation,
+        rowid: i64,
+    ) {
+        let known = self
+            .tables
+            .iter()
+            .any(|table| table.schema == schema && table.table_name == table_name);
+        if !known {
+            self.tables.push(ChangedTable {
+                schema: schema.to_string(),
+                table_name: table_name.to_string(),
+            });
+        }
+        if self.rows.len() < MAX_ROW_CHANGES {
+            self.rows.push(RowChange {
+                schema: schema.to_string(),
+                table_name: table_name.to_string(),
+                operation,
+                rowid,
+            });
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    /// Returns whether no row was changed.
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Returns how many changes are recorded, so the ones recorded after can be dropped.
+    fn mark(&self) -> ChangeMark {
+        ChangeMark {
+            tables: self.tables.len(),
+            rows: self.rows.len(),
+            truncated: self.truncated,
+        }
+    }
+
+    /// Drops the changes recorded after `mark`. Tables are listed in the order they were first
+    /// changed, so the ones listed after `mark` were not changed before it.
+    fn truncate(&mut self, mark: ChangeMark) {
+        self.tables.truncate(mark.tables);
+        self.rows.truncate(mark.rows);
+        self.truncated &= mark.truncated;
+    }
+}
+
+/// `ChangeMark` is a struct that records how many changes a transaction had recorded when a
+/// savepoint was opened.
+#[derive(Debug, Clone, Copy)]
+struct ChangeMark {
+    tables: usize,
+    rows: usize,
+    truncated: bool,
+}
+
+/// `ChangeTracker` is a struct that follows the rows changed by the transactions of a connection,
+/// so only the changes that are committed are reported.
+///
+/// SQLite's hooks do not tell everything: rolling back to a savepoint calls no hook, and the
+/// commit hook is called before the commit is known to succeed. So the tracker is also told about
+/// every statement once it returns; it keeps the savepoints that are open, and only hands the
+/// changes to the listener once the statement that committed them returns with the connection
+/// back in autocommit mode.
+pub struct ChangeTracker {
+    /// The changes of the open transaction.
+    pending: CommittedChanges,
+    /// The open savepoints, innermost last, with the changes recorded when each was opened.
+    savepoints: Vec<(String, ChangeMark)>,
+    /// Whether the commit hook was called since the last statement returned.
+    committing: bool,
+    listener: CommitListener,
+}
+
+/// `ChangeTracker` implementation.
+impl ChangeTracker {
+    /// Creates a tracker that hands the changes of each committed transaction to `listener`.
+    pub fn new(listener: CommitListener) -> Self {
+        ChangeTracker {
+            pending: CommittedChanges::default(),
+            savepoints: vec![],
+            committing: false,
+            listener,
+        }
+    }
+
+    /// Records a changed row, from the update hook.
+    pub fn record(
+        &mut self,
+        schema: &str,
+        table_name: &str,
+        operation: ChangeOperation,
+        rowid: i64,
+    ) {
+        self.pending.record(schema, table_name, operation, rowid);
+    }
+
+    /// Notes that the open transaction is about to commit, from the commit hook.
+    pub fn commit(&mut self) {
+        self.committing = true;
+    }
+
+    /// Drops the changes of the open transaction, from the rollback hook.
+    pub fn rollback(&mut self) {
+        self.pending = CommittedChanges::default();
+        self.savepoints.clear();
+        self.committing = false;
+    }
+
+    /// Follows the savepoints opened and closed by `sql` once it returns, then hands the changes
+    /// to the listener if it committed them.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The statements that ran.
+    /// * `succeeded` - Whether every statement succeeded.
+    /// * `autocommit` - Whether the connection is in autocommit mode, i.e. no transaction is open.
+    pub fn statement_returned(&mut self, sql: &str, succeeded: bool, autocommit: bool) {
+        if succeeded {
+            for statement in lexer::split_statements(sql) {
+                match lexer::savepoint_statement(statement) {
+                    Some(SavepointStatement::Savepoint(name)) => {
+                        let mark = self.pending.mark();
+                        self.savepoints.push((name, mark));
+                    }
+                    Some(SavepointStatement::RollbackTo(name)) => {
+                        // the savepoint stays open, the ones opened after it are closed
+                        if let Some(index) = self.savepoint(&name) {
+                            self.pending.truncate(self.savepoints[index].1);
+                            self.savepoints.truncate(index + 1);
+                        }
+                    }
+                    Some(SavepointStatement::Release(name)) => {
+                        if let Some(index) = self.savepoint(&name) {
+                            self.savepoints.truncate(index);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        if !autocommit {
+            // a commit that failed, e.g. on a busy database, leaves the transaction open
+            self.committing = false;
+            return;
+        }
+        let changes = std::mem::take(&mut self.pending);
+        self.savepoints.clear();
+        if std::mem::take(&mut self.committing) && succeeded && !changes.is_empty() {
+            (self.listener)(changes);
+        }
+    }
+
+    /// Returns the index of the innermost open savepoint named `name`.
+    /// Savepoint names are not case sensitive.
+    fn savepoint(&self, name: &str) -> Option<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|(open, _)| open.eq_ignore_ascii_case(name))
+    }
+}
+
+/// `CommitListener` is called on the writer's thread with the rows of each transaction it commits.
+/// It must not use the connection.
+pub type CommitListener = Box<dyn FnMut(CommittedChanges) + Send>;
+
+/// `RowsChanged` is the payload of the `rows-changed` event, sent when a transaction of the
+/// application commits.
+#[derive(Serialize, Debug, Clone)]
+pub struct RowsChanged {
+    pub connection_id: ConnectionId,
+    /// Every table whose rows were changed.
+    pub tables: Vec<ChangedTable>,
+    /// The changed rows, up to `MAX_ROW_CHANGES`. Rows of `WITHOUT ROWID` tables are not reported.
+    pub rows: Vec<RowChange>,
+    /// Whether more rows were changed than `rows` holds.
+    pub truncated: bool,
+}
+
+/// `DatabaseChanged` is the payload of the `database-changed` event, sent when another process
+/// commits to the database file. Which rows changed is not known.
+#[derive(Serialize, Debug, Clone)]
+pub struct DatabaseChanged {
+    pub connection_id: ConnectionId,
+    /// The database file.
+    pub path: String,
+}
+
+/// `ChangeEvent` is an enum that holds the payload of a change notification.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ChangeEvent {
+    Rows(RowsChanged),
+    Database(DatabaseChanged),
+}
+
+/// `ChangeEvent` implementation.
+impl ChangeEvent {
+    /// Returns the name of the event the payload is sent as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeEvent::Rows(_) => "rows-changed",
+            ChangeEvent::Database(_) => "database-changed",
+        }
+    }
+}
+
+/// `ChangeListener` receives the change notifications of a connection.
+pub type ChangeListener = Arc<dyn Fn(ChangeEvent) + Send + Sync>;
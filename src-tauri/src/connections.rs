@@ -0,0 +1,457 @@
+/// The change types, which report the changes made to a database.
+use crate::changes::{ChangeEvent, ChangeListener, DatabaseChanged, RowsChanged, WATCH_DEBOUNCE};
+/// The `ConnectionType` and `ConnectionError` enums and the `DbManager` struct from the `db_manager` module.
+use crate::db_manager::{row_count_key, ConnectionError, ConnectionType, DbManager, TableQuery};
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `RowCounts` cache and the `RowCountKind` enum.
+use crate::row_counts::{CountSource, RowCountKind, RowCounts};
+/// The `Worker` struct, which runs the jobs of a connection on its own thread.
+use crate::worker::Worker;
+/// The `TableRequest` struct.
+use crate::TableRequest;
+/// The `RecommendedWatcher` type and the `Watcher` trait from the `notify` crate, which watch database files.
+use notify::{event::ModifyKind, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `BTreeMap` type, which keeps connections in the order they were opened.
+use std::collections::BTreeMap;
+/// The `Path` type.
+use std::path::Path;
+/// The `Arc` and `Mutex` types, which share the status of a connection with its worker.
+use std::sync::{Arc, Mutex};
+/// The `mpsc` channel, which hands the events of the file watcher to the task checking them.
+use tokio::sync::mpsc;
+
+/// `ConnectionId` identifies an open connection for the lifetime of the application.
+pub type ConnectionId = u64;
+
+/// `ConnectionInfo` is a struct that describes an open connection.
+#[derive(Serialize, Debug, Clone)]
+pub struct ConnectionInfo {
+    pub id: ConnectionId,
+    /// `"sqlite"` or `"libsql"`.
+    pub backend: String,
+    /// The database file path or the libsql URL. The auth token is never included.
+    pub location: String,
+    /// Whether edits are being staged.
+    pub editing: bool,
+    /// Whether there are staged edits that have not been committed or rolled back.
+    pub dirty: bool,
+    /// Whether there is an edit to undo.
+    pub can_undo: bool,
+    /// Whether there is an undone edit to redo.
+    pub can_redo: bool,
+}
+
+/// `ConnectionInfo` implementation.
+impl ConnectionInfo {
+    /// Describes a connection from its backend, location and editing state.
+    pub fn new(
+        id: ConnectionId,
+        backend: String,
+        location: String,
+        status: ConnectionStatus,
+    ) -> Self {
+        ConnectionInfo {
+            id,
+            backend,
+            location,
+            editing: status.editing,
+            dirty: status.dirty,
+            can_undo: status.can_undo,
+            can_redo: status.can_redo,
+        }
+    }
+}
+
+/// `ConnectionStatus` is a struct that holds the editing state of a connection, as of the last job
+/// its writer ran. It can be read without waiting for a running query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStatus {
+    pub editing: bool,
+    pub dirty: bool,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+/// `ConnectionStatus` implementation.
+impl ConnectionStatus {
+    /// Reads the editing state of a database manager.
+    fn of(db_manager: &DbManager) -> Self {
+        ConnectionStatus {
+            editing: db_manager.pending_edits.is_some(),
+            dirty: db_manager.is_dirty(),
+            can_undo: db_manager.journal.can_undo(),
+            can_redo: db_manager.journal.can_redo(),
+        }
+    }
+}
+
+/// `StatusListener` is called with the editing state of a connection whenever it changes.
+pub type StatusListener = Arc<dyn Fn(ConnectionStatus) + Send + Sync>;
+
+/// `Connection` is a struct that holds the workers of an open connection.
+///
+/// Every write, and every query of the SQL console, runs on the writer. Browsing runs on a
+/// separate read-only connection when the database can be opened twice, so it is not held up
+/// by a long query. While edits are staged, browsing runs on the writer instead, because the
+/// reader cannot see the staged edits.
+///
+/// The exact row counts of tables are counted in the background on a third connection, the
+/// counter, so a slow count does not hold up browsing. They are cached until a change is
+/// detected; pages show an estimate until then.
+#[derive(Clone)]
+pub struct Connection {
+    pub connection_type: ConnectionType,
+    writer: Worker,
+    reader: Option<Worker>,
+    counter: Option<Worker>,
+    status: Arc<Mutex<ConnectionStatus>>,
+    row_counts: Arc<Mutex<RowCounts>>,
+    /// Watches the database file once `watch_changes` is called. It stops when the last clone
+    /// of the connection is dropped.
+    watcher: Arc<Mutex<Option<RecommendedWatcher>>>,
+    status_listener: Arc<Mutex<Option<StatusListener>>>,
+}
+
+/// `Connection` implementation.
+impl Connection {
+    /// Connects to the database at `path` and starts the workers of the connection.
+    /// This blocks until the database is opened, so it should not run on an async task.
+    pub fn open(path: &str, create: bool) -> Result<Self, ConnectionError> {
+        let db_manager = DbManager::connect(path, create)?;
+        // the path of a libsql connection holds its auth token, which thread names must not show
+        let (_, location) = db_manager.connection_type.describe();
+        let reader = match db_manager.open_reader()? {
+            Some(reader) => Some(Worker::spawn(format!("{} (reader)", location), reader)?),
+            None => None,
+        };
+        let counter = match db_manager.open_reader()? {
+            Some(counter) => Some(Worker::spawn(format!("{} (counter)", location), counter)?),
+            None => None,
+        };
+        Ok(Connection {
+            connection_type: db_manager.connection_type.clone(),
+            writer: Worker::spawn(format!("{} (writer)", location), db_manager)?,
+            reader,
+            counter,
+            status: Arc::new(Mutex::new(ConnectionStatus::default())),
+            row_counts: Arc::new(Mutex::new(RowCounts::default())),
+            watcher: Arc::new(Mutex::new(None)),
+            status_listener: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Returns the backend of the connection, `"sqlite"` or `"libsql"`, and its location: the
+    /// database file path or the libsql URL, without the auth token.
+    pub fn describe(&self) -> (String, String) {
+        self.connection_type.describe()
+    }
+
+    /// Sends the changes made to the database to `listener`: a `RowsChanged` event for each
+    /// transaction the writer commits, and, for database files, a `DatabaseChanged` event when
+    /// another process commits. Those are noticed by watching the file and its write-ahead log,
+    /// then checking `PRAGMA data_version`, so writes of the application itself are not reported
+    /// twice. libsql connections report neither.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_id` - The id the events carry.
+    /// * `listener` - Called with each event, from the writer's thread or from a task.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if the database file cannot be watched.
+    pub async fn watch_changes(
+        &self,
+        connection_id: ConnectionId,
+        listener: ChangeListener,
+    ) -> Result<(), KitError> {
+        let on_commit = listener.clone();
+        let version = self
+            .writer
+            .run(move |db_manager| {
+                db_manager.db.watch_changes(Box::new(move |changes| {
+                    on_commit(ChangeEvent::Rows(RowsChanged {
+                        connection_id,
+                        tables: changes.tables,
+                        rows: changes.rows,
+                        truncated: changes.truncated,
+                    }));
+                }));
+                db_manager.data_version()
+            })
+            .await?;
+        let path = match &self.connection_type {
+            ConnectionType::Sqlite(path) if Path::new(path).is_file() => path.clone(),
+            _ => return Ok(()),
+        };
+        let file = Path::new(&path);
+        let (dir, file_name) = match (file.parent(), file.file_name()) {
+            (Some(dir), Some(file_name)) => (dir, file_name.to_os_string()),
+            _ => return Ok(()),
+        };
+        let mut wal_name = file_name.clone();
+        wal_name.push("-wal");
+
+        // the directory is watched, since the write-ahead log comes and goes
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(_) => return,
+                };
+                let written =
+                    matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)
+                    ) && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)));
+                let ours = event.paths.iter().any(|path| {
+                    path.file_name()
+                        .is_some_and(|name| name == file_name || name == wal_name)
+                });
+                if written && ours {
+                    let _ = sender.send(());
+                }
+            })
+            .map_err(|e| KitError::invalid_state(format!("cannot watch {}: {}", path, e)))?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| KitError::invalid_state(format!("cannot watch {}: {}", path, e)))?;
+        *self.watcher.lock().unwrap() = Some(watcher);
+
+        // the task holds the writer but not the connection, so it ends once the watcher is dropped
+        let writer = self.writer.clone();
+        tokio::spawn(async move {
+            let mut last_version = version;
+            while receiver.recv().await.is_some() {
+                tokio::time::sleep(WATCH_DEBOUNCE).await;
+                while receiver.try_recv().is_ok() {}
+                let version = match writer.run(|db_manager| db_manager.data_version()).await {
+                    Ok(version) => version,
+                    // a busy or closed database is checked again on the next write
+                    Err(_) => continue,
+                };
+                if version != last_version {
+                    last_version = version;
+                    listener(ChangeEvent::Database(DatabaseChanged {
+                        connection_id,
+                        path: path.clone(),
+                    }));
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Sets the listener called with the editing state of the connection whenever a job of the
+    /// writer changes it.
+    pub fn on_status_change(&self, listener: StatusListener) {
+        *self.status_listener.lock().unwrap() = Some(listener);
+    }
+
+    /// Runs a job that may write on the writer, records the editing state it leaves, and
+    /// clears the cached row counts if the job changed rows. Commits of the writer also move
+    /// the `PRAGMA data_version` of the other connections, which catches schema changes too.
+    pub async fn write<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut DbManager) -> Result<T, KitError> + Send + 'static,
+    ) -> Result<T, KitError> {
+        let status = self.status.clone();
+        let row_counts = self.row_counts.clone();
+        let status_listener = self.status_listener.clone();
+        self.writer
+            .run(move |db_manager| {
+                let changes_before = db_manager.db.total_changes().ok();
+                let result = job(db_manager);
+                let new_status = ConnectionStatus::of(db_manager);
+                let old_status = std::mem::replace(&mut *status.lock().unwrap(), new_status);
+                // a count that cannot be read is taken as a change
+                if changes_before.is_none() || db_manager.db.total_changes().ok() != changes_before
+                {
+                    row_counts.lock().unwrap().invalidate();
+                }
+                if new_status != old_status {
+                    let listener = status_listener.lock().unwrap().clone();
+                    if let Some(listener) = listener {
+                        listener(new_status);
+                    }
+                }
+                result
+            })
+            .await
+    }
+
+    /// Runs a read-only job on the writer, which sees the temporary objects and the staged edits.
+    pub async fn read_on_writer<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut DbManager) -> Result<T, KitError> + Send + 'static,
+    ) -> Result<T, KitError> {
+        self.writer.run(job).await
+    }
+
+    /// Runs a read-only job on the reader, or on the writer if there is no reader or edits are staged.
+    pub async fn read<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut DbManager) -> Result<T, KitError> + Send + 'static,
+    ) -> Result<T, KitError> {
+        self.read_schema(None, job).await
+    }
+
+    /// Runs a read-only job on the objects of `schema`. Temporary objects only exist on the writer,
+    /// so jobs on the `temp` schema run there.
+    pub async fn read_schema<T: Send + 'static>(
+        &self,
+        schema: Option<&str>,
+        job: impl FnOnce(&mut DbManager) -> Result<T, KitError> + Send + 'static,
+    ) -> Result<T, KitError> {
+        match self.reader_for(schema) {
+            Some(reader) => reader.run(job).await,
+            None => self.writer.run(job).await,
+        }
+    }
+
+    /// Fetches a page of a table, with its exact row count if it is cached. Otherwise the page
+    /// has an estimate, and the exact count is started in the background on the counter, unless
+    /// it is already running, so `count_rows` returns it sooner.
+    pub async fn table_data(
+        &self,
+        table_name: String,
+        query: TableQuery,
+    ) -> Result<TableRequest, KitError> {
+        let key = match self.counter_for(query.schema.as_deref()) {
+            Some(_) => Some(row_count_key(&table_name, &query)?),
+            None => None,
+        };
+        let row_counts = self.row_counts.clone();
+        let (name, page_query, page_key) = (table_name.clone(), query.clone(), key.clone());
+        let page = self
+            .read_schema(query.schema.as_deref(), move |db_manager| {
+                let mut page = db_manager.get_table_data(&name, &page_query)?;
+                if let Some(key) = page_key.filter(|_| page.row_count_kind != RowCountKind::Exact) {
+                    let mut row_counts = row_counts.lock().unwrap();
+                    row_counts.check(CountSource::Reader, db_manager.data_version()?);
+                    if let Some(count) = row_counts.get(&key) {
+                        page.row_count = count;
+                        page.row_count_kind = RowCountKind::Exact;
+                    }
+                }
+                Ok(page)
+            })
+            .await?;
+        if let Some(key) = key.filter(|_| page.row_count_kind != RowCountKind::Exact) {
+            if self.row_counts.lock().unwrap().start(key.clone()) {
+                let connection = self.clone();
+                tokio::spawn(async move {
+                    // the caller asks for the count with `count_rows`, which reports any error
+                    let _ = connection.count_rows(table_name, query).await;
+                    connection.row_counts.lock().unwrap().finish(&key);
+                });
+            }
+        }
+        Ok(page)
+    }
+
+    /// Counts the rows of a table matching the filters of `query`, or returns the cached count.
+    /// Counts run on the counter are cached; counts of staged edits and temporary tables are not.
+    pub async fn count_rows(&self, table_name: String, query: TableQuery) -> Result<i64, KitError> {
+        let counter = match self.counter_for(query.schema.as_deref()) {
+            Some(counter) => counter,
+            None => {
+                let schema = query.schema.clone();
+                return self
+                    .read_schema(schema.as_deref(), move |db_manager| {
+                        db_manager.count_rows(&table_name, &query)
+                    })
+                    .await;
+            }
+        };
+        let row_counts = self.row_counts.clone();
+        counter
+            .run(move |db_manager| {
+                let key = row_count_key(&table_name, &query)?;
+                let generation = {
+                    let mut row_counts = row_counts.lock().unwrap();
+                    row_counts.check(CountSource::Counter, db_manager.data_version()?);
+                    if let Some(count) = row_counts.get(&key) {
+                        return Ok(count);
+                    }
+                    row_counts.generation()
+                };
+                let count = db_manager.count_rows(&table_name, &query)?;
+                row_counts.lock().unwrap().store(generation, key, count);
+                Ok(count)
+            })
+            .await
+    }
+
+    /// Returns the reader if read-only jobs on the objects of `schema` can run there.
+    fn reader_for(&self, schema: Option<&str>) -> Option<&Worker> {
+        match (&self.reader, schema) {
+            (_, Some("temp")) => None,
+            (Some(reader), _) if !self.status().editing => Some(reader),
+            _ => None,
+        }
+    }
+
+    /// Returns the counter if the rows of `schema` can be counted and cached there, which is
+    /// when pages of it are read on the reader.
+    fn counter_for(&self, schema: Option<&str>) -> Option<&Worker> {
+        self.reader_for(schema).and(self.counter.as_ref())
+    }
+
+    /// Returns the editing state of the connection as of the last job its writer ran.
+    pub fn status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+}
+
+/// `ConnectionRegistry` is a struct that holds every open connection, keyed by its id.
+///
+/// The registry is only locked to look a connection up; the work itself is sent to the
+/// connection's workers, so one slow connection does not hold up the others.
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: BTreeMap<ConnectionId, Connection>,
+    next_id: ConnectionId,
+}
+
+/// `ConnectionRegistry` implementation.
+impl ConnectionRegistry {
+    /// Adds an opened connection and returns its id.
+    /// Connections that are already open are left untouched.
+    pub fn insert(&mut self, connection: Connection) -> ConnectionId {
+        self.next_id += 1;
+        self.connections.insert(self.next_id, connection);
+        self.next_id
+    }
+
+    /// Closes a connection. Its workers stop, and drop the database handles, once their queued jobs are done.
+    pub fn close(&mut self, id: ConnectionId) -> Result<(), KitError> {
+        match self.connections.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(KitError::UnknownConnection { connection_id: id }),
+        }
+    }
+
+    /// Returns an open connection.
+    pub fn get(&self, id: ConnectionId) -> Result<Connection, KitError> {
+        self.connections
+            .get(&id)
+            .cloned()
+            .ok_or(KitError::UnknownConnection { connection_id: id })
+    }
+
+    /// Lists the open connections in the order they were opened.
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .iter()
+            .map(|(id, connection)| {
+                let (backend, location) = connection.describe();
+                ConnectionInfo::new(*id, backend, location, connection.status())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,238 @@
+/// The `ConnectionId` type.
+use crate::connections::ConnectionId;
+/// The `DbManager` struct, the `QueryParams` enum and the `StreamItem` enum from the `db_manager` module.
+use crate::db_manager::{DbManager, QueryParams, StreamItem};
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `QueryControl` struct, which stops the query of a closed cursor.
+use crate::queries::QueryControl;
+/// The `ColumnInfo` struct.
+use crate::ColumnInfo;
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `BTreeMap` type, which holds the open cursors by id.
+use std::collections::BTreeMap;
+/// The `Arc` type, which shares a cursor with the command fetching from it.
+use std::sync::Arc;
+/// The `thread` module, used to run the query of a cursor.
+use std::thread;
+/// The channel and lock types of `tokio`, which let commands wait for rows without blocking.
+use tokio::sync::{mpsc, Mutex};
+
+/// The number of rows a cursor reads ahead of the rows fetched from it. The query waits
+/// when they have not been fetched yet, so a slow reader holds at most this many rows.
+pub const CURSOR_BUFFER: usize = 1000;
+
+/// The number of bytes of cell values above which a batch is cut short. A batch always holds
+/// at least one row, however large.
+pub const MAX_BATCH_BYTES: usize = 8 * 1024 * 1024;
+
+/// `CursorId` identifies an open cursor for the lifetime of the application.
+pub type CursorId = u64;
+
+/// `OpenedCursor` is a struct that describes a cursor that was just opened.
+#[derive(Serialize, Debug)]
+pub struct OpenedCursor {
+    pub cursor_id: CursorId,
+    pub column_names: Vec<ColumnInfo>,
+}
+
+/// `CursorBatch` is a struct that holds the rows fetched from a cursor in one call.
+#[derive(Serialize, Debug)]
+pub struct CursorBatch {
+    pub rows: Vec<Vec<SerializableValue>>,
+    /// Whether every row has been fetched. The cursor still has to be closed.
+    pub done: bool,
+}
+
+/// `Cursor` is a struct that reads the result of a query on its own thread and connection,
+/// so it can be fetched in batches without holding the whole result in memory or holding up
+/// the connection it was opened from. The query stops when the cursor is dropped.
+pub struct Cursor {
+    rows: mpsc::Receiver<Result<StreamItem, KitError>>,
+    /// A row that did not fit in the previous batch.
+    held: Option<Vec<SerializableValue>>,
+    /// An error that ended the query after the rows of the previous batch.
+    failed: Option<KitError>,
+    done: bool,
+    control: QueryControl,
+}
+
+/// `Cursor` implementation.
+impl Cursor {
+    /// Starts running `query` on `db_manager` and waits for its columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_manager` - A connection of its own, dropped when the query ends.
+    /// * `query` - The query to run.
+    /// * `params` - The values of the parameters of the query.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Cursor, Vec<ColumnInfo>), KitError>` - The cursor and the columns of the result.
+    pub async fn open(
+        mut db_manager: DbManager,
+        query: String,
+        params: QueryParams,
+    ) -> Result<(Self, Vec<ColumnInfo>), KitError> {
+        let (sender, mut rows) = mpsc::channel(CURSOR_BUFFER);
+        let control = QueryControl::default();
+        let query_control = control.clone();
+        thread::Builder::new()
+            .name("cursor".to_string())
+            .spawn(move || {
+                // sending fails once the cursor is dropped, which stops the query
+                let result =
+                    db_manager.stream_query(&query, &params, &query_control, &mut |item| {
+                        sender.blocking_send(Ok(item)).is_ok()
+                    });
+                if let Err(e) = result {
+                    let _ = sender.blocking_send(Err(e));
+                }
+            })
+            .map_err(|e| KitError::invalid_state(e.to_string()))?;
+        let columns = match rows.recv().await {
+            Some(Ok(StreamItem::Columns(columns))) => columns,
+            Some(Err(e)) => return Err(e),
+            _ => {
+                return Err(KitError::invalid_state(
+                    "the cursor stopped before reading its columns",
+                ))
+            }
+        };
+        let cursor = Cursor {
+            rows,
+            held: None,
+            failed: None,
+            done: false,
+            control,
+        };
+        Ok((cursor, columns))
+    }
+
+    /// Fetches up to `count` rows, fewer if their cell values exceed `MAX_BATCH_BYTES`.
+    /// Waits until the rows are read or the result ends.
+    pub async fn fetch(&mut self, count: usize) -> Result<CursorBatch, KitError> {
+        if let Some(e) = self.failed.take() {
+            self.done = true;
+            return Err(e);
+        }
+        let mut rows = Vec::new();
+        let mut bytes = 0;
+        while rows.len() < count && !self.done {
+            let row = match self.held.take() {
+                Some(row) => row,
+                None => match self.rows.recv().await {
+                    Some(Ok(StreamItem::Row(row))) => row,
+                    Some(Ok(StreamItem::Columns(_))) => continue,
+                    Some(Err(e)) if rows.is_empty() => {
+                        self.done = true;
+                        return Err(e);
+                    }
+                    Some(Err(e)) => {
+                        self.failed = Some(e);
+                        break;
+                    }
+                    None => {
+                        self.done = true;
+                        break;
+                    }
+                },
+            };
+            let size = row.iter().map(cell_bytes).sum::<usize>();
+            if !rows.is_empty() && bytes + size > MAX_BATCH_BYTES {
+                self.held = Some(row);
+                break;
+            }
+            bytes += size;
+            rows.push(row);
+        }
+        Ok(CursorBatch {
+            rows,
+            done: self.done,
+        })
+    }
+}
+
+/// This implementation stops the query of a cursor that is closed before its result ends.
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        self.control.cancel();
+    }
+}
+
+/// Returns roughly how many bytes a cell value takes up.
+fn cell_bytes(value: &SerializableValue) -> usize {
+    match value {
+        SerializableValue::Null => 1,
+        SerializableValue::Integer(_) | SerializableValue::Real(_) => 8,
+        SerializableValue::Text(text) => text.len(),
+        SerializableValue::Blob(blob) => blob.len(),
+    }
+}
+
+/// `OpenCursor` is a struct that holds a cursor in the registry, along with what is needed to
+/// close it while a fetch holds its lock.
+struct OpenCursor {
+    connection_id: ConnectionId,
+    control: QueryControl,
+    cursor: Arc<Mutex<Cursor>>,
+}
+
+/// `CursorRegistry` is a struct that holds every open cursor, keyed by its id.
+#[derive(Default)]
+pub struct CursorRegistry {
+    cursors: BTreeMap<CursorId, OpenCursor>,
+    next_id: CursorId,
+}
+
+/// `CursorRegistry` implementation.
+impl CursorRegistry {
+    /// Adds a cursor opened from a connection and returns its id.
+    pub fn insert(&mut self, connection_id: ConnectionId, cursor: Cursor) -> CursorId {
+        self.next_id += 1;
+        let open = OpenCursor {
+            connection_id,
+            control: cursor.control.clone(),
+            cursor: Arc::new(Mutex::new(cursor)),
+        };
+        self.cursors.insert(self.next_id, open);
+        self.next_id
+    }
+
+    /// Returns an open cursor.
+    pub fn get(&self, id: CursorId) -> Result<Arc<Mutex<Cursor>>, KitError> {
+        self.cursors
+            .get(&id)
+            .map(|open| open.cursor.clone())
+            .ok_or_else(|| KitError::invalid_argument(format!("there is no open cursor {}", id)))
+    }
+
+    /// Closes a cursor and stops its query, even while rows are being fetched from it.
+    pub fn close(&mut self, id: CursorId) -> Result<(), KitError> {
+        match self.cursors.remove(&id) {
+            Some(open) => {
+                open.control.cancel();
+                Ok(())
+            }
+            None => Err(KitError::invalid_argument(format!(
+                "there is no open cursor {}",
+                id
+            ))),
+        }
+    }
+
+    /// Closes every cursor opened from a connection.
+    pub fn close_connection(&mut self, connection_id: ConnectionId) {
+        self.cursors.retain(|_, open| {
+            let keep = open.connection_id != connection_id;
+            if !keep {
+                open.control.cancel();
+            }
+            keep
+        });
+    }
+}
@@ -1,7 +1,26 @@
+use crate::changes::CommitListener;
+use crate::error::KitError;
 use crate::native::NativeDbManager;
-use crate::TableRequest;
-use crate::{libsql::LibsqlDbManager, SerializableValue};
-use rusqlite::{Connection, Result};
+use crate::queries::QueryControl;
+use crate::row_counts::RowCountKind;
+use crate::schema::load_table_schema_if_exists;
+use crate::schema::{
+    load_key_shape, load_schema_objects, load_table_schema, SchemaObject, TableSchema,
+};
+use crate::{libsql::LibsqlDbManager, ColumnInfo, SerializableValue};
+use crate::{TableRequest, DEFAULT_PAGE_SIZE};
+use explain::{Bytecode, QueryPlan};
+use journal::{Journal, JournalEntry};
+use rusqlite::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Instant;
+
+pub mod explain;
+pub mod journal;
+pub mod lexer;
+pub mod sql;
 
 /// `ConnectionType` is an enum that represents the type of database connection.
 /// It can be one of two types: `Sqlite` or `Libsql`.
@@ -9,151 +28,1163 @@ use rusqlite::{Connection, Result};
 pub enum ConnectionType {
     /// `Sqlite` variant takes a string which represents the path to the sqlite database.
     Sqlite(String),
-    /// `Libsql` variant takes two strings which represent the host and token for the libsql database.
-    Libsql(String, String),
+    /// `Libsql` variant takes the host and the optional auth token for the libsql database.
+    Libsql(String, Option<String>),
+}
+
+/// `ConnectionType` implementation.
+impl ConnectionType {
+    /// Returns the backend of the connection, `"sqlite"` or `"libsql"`, and its location: the
+    /// database file path or the libsql URL, without the auth token.
+    pub fn describe(&self) -> (String, String) {
+        match self {
+            ConnectionType::Sqlite(path) => ("sqlite".to_string(), path.clone()),
+            ConnectionType::Libsql(host, _) => ("libsql".to_string(), host.clone()),
+        }
+    }
+}
+
+/// `ConnectionError` is an enum that represents why a connection could not be opened.
+/// It is serialized with a `kind` tag so the frontend can react to each case.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConnectionError {
+    /// The database file does not exist and creating it was not requested.
+    NotFound { path: String },
+    /// The database file exists but cannot be read.
+    PermissionDenied { path: String },
+    /// The file exists but is not an SQLite database, or is encrypted.
+    NotADatabase { path: String },
+    /// The libsql URL could not be parsed.
+    BadUrl { url: String, message: String },
+    /// The libsql server rejected the auth token.
+    AuthFailure { url: String, message: String },
+    /// Any other failure, such as an unreachable server.
+    Failed { location: String, message: String },
+}
+
+/// This implementation allows a `ConnectionError` to be shown as a message.
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::NotFound { path } => write!(f, "database file not found: {}", path),
+            ConnectionError::PermissionDenied { path } => {
+                write!(f, "permission denied: {}", path)
+            }
+            ConnectionError::NotADatabase { path } => {
+                write!(f, "file is not a database: {}", path)
+            }
+            ConnectionError::BadUrl { url, message } => {
+                write!(f, "invalid URL {}: {}", url, message)
+            }
+            ConnectionError::AuthFailure { url, message } => {
+                write!(f, "authentication failed for {}: {}", url, message)
+            }
+            ConnectionError::Failed { location, message } => {
+                write!(f, "could not connect to {}: {}", location, message)
+            }
+        }
+    }
+}
+
+/// `WriteResult` is a struct that describes the effect of a statement that changed rows.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct WriteResult {
+    /// The number of rows inserted, updated or deleted.
+    pub rows_affected: u64,
+    /// The rowid of the inserted row, for inserts into rowid tables.
+    pub last_insert_rowid: Option<i64>,
+    /// Whether the statement was queued to run when the staged edits are committed,
+    /// in which case the counts are not known yet.
+    pub staged: bool,
+}
+
+/// `StatementResult` is a struct that holds the result of one statement of a script.
+#[derive(Serialize, Debug, Clone)]
+pub struct StatementResult {
+    /// The statement as it was split from the script.
+    pub sql: String,
+    pub column_names: Vec<ColumnInfo>,
+    pub rows: Vec<Vec<SerializableValue>>,
+    /// The number of rows inserted, updated or deleted, not counting changes made by triggers.
+    pub rows_affected: u64,
+    /// The rowid of the last row inserted, for statements that inserted rows.
+    pub last_insert_rowid: Option<i64>,
+    /// How long the statement took to run, in milliseconds.
+    pub elapsed_ms: Option<f64>,
+}
+
+/// `QueryStats` is a struct that holds the counters SQLite keeps while a statement runs
+/// (`sqlite3_stmt_status`).
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct QueryStats {
+    /// The number of rows stepped through in full table scans. A large number suggests a missing index.
+    pub fullscan_steps: i32,
+    /// The number of sorts, which an index matching the `ORDER BY` would avoid.
+    pub sorts: i32,
+    /// The number of rows inserted into indexes built on the fly for a join.
+    pub autoindexes: i32,
+    /// The number of virtual machine instructions run.
+    pub vm_steps: i32,
+}
+
+/// `ScriptError` is a struct that describes the statement of a script that failed.
+#[derive(Serialize, Debug, Clone)]
+pub struct ScriptError {
+    /// The index of the statement that failed, `None` if the transaction of the script failed to
+    /// begin or to commit, or the script was stopped while waiting for a libsql server.
+    pub index: Option<usize>,
+    pub error: KitError,
+}
+
+/// `ScriptResult` is a struct that holds the results of the statements of a script.
+/// Statements run in order and the script stops at the first one that fails.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ScriptResult {
+    /// The results of the statements that ran, in order.
+    pub statements: Vec<StatementResult>,
+    /// The statement that failed, if any.
+    pub error: Option<ScriptError>,
+    /// Whether the statements were rolled back because one failed inside the transaction.
+    pub rolled_back: bool,
+    /// How long the whole script took to run, in milliseconds.
+    pub elapsed_ms: f64,
+}
+
+/// `StreamItem` is an enum that represents what a streamed query produces, in order: its columns
+/// once, then each of its rows.
+#[derive(Debug, Clone)]
+pub enum StreamItem {
+    Columns(Vec<ColumnInfo>),
+    Row(Vec<SerializableValue>),
+}
+
+/// `StreamSink` receives the items of a streamed query, and returns `false` to stop the query.
+pub type StreamSink<'a> = dyn FnMut(StreamItem) -> bool + 'a;
+
+/// `FilterOperator` is an enum that represents the comparison applied by a `Filter`.
+/// It is serialized as the SQL operator it stands for, e.g. `"="` or `"IS NULL"`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOperator {
+    #[serde(rename = "=")]
+    Eq,
+    #[serde(rename = "!=")]
+    NotEq,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = "LIKE")]
+    Like,
+    #[serde(rename = "IS NULL")]
+    IsNull,
+    #[serde(rename = "IN")]
+    In,
+    #[serde(rename = "BETWEEN")]
+    Between,
+}
+
+/// `Filter` is a struct that represents a single condition on a column.
+/// `values` holds no value for `IS NULL`, two values for `BETWEEN`, one or more values
+/// for `IN` and exactly one value for every other operator.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Filter {
+    pub column: String,
+    pub operator: FilterOperator,
+    #[serde(default)]
+    pub values: Vec<SerializableValue>,
+}
+
+/// `OrderBy` is a struct that represents a column to sort by.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrderBy {
+    pub column: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// `QueryParams` is an enum that holds the values bound to the parameters of a query.
+/// It is deserialized from a JSON array of values, or from an object of values keyed by name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum QueryParams {
+    /// One value per parameter index, in order, for `?` and `?NNN` parameters.
+    Positional(Vec<SerializableValue>),
+    /// The values of `:name`, `@name` and `$name` parameters, keyed by name with or without its prefix.
+    Named(BTreeMap<String, SerializableValue>),
+}
+
+/// This implementation allows for the creation of default `QueryParams`: no values.
+impl Default for QueryParams {
+    fn default() -> Self {
+        QueryParams::Positional(vec![])
+    }
 }
 
+/// `QueryParams` implementation.
+impl QueryParams {
+    /// Returns the value of each parameter in index order, given the parameter names of the statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The name of each parameter in index order, `None` for anonymous parameters.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<SerializableValue>, KitError>` - The values to bind, or `InvalidArgument` if a value is missing or unused.
+    pub fn resolve(&self, names: &[Option<String>]) -> Result<Vec<SerializableValue>, KitError> {
+        match self {
+            QueryParams::Positional(values) if values.len() == names.len() => Ok(values.clone()),
+            QueryParams::Positional(values) => Err(KitError::invalid_argument(format!(
+                "the statement has {} parameters but {} values were given",
+                names.len(),
+                values.len()
+            ))),
+            QueryParams::Named(values) => {
+                let unknown = values.keys().find(|key| {
+                    !names
+                        .iter()
+                        .flatten()
+                        .any(|name| name == *key || &name[1..] == key.as_str())
+                });
+                if let Some(unknown) = unknown {
+                    return Err(KitError::invalid_argument(format!(
+                        "the statement has no parameter {}",
+                        unknown
+                    )));
+                }
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        let name = name.as_deref().ok_or_else(|| {
+                            KitError::invalid_argument(format!(
+                                "parameter {} has no name, so values must be given in order",
+                                i + 1
+                            ))
+                        })?;
+                        let value = values
+                            .get(name)
+                            .or_else(|| values.get(&name[1..]))
+                            .ok_or_else(|| {
+                                KitError::invalid_argument(format!(
+                                    "no value was given for parameter {}",
+                                    name
+                                ))
+                            })?;
+                        Ok(value.clone())
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// `RowKey` is an enum that identifies a single row of a table.
+/// It is serialized with a `kind` tag, e.g. `{ "kind": "rowid", "rowid": 4 }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RowKey {
+    /// The rowid of a row in a table without a declared primary key.
+    Rowid { rowid: i64 },
+    /// The value of the primary key column of a table with a single-column primary key.
+    PrimaryKey {
+        column: String,
+        value: SerializableValue,
+    },
+    /// The values of the primary key columns of a table with a composite primary key, in key order.
+    CompositeKey {
+        columns: Vec<String>,
+        values: Vec<SerializableValue>,
+    },
+}
+
+/// `RowKey` implementation.
+impl RowKey {
+    /// Returns the key the row has once `column` is set to `value`.
+    /// The key is unchanged unless `column` is one of its columns.
+    pub fn with_value(&self, column: &str, value: &SerializableValue) -> RowKey {
+        let mut key = self.clone();
+        match &mut key {
+            RowKey::Rowid { rowid } => {
+                let is_rowid = ["rowid", "oid", "_rowid_"]
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(column));
+                if let (true, SerializableValue::Integer(value)) = (is_rowid, value) {
+                    *rowid = *value;
+                }
+            }
+            RowKey::PrimaryKey {
+                column: key_column,
+                value: key_value,
+            } => {
+                if key_column.eq_ignore_ascii_case(column) {
+                    *key_value = value.clone();
+                }
+            }
+            RowKey::CompositeKey { columns, values } => {
+                if let Some(i) = columns
+                    .iter()
+                    .position(|key_column| key_column.eq_ignore_ascii_case(column))
+                {
+                    values[i] = value.clone();
+                }
+            }
+        }
+        key
+    }
+}
+
+/// `KeyShape` is an enum that describes how the rows of a table are identified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyShape {
+    /// Rows are identified by their rowid.
+    Rowid,
+    /// Rows are identified by the primary key columns, in key order.
+    PrimaryKey(Vec<String>),
+}
+
+/// `KeyShape` implementation.
+impl KeyShape {
+    /// Returns the number of values that make up a key of this shape.
+    pub fn column_count(&self) -> usize {
+        match self {
+            KeyShape::Rowid => 1,
+            KeyShape::PrimaryKey(columns) => columns.len(),
+        }
+    }
+
+    /// Builds the `RowKey` of a row from its key values, or `None` if the values do not fit the shape.
+    pub fn row_key(&self, mut values: Vec<SerializableValue>) -> Option<RowKey> {
+        match self {
+            KeyShape::Rowid => match values.as_slice() {
+                [SerializableValue::Integer(rowid)] => Some(RowKey::Rowid { rowid: *rowid }),
+                _ => None,
+            },
+            KeyShape::PrimaryKey(columns) if columns.len() == 1 && values.len() == 1 => {
+                Some(RowKey::PrimaryKey {
+                    column: columns[0].clone(),
+                    value: values.remove(0),
+                })
+            }
+            KeyShape::PrimaryKey(columns) if columns.len() == values.len() => {
+                Some(RowKey::CompositeKey {
+                    columns: columns.clone(),
+                    values,
+                })
+            }
+            KeyShape::PrimaryKey(_) => None,
+        }
+    }
+}
+
+/// `Edit` is an enum that represents a change made to the rows of a table.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Edit {
+    Insert {
+        /// The columns the values are given for, empty for every column in table order.
+        columns: Vec<String>,
+        row: Vec<SerializableValue>,
+    },
+    Update {
+        column: String,
+        key: RowKey,
+        value: SerializableValue,
+    },
+    Delete {
+        key: RowKey,
+    },
+}
+
+/// `PendingEdit` is a struct that represents an edit made since the staged edits were begun.
+#[derive(Serialize, Debug, Clone)]
+pub struct PendingEdit {
+    /// The attached schema of the table, `None` for the default search order.
+    pub schema: Option<String>,
+    pub table_name: String,
+    #[serde(flatten)]
+    pub edit: Edit,
+}
+
+/// `TableQuery` is a struct that describes which rows of a table to fetch.
+/// It holds the schema the table lives in, the page window, the filters (combined with `AND`)
+/// and the sort order.
+#[derive(Debug, Clone)]
+pub struct TableQuery {
+    /// The attached schema of the table, `None` for the default search order.
+    pub schema: Option<String>,
+    pub offset: i64,
+    pub limit: i64,
+    pub filters: Vec<Filter>,
+    pub order_by: Vec<OrderBy>,
+}
+
+/// This implementation allows for the creation of a default `TableQuery`: the first page, unfiltered.
+impl Default for TableQuery {
+    fn default() -> Self {
+        TableQuery {
+            schema: None,
+            offset: 0,
+            limit: DEFAULT_PAGE_SIZE,
+            filters: vec![],
+            order_by: vec![],
+        }
+    }
+}
+
+/// Builds the `ColumnInfo` of each result column.
+///
+/// The declared type of the column is used when it has one. Expressions have no declared
+/// type, so their type is taken from the first non-NULL value in `rows`, or `NULL` if
+/// every value is NULL.
+pub fn column_infos(
+    names: &[String],
+    declared_types: &[Option<String>],
+    rows: &[Vec<SerializableValue>],
+) -> Vec<ColumnInfo> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let type_name = match declared_types.get(i) {
+                Some(Some(declared_type)) if !declared_type.is_empty() => declared_type.clone(),
+                _ => rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .find_map(|value| match value {
+                        SerializableValue::Null => None,
+                        SerializableValue::Integer(_) => Some("INTEGER"),
+                        SerializableValue::Real(_) => Some("REAL"),
+                        SerializableValue::Text(_) => Some("TEXT"),
+                        SerializableValue::Blob(_) => Some("BLOB"),
+                    })
+                    .unwrap_or("NULL")
+                    .to_string(),
+            };
+            ColumnInfo {
+                name: name.clone(),
+                type_name,
+            }
+        })
+        .collect()
+}
+
+/// Returns the time since `started` in milliseconds.
+pub fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Returns the key the exact row count of a page is cached under: the statement that counts it.
+pub fn row_count_key(table_name: &str, query: &TableQuery) -> Result<String, KitError> {
+    let count = sql::count_rows(table_name, query)?;
+    Ok(format!("{}\n{:?}", count.sql, count.params))
+}
+
+/// Reads the first integer of a value, e.g. the row count at the start of a `sqlite_stat1` stat.
+fn leading_integer(value: &SerializableValue) -> Option<i64> {
+    match value {
+        SerializableValue::Integer(value) => Some(*value),
+        SerializableValue::Text(text) => text.split_whitespace().next()?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Returns whether a write can be recorded in the journal: it changed rows and was not queued
+/// to run later, in which case the row image read before it may not match.
+fn journaled(result: &WriteResult) -> bool {
+    result.rows_affected > 0 && !result.staged
+}
+
+/// `ResultRows` holds the column names and the rows returned by a statement.
+pub type ResultRows = (Vec<String>, Vec<Vec<SerializableValue>>);
+
+/// `RowImage` holds the names and values of the columns a row can be inserted again with.
+type RowImage = (Vec<String>, Vec<SerializableValue>);
+
 /// `DbManager` is a struct that holds a database manager.
 /// The database manager is a trait object that implements the `DbManagerTrait`.
 pub struct DbManager {
     /// `db` is a Box holding a trait object that implements `DbManagerTrait` and `Send`.
     pub db: Box<dyn DbManagerTrait + Send>,
+    /// `connection_type` is the database the manager is connected to.
+    pub connection_type: ConnectionType,
+    /// `pending_edits` holds the edits made since `begin_edits`, `None` when edits are not staged.
+    pub pending_edits: Option<Vec<PendingEdit>>,
+    /// `journal` holds the edits that can be undone and redone, across every table of the connection.
+    pub journal: Journal,
+    /// `journal_checkpoint` holds the journal as it was at `begin_edits`, restored when the edits are rolled back.
+    pub journal_checkpoint: Option<Journal>,
 }
 
 /// `DbManagerTrait` is a trait that defines the operations that a database manager should support.
 pub trait DbManagerTrait {
-    /// `get_all_tables` is a method that returns all table names in the database.
-    fn get_all_tables(&mut self) -> Result<Vec<String>, String>;
-    /// `get_table_data` is a method that returns the data of a specific table.
-    fn get_table_data(&mut self, table_name: &str) -> Result<TableRequest, String>;
+    /// `get_table_data` is a method that returns one filtered and sorted page of the data of a specific table.
+    /// When a `key` is given, its values are selected after the columns of the table.
+    fn get_table_data(
+        &mut self,
+        table_name: &str,
+        query: &TableQuery,
+        key: Option<&KeyShape>,
+    ) -> Result<TableRequest, KitError>;
     /// `remove_row` is a method that removes a specific row from a table.
+    /// The table is looked up in `schema`, or in the default search order if it is `None`.
     fn remove_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
-        col_name: &str,
-        row_id: i64,
-    ) -> Result<String, String>;
+        key: &RowKey,
+    ) -> Result<WriteResult, KitError>;
     /// `insert_row` is a method that inserts a new row into a table.
+    /// The values are given for `columns`, or for every column in table order if it is empty.
     fn insert_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
+        columns: &[String],
         row: Vec<SerializableValue>,
-    ) -> Result<String, String>;
+    ) -> Result<WriteResult, KitError>;
     /// `update_row` is a method that updates a specific row in a table.
     fn update_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
         col_name: &str,
-        index_col_name: &str,
-        id: i64,
+        key: &RowKey,
         value: SerializableValue,
-    ) -> Result<String, String>;
-    /// `run_query` is a method that runs a query on the database.
-    fn run_query(&mut self, query: &str) -> Result<TableRequest, String>;
+    ) -> Result<WriteResult, KitError>;
+    /// `run_query` is a method that runs a query on the database, with one value per parameter index,
+    /// until it finishes or `control` stops it.
+    fn run_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+    ) -> Result<TableRequest, KitError>;
+    /// `run_script` is a method that runs statements in order, optionally inside one transaction,
+    /// and stops at the first one that fails.
+    fn run_script(
+        &mut self,
+        statements: &[&str],
+        transaction: bool,
+        control: &QueryControl,
+    ) -> Result<ScriptResult, KitError>;
+    /// `stream_query` is a method that runs a query and hands its columns, then its rows one at a
+    /// time, to `sink`, until the rows run out, `sink` returns `false` or `control` stops the query.
+    fn stream_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+        sink: &mut StreamSink,
+    ) -> Result<(), KitError>;
+    /// `parameter_names` is a method that returns the name of each parameter of a statement
+    /// in index order, `None` for anonymous parameters.
+    fn parameter_names(&mut self, sql: &str) -> Result<Vec<Option<String>>, KitError>;
+    /// `query_rows` is a method that runs a single statement with bound parameters
+    /// and returns the column names and the rows of its result.
+    fn query_rows(
+        &mut self,
+        sql: &str,
+        params: &[SerializableValue],
+    ) -> Result<ResultRows, KitError>;
+    /// `begin_edits` is a method that starts staging edits, so they only take effect once committed.
+    fn begin_edits(&mut self) -> Result<(), KitError>;
+    /// `commit_edits` is a method that applies every staged edit at once.
+    fn commit_edits(&mut self) -> Result<(), KitError>;
+    /// `rollback_edits` is a method that discards every staged edit.
+    fn rollback_edits(&mut self) -> Result<(), KitError>;
+    /// `open_reader` is a method that opens a second connection to the same database, used to read
+    /// while this one is busy. It returns `None` when the database cannot be opened twice.
+    fn open_reader(&self) -> Result<Option<Box<dyn DbManagerTrait + Send>>, ConnectionError>;
+    /// `insert_rows` is a method that inserts rows into a table, with the values given for `columns`.
+    /// A row that fails is skipped and returned with its index in `rows` and its error.
+    fn insert_rows(
+        &mut self,
+        table_name: &str,
+        columns: &[String],
+        rows: &[Vec<SerializableValue>],
+    ) -> Result<Vec<(usize, KitError)>, KitError>;
+    /// `total_changes` is a method that returns a count which moves whenever a statement run on
+    /// this connection may have changed rows, so callers can tell whether a job wrote anything.
+    fn total_changes(&mut self) -> Result<u64, KitError>;
+    /// `watch_changes` is a method that hands the rows each committed transaction changed to
+    /// `listener`. It returns `false` when the backend cannot report changed rows.
+    fn watch_changes(&mut self, listener: CommitListener) -> bool;
 }
 
 /// `DbManager` implementation.
 impl DbManager {
-    /// Creates a new `DbManager` with a native database manager.
-    pub fn new() -> Self {
-        DbManager {
-            db: Box::new(NativeDbManager::new(Connection::open(":memory:").unwrap())),
-        }
-    }
-
-    /// Connects to a database given a path.
-    /// The path can be a `libsql` URL or a local file path.
-    pub fn connect_to_db(&mut self, path: &str) -> Result<usize, String> {
+    /// Connects to a database given a path and returns a new `DbManager` for it.
+    /// The path can be a `libsql` URL, optionally followed by `::` and an auth token, or a local file path.
+    /// A missing database file is only created when `create` is set; otherwise it is reported as not found.
+    pub fn connect(path: &str, create: bool) -> Result<Self, ConnectionError> {
         // check if starts with libsql:// or / or it's a relative path
         let connection_type = if path.starts_with("libsql://") {
+            let mut parts = path.splitn(2, "::");
             ConnectionType::Libsql(
-                path.split("::").next().unwrap().to_string(),
-                path.split("::").nth(1).unwrap().to_string(),
+                parts.next().unwrap_or_default().to_string(),
+                parts.next().map(|token| token.to_string()),
             )
         } else if path.starts_with('/') {
             ConnectionType::Sqlite(path.to_string())
         } else {
-            ConnectionType::Sqlite(format!(
-                "{}/../{}",
-                std::env::current_dir().unwrap().display(),
-                path
-            ))
+            let current_dir = std::env::current_dir().map_err(|e| ConnectionError::Failed {
+                location: path.to_string(),
+                message: e.to_string(),
+            })?;
+            ConnectionType::Sqlite(format!("{}/../{}", current_dir.display(), path))
         };
 
-        println!("Connecting to: {:?}", connection_type);
-
-        match connection_type {
-            ConnectionType::Sqlite(path) => {
-                self.db = Box::new(NativeDbManager::new(
-                    Connection::open_with_flags(
-                        path,
-                        // dont create file if it doesn't exist
-                        // rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
-                        rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
-                    )
-                    .unwrap_or(Connection::open_in_memory().unwrap()),
-                ));
-                Ok(1)
-            }
+        println!("Connecting to: {}", connection_type.describe().1);
+
+        let db: Box<dyn DbManagerTrait + Send> = match connection_type.clone() {
+            ConnectionType::Sqlite(path) => Box::new(NativeDbManager::open(&path, create)?),
             ConnectionType::Libsql(host, token) => {
-                println!("Connecting to libsql: {:?} {:?}", host, token);
-                self.db = Box::new(LibsqlDbManager::new(
-                    libsql_client::SyncClient::from_config(libsql_client::Config {
-                        url: host.as_str().try_into().unwrap(),
-                        auth_token: Some(token),
-                    })
-                    .unwrap(),
-                ));
-                Ok(1)
+                println!("Connecting to libsql: {:?}", host);
+                Box::new(LibsqlDbManager::connect(&host, token)?)
             }
+        };
+        Ok(DbManager::new(db, connection_type))
+    }
+
+    /// Creates a `DbManager` for an open database, with no staged edits and an empty journal.
+    pub fn new(db: Box<dyn DbManagerTrait + Send>, connection_type: ConnectionType) -> Self {
+        DbManager {
+            db,
+            connection_type,
+            pending_edits: None,
+            journal: Journal::default(),
+            journal_checkpoint: None,
         }
     }
 
-    /// Fetches the data of a specific table.
-    pub fn get_table_data(&mut self, table_name: &str) -> Result<TableRequest, String> {
-        println!("Getting table data for: {:?}", table_name);
-        self.db.get_table_data(table_name)
+    /// Opens a second, read-only manager for the same database, if it can be opened twice.
+    /// It does not see the edits staged on this one, nor its temporary tables.
+    pub fn open_reader(&self) -> Result<Option<DbManager>, ConnectionError> {
+        Ok(self
+            .db
+            .open_reader()?
+            .map(|db| DbManager::new(db, self.connection_type.clone())))
+    }
+
+    /// Fetches one filtered and sorted page of the data of a specific table.
+    /// Rows of tables carry the `RowKey` they can be edited and removed with.
+    pub fn get_table_data(
+        &mut self,
+        table_name: &str,
+        query: &TableQuery,
+    ) -> Result<TableRequest, KitError> {
+        println!("Getting table data for: {:?} ({:?})", table_name, query);
+        if query.offset < 0 || query.limit < 0 {
+            return Err(KitError::invalid_argument(
+                "offset and limit must not be negative",
+            ));
+        }
+        let key = load_key_shape(self.db.as_mut(), query.schema.as_deref(), table_name)?;
+        let mut table_data = self.db.get_table_data(table_name, query, key.as_ref())?;
+        let seen = query.offset + table_data.rows.len() as i64;
+        // a page that is not full ends the matching rows, so its count is known without counting;
+        // a page of no rows is never full
+        let ends_rows = query.limit > 0
+            && table_data.rows.len() < query.limit as usize
+            && (!table_data.rows.is_empty() || query.offset == 0);
+        (table_data.row_count, table_data.row_count_kind) = if ends_rows {
+            (seen, RowCountKind::Exact)
+        } else {
+            match self.estimate_row_count(table_name, query) {
+                Some(estimate) => (estimate.max(seen), RowCountKind::Estimate),
+                None => (seen, RowCountKind::AtLeast),
+            }
+        };
+        if let Some(key) = key {
+            // the key values were selected after the table columns, so they are split off the end
+            let width = table_data.column_names.len() - key.column_count();
+            table_data.column_names.truncate(width);
+            table_data.row_keys = table_data
+                .rows
+                .iter_mut()
+                .map(|row| key.row_key(row.split_off(width)))
+                .collect();
+        }
+        Ok(table_data)
+    }
+
+    /// Counts the rows of a table matching the filters of `query`. This reads the whole table
+    /// when there are no filters, or every row the filters cannot skip with an index.
+    pub fn count_rows(&mut self, table_name: &str, query: &TableQuery) -> Result<i64, KitError> {
+        let count = sql::count_rows(table_name, query)?;
+        let (_, rows) = self.db.query_rows(&count.sql, &count.params)?;
+        Ok(rows
+            .first()
+            .and_then(|row| row.first())
+            .and_then(leading_integer)
+            .unwrap_or(0))
+    }
+
+    /// Estimates the number of rows of a table without reading it, from the row counts
+    /// `ANALYZE` stored in `sqlite_stat1`, or else from the largest rowid. There is no estimate
+    /// for filtered pages, views and tables without rowid that were never analyzed.
+    ///
+    /// `dbstat` is not used: summing its cell counts reads every page of the table, like a count.
+    fn estimate_row_count(&mut self, table_name: &str, query: &TableQuery) -> Option<i64> {
+        if !query.filters.is_empty() {
+            return None;
+        }
+        let schema = query.schema.as_deref();
+        let stat1 = format!(
+            "SELECT stat FROM {} WHERE tbl = ?",
+            sql::qualified_name(schema, "sqlite_stat1")
+        );
+        // every index of the table starts its stat with the number of rows it covers
+        let analyzed = self
+            .db
+            .query_rows(&stat1, &[SerializableValue::Text(table_name.to_string())])
+            .ok()
+            .and_then(|(_, rows)| {
+                rows.iter()
+                    .filter_map(|row| row.first().and_then(leading_integer))
+                    .max()
+            });
+        if analyzed.is_some() {
+            return analyzed;
+        }
+        let max_rowid = format!(
+            "SELECT max(rowid) FROM {}",
+            sql::qualified_name(schema, table_name)
+        );
+        let (_, rows) = self.db.query_rows(&max_rowid, &[]).ok()?;
+        rows.first()?.first().and_then(leading_integer)
+    }
+
+    /// Returns the `PRAGMA data_version` of a SQLite connection, which changes whenever another
+    /// connection commits. libsql requests may each run on a different server connection, so
+    /// they have none.
+    pub fn data_version(&mut self) -> Result<Option<i64>, KitError> {
+        if let ConnectionType::Libsql(..) = self.connection_type {
+            return Ok(None);
+        }
+        let (_, rows) = self.db.query_rows("PRAGMA data_version", &[])?;
+        Ok(rows
+            .first()
+            .and_then(|row| row.first())
+            .and_then(leading_integer))
     }
 
-    /// Fetches all table names in the database.
-    pub fn get_all_tables(&mut self) -> Result<Vec<String>, String> {
-        self.db.get_all_tables()
+    /// Fetches every table, view, index and trigger of every attached schema.
+    pub fn get_schema_objects(&mut self) -> Result<Vec<SchemaObject>, KitError> {
+        load_schema_objects(self.db.as_mut())
     }
 
-    /// Removes a specific row from a table.
+    /// Removes a specific row from a table of `schema`, or of the default search order if it is `None`.
+    /// The row is read first, so the removal can be undone by inserting it again.
     pub fn remove_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
-        col_name: &str,
-        row_id: i64,
-    ) -> Result<String, String> {
-        self.db.remove_row(table_name, col_name, row_id)
+        key: &RowKey,
+    ) -> Result<WriteResult, KitError> {
+        let image = match load_table_schema_if_exists(self.db.as_mut(), schema, table_name)? {
+            Some(table) => self.row_image(&table, key)?,
+            None => None,
+        };
+        let result = self.db.remove_row(schema, table_name, key)?;
+        let change = Edit::Delete { key: key.clone() };
+        if let (Some((columns, row)), true) = (image, journaled(&result)) {
+            self.journal.record(JournalEntry {
+                schema: schema.map(str::to_string),
+                table_name: table_name.to_string(),
+                change: change.clone(),
+                inverse: Edit::Insert { columns, row },
+            });
+        }
+        self.record_edit(schema, table_name, change);
+        Ok(result)
     }
 
-    /// Inserts a new row into a table.
+    /// Inserts a new row into a table of `schema`, or of the default search order if it is `None`.
+    /// The values are given for `columns`, or for every column in table order if it is empty.
+    /// The inserted row is read back, so the insertion can be undone by removing it.
     pub fn insert_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
+        columns: Vec<String>,
         row: Vec<SerializableValue>,
-    ) -> Result<String, String> {
-        self.db.insert_row(table_name, row)
+    ) -> Result<WriteResult, KitError> {
+        let table = load_table_schema_if_exists(self.db.as_mut(), schema, table_name)?;
+        let result = self
+            .db
+            .insert_row(schema, table_name, &columns, row.clone())?;
+        if let (Some(table), true) = (table, journaled(&result)) {
+            let image = match self.inserted_key(&table, &columns, &row, &result)? {
+                Some(key) => self.row_image(&table, &key)?.map(|image| (key, image)),
+                None => None,
+            };
+            if let Some((key, (image_columns, image))) = image {
+                self.journal.record(JournalEntry {
+                    schema: schema.map(str::to_string),
+                    table_name: table_name.to_string(),
+                    change: Edit::Insert {
+                        columns: image_columns,
+                        row: image,
+                    },
+                    inverse: Edit::Delete { key },
+                });
+            }
+        }
+        self.record_edit(schema, table_name, Edit::Insert { columns, row });
+        Ok(result)
     }
 
-    /// Updates a specific row in a table.
+    /// Updates a specific row in a table of `schema`, or of the default search order if it is `None`.
+    /// The old value is read first, so the update can be undone by setting it back.
     pub fn update_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
         col_name: &str,
-        index_col_name: &str,
-        id: i64,
+        key: &RowKey,
         row: SerializableValue,
-    ) -> Result<String, String> {
+    ) -> Result<WriteResult, KitError> {
+        let old_value = self
+            .select_row(schema, table_name, &[col_name.to_string()], key)?
+            .and_then(|mut values| values.pop());
+        let result = self
+            .db
+            .update_row(schema, table_name, col_name, key, row.clone())?;
+        let change = Edit::Update {
+            column: col_name.to_string(),
+            key: key.clone(),
+            value: row.clone(),
+        };
+        if let (Some(old_value), true) = (old_value, journaled(&result)) {
+            self.journal.record(JournalEntry {
+                schema: schema.map(str::to_string),
+                table_name: table_name.to_string(),
+                change: change.clone(),
+                inverse: Edit::Update {
+                    column: col_name.to_string(),
+                    key: key.with_value(col_name, &row),
+                    value: old_value,
+                },
+            });
+        }
+        self.record_edit(schema, table_name, change);
+        Ok(result)
+    }
+
+    /// Reverts the most recent edit that was not undone yet and returns it.
+    ///
+    /// If the edited row no longer matches, e.g. because it was changed by a query since,
+    /// the edit is dropped from the history and `InvalidState` is returned.
+    pub fn undo(&mut self) -> Result<JournalEntry, KitError> {
+        let entry = self
+            .journal
+            .pop_undo()
+            .ok_or_else(|| KitError::invalid_state("there is no edit to undo"))?;
+        let schema = entry.schema.as_deref();
+        match self.apply(schema, &entry.table_name, &entry.inverse) {
+            Ok(result) if result.staged || result.rows_affected > 0 => {
+                self.record_edit(schema, &entry.table_name, entry.inverse.clone());
+                self.journal.push_redo(entry.clone());
+                Ok(entry)
+            }
+            Ok(_) => Err(KitError::invalid_state(
+                "the edited row has changed since, so the edit cannot be undone",
+            )),
+            Err(error) => {
+                self.journal.push_undo(entry);
+                Err(error)
+            }
+        }
+    }
+
+    /// Applies again the most recently undone edit and returns it.
+    ///
+    /// If the edited row no longer matches, the edit is dropped from the history
+    /// and `InvalidState` is returned.
+    pub fn redo(&mut self) -> Result<JournalEntry, KitError> {
+        let entry = self
+            .journal
+            .pop_redo()
+            .ok_or_else(|| KitError::invalid_state("there is no edit to redo"))?;
+        let schema = entry.schema.as_deref();
+        match self.apply(schema, &entry.table_name, &entry.change) {
+            Ok(result) if result.staged || result.rows_affected > 0 => {
+                self.record_edit(schema, &entry.table_name, entry.change.clone());
+                self.journal.push_undo(entry.clone());
+                Ok(entry)
+            }
+            Ok(_) => Err(KitError::invalid_state(
+                "the edited row has changed since, so the edit cannot be redone",
+            )),
+            Err(error) => {
+                self.journal.push_redo(entry);
+                Err(error)
+            }
+        }
+    }
+
+    /// Applies an edit to a table.
+    fn apply(
+        &mut self,
+        schema: Option<&str>,
+        table_name: &str,
+        edit: &Edit,
+    ) -> Result<WriteResult, KitError> {
+        match edit {
+            Edit::Insert { columns, row } => {
+                self.db.insert_row(schema, table_name, columns, row.clone())
+            }
+            Edit::Update { column, key, value } => {
+                self.db
+                    .update_row(schema, table_name, column, key, value.clone())
+            }
+            Edit::Delete { key } => self.db.remove_row(schema, table_name, key),
+        }
+    }
+
+    /// Selects `columns` of the row identified by `key`, or `None` if there is no such row.
+    fn select_row(
+        &mut self,
+        schema: Option<&str>,
+        table_name: &str,
+        columns: &[String],
+        key: &RowKey,
+    ) -> Result<Option<Vec<SerializableValue>>, KitError> {
+        let statement = sql::select_row(schema, table_name, columns, key)?;
+        let (_, rows) = self.db.query_rows(&statement.sql, &statement.params)?;
+        Ok(rows.into_iter().next())
+    }
+
+    /// Reads the image of the row identified by `key`: the names and values of the columns
+    /// it can be inserted again with, including its rowid for tables keyed by rowid.
+    fn row_image(
+        &mut self,
+        schema: &TableSchema,
+        key: &RowKey,
+    ) -> Result<Option<RowImage>, KitError> {
+        let mut columns = schema.writable_columns();
+        if schema.key_shape() == Some(KeyShape::Rowid) {
+            columns.push("rowid".to_string());
+        }
+        let row = self.select_row(schema.schema.as_deref(), &schema.table_name, &columns, key)?;
+        Ok(row.map(|row| (columns, row)))
+    }
+
+    /// Finds the key of a row that was just inserted, or `None` if it cannot be known,
+    /// e.g. for a `WITHOUT ROWID` table filled from its default values.
+    fn inserted_key(
+        &mut self,
+        schema: &TableSchema,
+        columns: &[String],
+        row: &[SerializableValue],
+        result: &WriteResult,
+    ) -> Result<Option<RowKey>, KitError> {
+        let shape = match schema.key_shape() {
+            Some(shape) => shape,
+            None => return Ok(None),
+        };
+        // the rowid of the last insert is stale for `WITHOUT ROWID` tables
+        let rowid = result.last_insert_rowid.filter(|_| schema.has_rowid);
+        let values = match (&shape, rowid) {
+            (KeyShape::Rowid, Some(rowid)) => return Ok(Some(RowKey::Rowid { rowid })),
+            (KeyShape::PrimaryKey(key_columns), Some(rowid)) => self.select_row(
+                schema.schema.as_deref(),
+                &schema.table_name,
+                key_columns,
+                &RowKey::Rowid { rowid },
+            )?,
+            (KeyShape::PrimaryKey(key_columns), None) if !schema.has_rowid => {
+                // without a rowid, the key can only be read from the inserted values
+                let names = if columns.is_empty() {
+                    schema.writable_columns()
+                } else {
+                    columns.to_vec()
+                };
+                key_columns
+                    .iter()
+                    .map(|key_column| {
+                        names
+                            .iter()
+                            .position(|name| name.eq_ignore_ascii_case(key_column))
+                            .and_then(|i| row.get(i).cloned())
+                    })
+                    .collect()
+            }
+            _ => None,
+        };
+        Ok(values.and_then(|values| shape.row_key(values)))
+    }
+
+    /// Starts staging edits. Until they are committed, edits can be listed and rolled back.
+    pub fn begin_edits(&mut self) -> Result<(), KitError> {
+        if self.pending_edits.is_some() {
+            return Err(KitError::invalid_state("edits are already being staged"));
+        }
+        self.db.begin_edits()?;
+        self.pending_edits = Some(vec![]);
+        self.journal_checkpoint = Some(self.journal.clone());
+        Ok(())
+    }
+
+    /// Lists the edits staged since `begin_edits`, in the order they were made.
+    pub fn pending_edits(&self) -> Vec<PendingEdit> {
+        self.pending_edits.clone().unwrap_or_default()
+    }
+
+    /// Returns whether there are staged edits that have not been committed or rolled back.
+    pub fn is_dirty(&self) -> bool {
+        self.pending_edits
+            .as_ref()
+            .is_some_and(|edits| !edits.is_empty())
+    }
+
+    /// Applies every staged edit and stops staging. Returns the number of edits applied.
+    /// If the commit fails, the edits stay staged so it can be retried or rolled back.
+    pub fn commit_edits(&mut self) -> Result<usize, KitError> {
+        if self.pending_edits.is_none() {
+            return Err(KitError::invalid_state("no edits are being staged"));
+        }
+        self.db.commit_edits()?;
+        self.journal_checkpoint = None;
+        Ok(self.pending_edits.take().unwrap_or_default().len())
+    }
+
+    /// Discards every staged edit and stops staging.
+    /// The undo history goes back to what it was when the edits were begun.
+    pub fn rollback_edits(&mut self) -> Result<(), KitError> {
+        if self.pending_edits.is_none() {
+            return Err(KitError::invalid_state("no edits are being staged"));
+        }
+        self.db.rollback_edits()?;
+        self.pending_edits = None;
+        if let Some(journal) = self.journal_checkpoint.take() {
+            self.journal = journal;
+        }
+        Ok(())
+    }
+
+    /// Records an edit if edits are being staged.
+    fn record_edit(&mut self, schema: Option<&str>, table_name: &str, edit: Edit) {
+        if let Some(pending_edits) = self.pending_edits.as_mut() {
+            pending_edits.push(PendingEdit {
+                schema: schema.map(str::to_string),
+                table_name: table_name.to_string(),
+                edit,
+            });
+        }
+    }
+
+    /// Runs a query on the database, binding `params` to its parameters.
+    /// The query stops with an `Interrupted` error if `control` cancels it or it times out.
+    pub fn run_query(
+        &mut self,
+        query: &str,
+        params: &QueryParams,
+        control: &QueryControl,
+    ) -> Result<TableRequest, KitError> {
+        let names = self.db.parameter_names(query)?;
+        let values = params.resolve(&names)?;
+        let started = Instant::now();
+        let mut result = self
+            .db
+            .run_query(query, &values, control)
+            .map_err(|error| control.explain(error))?;
+        result.elapsed_ms = Some(elapsed_ms(started));
+        Ok(result)
+    }
+
+    /// Explains how a query would run without running it: the `EXPLAIN QUERY PLAN` steps as a
+    /// tree, and the `EXPLAIN` bytecode. Parameters without a value in `params` are bound to
+    /// `NULL`, which does not change the plan.
+    pub fn explain_query(
+        &mut self,
+        query: &str,
+        params: Option<&QueryParams>,
+    ) -> Result<QueryPlan, KitError> {
+        let names = self.db.parameter_names(query)?;
+        let values = match params {
+            Some(params) => params.resolve(&names)?,
+            None => vec![SerializableValue::Null; names.len()],
+        };
+        let (_, plan_rows) = self
+            .db
+            .query_rows(&format!("EXPLAIN QUERY PLAN {}", query), &values)?;
+        let (column_names, rows) = self.db.query_rows(&format!("EXPLAIN {}", query), &values)?;
+        Ok(QueryPlan {
+            plan: explain::plan_tree(&plan_rows),
+            bytecode: Bytecode { column_names, rows },
+        })
+    }
+
+    /// Inserts many rows into a table at once, with the values given for `columns`, and returns
+    /// the index and error of each row that failed. Unlike `insert_row`, the rows are not recorded
+    /// in the journal, so they cannot be undone, and they cannot be staged.
+    pub fn insert_rows(
+        &mut self,
+        table_name: &str,
+        columns: &[String],
+        rows: &[Vec<SerializableValue>],
+    ) -> Result<Vec<(usize, KitError)>, KitError> {
+        if self.pending_edits.is_some() {
+            return Err(KitError::invalid_state(
+                "commit or roll back the staged edits before inserting rows in bulk",
+            ));
+        }
+        self.db.insert_rows(table_name, columns, rows)
+    }
+
+    /// Runs a query, binding `params` to its parameters, and hands its columns and then its rows
+    /// to `sink` as they are read, so they are never all held at once.
+    pub fn stream_query(
+        &mut self,
+        query: &str,
+        params: &QueryParams,
+        control: &QueryControl,
+        sink: &mut StreamSink,
+    ) -> Result<(), KitError> {
+        let names = self.db.parameter_names(query)?;
+        let values = params.resolve(&names)?;
         self.db
-            .update_row(table_name, col_name, index_col_name, id, row)
+            .stream_query(query, &values, control, sink)
+            .map_err(|error| control.explain(error))
     }
 
-    /// Runs a query on the database.
-    pub fn run_query(&mut self, query: &str) -> Result<TableRequest, String> {
-        self.db.run_query(query)
+    /// Splits a script into statements and runs them in order, optionally inside one transaction
+    /// that is rolled back if a statement fails. If `control` cancels the script or it times out,
+    /// the running statement fails with an `Interrupted` error.
+    pub fn run_script(
+        &mut self,
+        script: &str,
+        transaction: bool,
+        control: &QueryControl,
+    ) -> Result<ScriptResult, KitError> {
+        let statements = lexer::split_statements(script);
+        if statements.is_empty() {
+            return Ok(ScriptResult::default());
+        }
+        let started = Instant::now();
+        let mut result = self.db.run_script(&statements, transaction, control)?;
+        if let Some(script_error) = result.error.as_mut() {
+            script_error.error = control.explain(script_error.error.clone());
+        }
+        result.elapsed_ms = elapsed_ms(started);
+        Ok(result)
+    }
+
+    /// Returns the name of each parameter of a statement in index order, `None` for anonymous parameters.
+    pub fn parameter_names(&mut self, sql: &str) -> Result<Vec<Option<String>>, KitError> {
+        self.db.parameter_names(sql)
+    }
+
+    /// Fetches the declared schema of a specific table.
+    pub fn get_table_schema(
+        &mut self,
+        schema: Option<&str>,
+        table_name: &str,
+    ) -> Result<TableSchema, KitError> {
+        load_table_schema(self.db.as_mut(), schema, table_name)
     }
 }
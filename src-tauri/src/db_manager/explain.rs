@@ -0,0 +1,78 @@
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+
+/// `PlanNode` is a struct that holds one step of a query plan and the steps nested under it.
+#[derive(Serialize, Debug, Clone)]
+pub struct PlanNode {
+    pub id: i64,
+    /// What the step does, e.g. `SCAN t` or `SEARCH t USING INDEX t_a (a=?)`.
+    pub detail: String,
+    pub children: Vec<PlanNode>,
+}
+
+/// `Bytecode` is a struct that holds the `EXPLAIN` output of a query: one row per virtual
+/// machine instruction, with the columns SQLite returns (`addr`, `opcode`, `p1` to `p5`, `comment`).
+#[derive(Serialize, Debug, Clone)]
+pub struct Bytecode {
+    pub column_names: Vec<String>,
+    pub rows: Vec<Vec<SerializableValue>>,
+}
+
+/// `QueryPlan` is a struct that holds how a query would run.
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryPlan {
+    /// The top-level steps of `EXPLAIN QUERY PLAN`, in order.
+    pub plan: Vec<PlanNode>,
+    pub bytecode: Bytecode,
+}
+
+/// Builds the tree of an `EXPLAIN QUERY PLAN` result, whose rows are `id, parent, notused, detail`.
+/// Steps whose parent is `0`, or is not in the result, are at the top level.
+pub fn plan_tree(rows: &[Vec<SerializableValue>]) -> Vec<PlanNode> {
+    let steps: Vec<(i64, i64, String)> = rows
+        .iter()
+        .map(|row| {
+            let integer = |i: usize| match row.get(i) {
+                Some(SerializableValue::Integer(value)) => *value,
+                _ => 0,
+            };
+            let detail = match row.get(3) {
+                Some(SerializableValue::Text(detail)) => detail.clone(),
+                _ => String::new(),
+            };
+            (integer(0), integer(1), detail)
+        })
+        .collect();
+    let is_step = |id: i64| id != 0 && steps.iter().any(|(step, _, _)| *step == id);
+    let top_level: Vec<i64> = steps
+        .iter()
+        .filter(|(_, parent, _)| !is_step(*parent))
+        .map(|(id, _, _)| *id)
+        .collect();
+    top_level
+        .into_iter()
+        .map(|id| plan_node(&steps, id))
+        .collect()
+}
+
+/// Builds the node of the step `id` and, recursively, of the steps nested under it.
+/// Ids are increasing in SQLite's output, so a step is only nested under an earlier one.
+fn plan_node(steps: &[(i64, i64, String)], id: i64) -> PlanNode {
+    let detail = steps
+        .iter()
+        .find(|(step, _, _)| *step == id)
+        .map(|(_, _, detail)| detail.clone())
+        .unwrap_or_default();
+    let children = steps
+        .iter()
+        .filter(|(child, parent, _)| *parent == id && *child > id)
+        .map(|(child, _, _)| plan_node(steps, *child))
+        .collect();
+    PlanNode {
+        id,
+        detail,
+        children,
+    }
+}
@@ -0,0 +1,71 @@
+/// The `Edit` enum from the `db_manager` module.
+use crate::db_manager::Edit;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+
+/// The number of edits kept in the journal. Older edits can no longer be undone.
+pub const JOURNAL_CAPACITY: usize = 500;
+
+/// `JournalEntry` is a struct that holds an edit made to a table and the edit that reverts it.
+///
+/// `change` is the edit as it was applied, with inserts spelled out as the full row image
+/// (including its rowid), so redoing it recreates the exact same row.
+/// `inverse` is built from the row image read before the change, e.g. an update back to the
+/// old value or an insert of the deleted row.
+#[derive(Serialize, Debug, Clone)]
+pub struct JournalEntry {
+    /// The attached schema of the table, `None` for the default search order.
+    pub schema: Option<String>,
+    pub table_name: String,
+    pub change: Edit,
+    pub inverse: Edit,
+}
+
+/// `Journal` is a struct that holds the undo and redo history of a connection.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    undo: Vec<JournalEntry>,
+    redo: Vec<JournalEntry>,
+}
+
+/// `Journal` implementation.
+impl Journal {
+    /// Records a new edit. The redo history is cleared, because it no longer follows from the current rows.
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.redo.clear();
+        self.push_undo(entry);
+    }
+
+    /// Takes the most recent edit that can be undone.
+    pub fn pop_undo(&mut self) -> Option<JournalEntry> {
+        self.undo.pop()
+    }
+
+    /// Takes the most recently undone edit.
+    pub fn pop_redo(&mut self) -> Option<JournalEntry> {
+        self.redo.pop()
+    }
+
+    /// Puts an edit on the undo history without touching the redo history.
+    pub fn push_undo(&mut self, entry: JournalEntry) {
+        self.undo.push(entry);
+        if self.undo.len() > JOURNAL_CAPACITY {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Puts an undone edit on the redo history.
+    pub fn push_redo(&mut self, entry: JournalEntry) {
+        self.redo.push(entry);
+    }
+
+    /// Returns whether there is an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns whether there is an edit to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
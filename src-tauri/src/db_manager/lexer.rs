@@ -0,0 +1,466 @@
+/// `TokenKind` is an enum that represents the kind of a token of SQL text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    /// A `-- line` or `/* block */` comment.
+    Comment,
+    /// A `'string'` literal.
+    String,
+    /// A `"quoted"`, `` `quoted` `` or `[quoted]` identifier.
+    QuotedIdentifier,
+    /// A keyword, bare identifier or number.
+    Word,
+    /// A `?`, `?NNN`, `:name`, `@name` or `$name` parameter.
+    Parameter,
+    Semicolon,
+    /// Any other character, such as an operator or a parenthesis.
+    Punctuation,
+}
+
+/// `SavepointStatement` is an enum that represents a statement that opens, rolls back to or
+/// releases a savepoint, with the name of the savepoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SavepointStatement {
+    /// `SAVEPOINT name`
+    Savepoint(String),
+    /// `ROLLBACK [TRANSACTION] TO [SAVEPOINT] name`
+    RollbackTo(String),
+    /// `RELEASE [SAVEPOINT] name`
+    Release(String),
+}
+
+/// `Token` is a struct that holds a token of SQL text and where it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// The byte offset of the token in the SQL text.
+    pub start: usize,
+}
+
+/// Splits SQL text into tokens, following the quoting and comment rules of SQLite.
+///
+/// Unterminated strings, identifiers and comments run to the end of the text, so every byte
+/// of `sql` belongs to exactly one token.
+pub fn tokenize(sql: &str) -> Vec<Token<'_>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let next = bytes.get(i + 1).copied();
+        let kind = match bytes[i] {
+            byte if byte.is_ascii_whitespace() => {
+                i = skip_while(bytes, i, |byte| byte.is_ascii_whitespace());
+                TokenKind::Whitespace
+            }
+            b'-' if next == Some(b'-') => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+                TokenKind::Comment
+            }
+            b'/' if next == Some(b'*') => {
+                i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+                TokenKind::Comment
+            }
+            b'\'' => {
+                i = quoted_end(bytes, i, b'\'');
+                TokenKind::String
+            }
+            quote @ (b'"' | b'`') => {
+                i = quoted_end(bytes, i, quote);
+                TokenKind::QuotedIdentifier
+            }
+            b'[' => {
+                i = find(bytes, i + 1, b"]").map_or(bytes.len(), |end| end + 1);
+                TokenKind::QuotedIdentifier
+            }
+            b'?' => {
+                i = skip_while(bytes, i + 1, |byte| byte.is_ascii_digit());
+                TokenKind::Parameter
+            }
+            b':' | b'@' | b'$' if next.is_some_and(is_identifier_byte) => {
+                i = skip_while(bytes, i + 1, is_identifier_byte);
+                TokenKind::Parameter
+            }
+            b';' => {
+                i += 1;
+                TokenKind::Semicolon
+            }
+            byte if is_identifier_byte(byte) => {
+                i = skip_while(bytes, i, is_identifier_byte);
+                TokenKind::Word
+            }
+            _ => {
+                i += 1;
+                TokenKind::Punctuation
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: &sql[start..i],
+            start,
+        });
+    }
+    tokens
+}
+
+/// Removes the quotes around an identifier or string literal, if any.
+/// A doubled quote inside stands for the quote itself, except inside brackets.
+pub fn unquote(text: &str) -> String {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next_back()) {
+        (Some('['), Some(']')) => text[1..text.len() - 1].to_string(),
+        (Some(open), Some(close)) if open == close && "\"'`".contains(open) => {
+            text[1..text.len() - 1].replace(&format!("{}{}", open, open), &open.to_string())
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// Splits a script into its statements, in order, without their terminating semicolons.
+///
+/// Semicolons inside string literals, quoted identifiers and comments do not end a statement,
+/// and neither do the ones inside the body of a `CREATE TRIGGER`, which ends at `; END;`
+/// like SQLite's `sqlite3_complete`. Empty statements and comments between statements are dropped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut leading: Vec<&str> = Vec::new();
+    let mut previous: [Option<Token>; 2] = [None, None];
+    for token in tokenize(sql) {
+        match token.kind {
+            TokenKind::Whitespace | TokenKind::Comment => continue,
+            TokenKind::Semicolon => {
+                let in_trigger = is_create_trigger(&leading);
+                let trigger_ended = matches!(
+                    previous,
+                    [Some(end), Some(semicolon)] if end.text.eq_ignore_ascii_case("END")
+                        && semicolon.kind == TokenKind::Semicolon
+                );
+                if !in_trigger || trigger_ended {
+                    if let Some(start) = start.take() {
+                        statements.push(sql[start..token.start].trim_end());
+                    }
+                    leading.clear();
+                    previous = [None, None];
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        if start.is_none() {
+            start = Some(token.start);
+        }
+        if leading.len() < 3 {
+            leading.push(token.text);
+        }
+        previous = [Some(token), previous[0]];
+    }
+    if let Some(start) = start {
+        statements.push(sql[start..].trim_end());
+    }
+    statements
+}
+
+/// Returns the first keyword of a statement in upper case, skipping whitespace and comments.
+pub fn leading_keyword(sql: &str) -> Option<String> {
+    tokenize(sql)
+        .into_iter()
+        .find(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .filter(|token| token.kind == TokenKind::Word)
+        .map(|token| token.text.to_ascii_uppercase())
+}
+
+/// Returns the savepoint a statement opens, rolls back to or releases, or `None` for any other statement.
+pub fn savepoint_statement(sql: &str) -> Option<SavepointStatement> {
+    let tokens = tokenize(sql);
+    let mut words: Vec<&str> = tokens
+        .iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .map(|token| token.text)
+        .collect();
+    if words.last() == Some(&";") {
+        words.pop();
+    }
+    let is = |word: &&str, keyword: &str| word.eq_ignore_ascii_case(keyword);
+    // the optional keywords are only skipped when a name follows them
+    let savepoint_name = |words: &[&str], optional: &str| match words {
+        [keyword, name] if is(keyword, optional) => Some(unquote(name)),
+        [name] => Some(unquote(name)),
+        _ => None,
+    };
+    match words.as_slice() {
+        [first, name] if is(first, "SAVEPOINT") => {
+            Some(SavepointStatement::Savepoint(unquote(name)))
+        }
+        [first, rest @ ..] if is(first, "RELEASE") => {
+            savepoint_name(rest, "SAVEPOINT").map(SavepointStatement::Release)
+        }
+        [first, transaction, to, rest @ ..]
+            if is(first, "ROLLBACK") && is(transaction, "TRANSACTION") && is(to, "TO") =>
+        {
+            savepoint_name(rest, "SAVEPOINT").map(SavepointStatement::RollbackTo)
+        }
+        [first, to, rest @ ..] if is(first, "ROLLBACK") && is(to, "TO") => {
+            savepoint_name(rest, "SAVEPOINT").map(SavepointStatement::RollbackTo)
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether a statement inserts rows, i.e. starts with `INSERT` or `REPLACE`.
+pub fn is_insert(sql: &str) -> bool {
+    matches!(
+        leading_keyword(sql).as_deref(),
+        Some("INSERT") | Some("REPLACE")
+    )
+}
+
+/// Returns whether the first tokens of a statement are `CREATE [TEMP | TEMPORARY] TRIGGER`.
+fn is_create_trigger(leading: &[&str]) -> bool {
+    let is = |i: usize, keyword: &str| {
+        leading
+            .get(i)
+            .is_some_and(|text| text.eq_ignore_ascii_case(keyword))
+    };
+    is(0, "CREATE")
+        && (is(1, "TRIGGER") || ((is(1, "TEMP") || is(1, "TEMPORARY")) && is(2, "TRIGGER")))
+}
+
+/// Returns the names of the parameters of a statement, in index order, as
+/// `sqlite3_bind_parameter_name` does: anonymous `?` parameters, and the indexes skipped
+/// by `?NNN` parameters, have no name, and repeated names share one index.
+pub fn parameter_names(sql: &str) -> Vec<Option<String>> {
+    let mut names: Vec<Option<String>> = Vec::new();
+    for token in tokenize(sql) {
+        if token.kind != TokenKind::Parameter {
+            continue;
+        }
+        if token.text == "?" {
+            names.push(None);
+        } else if let Some(number) = token.text.strip_prefix('?') {
+            let index = number.parse::<usize>().unwrap_or_default();
+            if index == 0 {
+                continue;
+            }
+            if names.len() < index {
+                names.resize(index, None);
+            }
+            names[index - 1] = Some(token.text.to_string());
+        } else if !names.iter().flatten().any(|name| name == token.text) {
+            names.push(Some(token.text.to_string()));
+        }
+    }
+    names
+}
+
+/// Returns whether a byte can be part of a bare identifier. Bytes of non-ASCII characters can.
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80
+}
+
+/// Returns the index of the first byte from `from` that does not match `predicate`.
+fn skip_while(bytes: &[u8], from: usize, predicate: impl Fn(u8) -> bool) -> usize {
+    bytes[from..]
+        .iter()
+        .position(|byte| !predicate(*byte))
+        .map_or(bytes.len(), |offset| from + offset)
+}
+
+/// Returns the index of the first occurrence of `needle` from `from`, if any.
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|offset| from + offset)
+}
+
+/// Returns the index just past the quote that closes the quoted text starting at `start`.
+/// A doubled quote stands for the quote itself and does not close the text.
+fn quoted_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the kinds and texts of the tokens of `sql`.
+    fn tokens(sql: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(sql)
+            .into_iter()
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_quotes_and_comments() {
+        assert_eq!(
+            tokens("'a;b' \"c\"\"d\" [e;f] `g` -- h;\n/* i; */"),
+            vec![
+                (TokenKind::String, "'a;b'"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::QuotedIdentifier, "\"c\"\"d\""),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::QuotedIdentifier, "[e;f]"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::QuotedIdentifier, "`g`"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::Comment, "-- h;\n"),
+                (TokenKind::Comment, "/* i; */"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_unterminated() {
+        for sql in [
+            "SELECT 'a; b",
+            "SELECT \"a; b",
+            "SELECT [a; b",
+            "SELECT 1 /* a; b",
+        ] {
+            let tokens = tokenize(sql);
+            let last = tokens.last().unwrap();
+            assert_eq!(&sql[last.start..], last.text, "{}", sql);
+            assert!(last.text.contains(';'), "{}", sql);
+            assert!(!tokens
+                .iter()
+                .any(|token| token.kind == TokenKind::Semicolon));
+            assert_eq!(
+                tokens.iter().map(|token| token.text).collect::<String>(),
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn parameter_names_in_index_order() {
+        assert_eq!(
+            parameter_names("SELECT ?, ?3, :a, @b, $c"),
+            vec![
+                None,
+                None,
+                Some("?3".to_string()),
+                Some(":a".to_string()),
+                Some("@b".to_string()),
+                Some("$c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_names_repeated() {
+        assert_eq!(
+            parameter_names("SELECT :a, ?2, :a, ?2, @a"),
+            vec![
+                Some(":a".to_string()),
+                Some("?2".to_string()),
+                Some("@a".to_string()),
+            ]
+        );
+        assert_eq!(
+            parameter_names("SELECT ?1, ?"),
+            vec![Some("?1".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn parameter_names_skip_quotes_and_comments() {
+        assert_eq!(
+            parameter_names("SELECT ':a', \"@b\", [$c], `?1` -- :d\n/* @e */ FROM t WHERE x = :f"),
+            vec![Some(":f".to_string())]
+        );
+        assert_eq!(parameter_names("SELECT ':a"), Vec::<Option<String>>::new());
+    }
+
+    #[test]
+    fn split_statements_quoted_semicolons() {
+        assert_eq!(
+            split_statements(
+                "INSERT INTO t VALUES ('a;b'); SELECT \"c;d\" FROM [e;f]; SELECT `g;h`;"
+            ),
+            vec![
+                "INSERT INTO t VALUES ('a;b')",
+                "SELECT \"c;d\" FROM [e;f]",
+                "SELECT `g;h`",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_statements_comments() {
+        assert_eq!(
+            split_statements("-- first; still a comment\nSELECT 1; /* ; */ SELECT 2 -- two;\n;"),
+            vec!["SELECT 1", "SELECT 2 -- two;"]
+        );
+        assert_eq!(
+            split_statements(" ;; -- only a comment"),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn split_statements_triggers() {
+        let trigger = "CREATE TRIGGER tr AFTER INSERT ON t BEGIN \
+            INSERT INTO log VALUES (new.id); UPDATE n SET c = c + 1; END";
+        let temp_trigger = "CREATE TEMP TRIGGER tt BEFORE DELETE ON t BEGIN SELECT 1; END";
+        assert_eq!(
+            split_statements(&format!("{}; {}; SELECT 3", trigger, temp_trigger)),
+            vec![trigger, temp_trigger, "SELECT 3"]
+        );
+    }
+
+    #[test]
+    fn split_statements_unterminated() {
+        assert_eq!(
+            split_statements("SELECT 1; SELECT 'a; b"),
+            vec!["SELECT 1", "SELECT 'a; b"]
+        );
+        assert_eq!(
+            split_statements("SELECT 1; SELECT 2 /* a; b"),
+            vec!["SELECT 1", "SELECT 2 /* a; b"]
+        );
+    }
+
+    #[test]
+    fn savepoint_statements() {
+        use SavepointStatement::*;
+        let cases = [
+            (
+                "SAVEPOINT kit_script",
+                Some(Savepoint("kit_script".to_string())),
+            ),
+            ("savepoint \"a b\";", Some(Savepoint("a b".to_string()))),
+            (
+                "RELEASE kit_script",
+                Some(Release("kit_script".to_string())),
+            ),
+            ("RELEASE SAVEPOINT [x]", Some(Release("x".to_string()))),
+            ("RELEASE savepoint", Some(Release("savepoint".to_string()))),
+            ("ROLLBACK TO a", Some(RollbackTo("a".to_string()))),
+            (
+                "rollback transaction to savepoint /* b */ a",
+                Some(RollbackTo("a".to_string())),
+            ),
+            ("ROLLBACK", None),
+            ("ROLLBACK TRANSACTION", None),
+            ("SELECT 'SAVEPOINT a'", None),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(savepoint_statement(sql), expected, "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+/// The `Filter`, `FilterOperator`, `KeyShape`, `OrderBy`, `RowKey` and `TableQuery` types from the `db_manager` module.
+use crate::db_manager::{Filter, FilterOperator, KeyShape, OrderBy, RowKey, TableQuery};
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+
+/// `SqlStatement` is a struct that holds generated SQL and the values bound to its placeholders.
+///
+/// Identifiers in `sql` are always quoted and values are never interpolated, so a statement
+/// built here is safe to run whatever the table, column or value contains.
+#[derive(Debug, Clone)]
+pub struct SqlStatement {
+    pub sql: String,
+    /// The values for the `?` placeholders of `sql`, in order.
+    pub params: Vec<SerializableValue>,
+}
+
+/// Quotes an identifier (table or column name) so it can be used safely in generated SQL.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Returns the quoted name of a table, qualified with its schema if one is given.
+pub fn qualified_name(schema: Option<&str>, table_name: &str) -> String {
+    match schema {
+        Some(schema) => format!(
+            "{}.{}",
+            quote_identifier(schema),
+            quote_identifier(table_name)
+        ),
+        None => quote_identifier(table_name),
+    }
+}
+
+/// Returns the quoted column names separated by commas.
+pub fn column_list(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the ` WHERE ...` clause for the filters, or an empty string if there are none.
+/// Values are returned as parameters in placeholder order.
+pub fn where_clause(filters: &[Filter]) -> Result<(String, Vec<SerializableValue>), KitError> {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+    for filter in filters {
+        let column = quote_identifier(&filter.column);
+        let expected = match filter.operator {
+            FilterOperator::IsNull => filter.values.is_empty(),
+            FilterOperator::Between => filter.values.len() == 2,
+            FilterOperator::In => !filter.values.is_empty(),
+            _ => filter.values.len() == 1,
+        };
+        if !expected {
+            return Err(KitError::invalid_argument(format!(
+                "wrong number of values ({}) for {:?} filter on {}",
+                filter.values.len(),
+                filter.operator,
+                filter.column
+            )));
+        }
+        let condition = match filter.operator {
+            FilterOperator::Eq => format!("{} = ?", column),
+            FilterOperator::NotEq => format!("{} != ?", column),
+            FilterOperator::Lt => format!("{} < ?", column),
+            FilterOperator::Gt => format!("{} > ?", column),
+            FilterOperator::Like => format!("{} LIKE ?", column),
+            FilterOperator::IsNull => format!("{} IS NULL", column),
+            FilterOperator::In => format!(
+                "{} IN ({})",
+                column,
+                vec!["?"; filter.values.len()].join(", ")
+            ),
+            FilterOperator::Between => format!("{} BETWEEN ? AND ?", column),
+        };
+        conditions.push(condition);
+        params.extend(filter.values.iter().cloned());
+    }
+    if conditions.is_empty() {
+        return Ok((String::new(), params));
+    }
+    Ok((format!(" WHERE {}", conditions.join(" AND ")), params))
+}
+
+/// Builds the ` ORDER BY ...` clause for the sort columns, or an empty string if there are none.
+pub fn order_by_clause(order_by: &[OrderBy]) -> String {
+    if order_by.is_empty() {
+        return String::new();
+    }
+    let columns: Vec<String> = order_by
+        .iter()
+        .map(|order| {
+            format!(
+                "{} {}",
+                quote_identifier(&order.column),
+                if order.descending { "DESC" } else { "ASC" }
+            )
+        })
+        .collect();
+    format!(" ORDER BY {}", columns.join(", "))
+}
+
+/// Builds the ` WHERE ...` condition that matches the row identified by `key`.
+/// Values are returned as parameters in placeholder order.
+pub fn key_condition(key: &RowKey) -> Result<(String, Vec<SerializableValue>), KitError> {
+    match key {
+        RowKey::Rowid { rowid } => Ok((
+            " WHERE rowid = ?".to_string(),
+            vec![SerializableValue::Integer(*rowid)],
+        )),
+        RowKey::PrimaryKey { column, value } => Ok((
+            format!(" WHERE {} = ?", quote_identifier(column)),
+            vec![value.clone()],
+        )),
+        RowKey::CompositeKey { columns, values } => {
+            if columns.is_empty() || columns.len() != values.len() {
+                return Err(KitError::invalid_argument(format!(
+                    "composite key has {} columns but {} values",
+                    columns.len(),
+                    values.len()
+                )));
+            }
+            let conditions: Vec<String> = columns
+                .iter()
+                .map(|column| format!("{} = ?", quote_identifier(column)))
+                .collect();
+            Ok((
+                format!(" WHERE {}", conditions.join(" AND ")),
+                values.clone(),
+            ))
+        }
+    }
+}
+
+/// Builds the statement that selects one filtered and sorted page of a table.
+/// When a `key` is given, the key values of each row are selected after the columns of the table.
+pub fn select_page(
+    table_name: &str,
+    query: &TableQuery,
+    key: Option<&KeyShape>,
+) -> Result<SqlStatement, KitError> {
+    let (where_clause, mut params) = where_clause(&query.filters)?;
+    params.push(SerializableValue::Integer(query.limit));
+    params.push(SerializableValue::Integer(query.offset));
+    let key_columns = match key {
+        Some(KeyShape::Rowid) => ", rowid".to_string(),
+        Some(KeyShape::PrimaryKey(columns)) => columns
+            .iter()
+            .map(|column| format!(", {}", quote_identifier(column)))
+            .collect(),
+        None => String::new(),
+    };
+    Ok(SqlStatement {
+        sql: format!(
+            "SELECT *{} FROM {}{}{} LIMIT ? OFFSET ?",
+            key_columns,
+            qualified_name(query.schema.as_deref(), table_name),
+            where_clause,
+            order_by_clause(&query.order_by)
+        ),
+        params,
+    })
+}
+
+/// Builds the statement that counts the rows of a table matching the filters of `query`.
+pub fn count_rows(table_name: &str, query: &TableQuery) -> Result<SqlStatement, KitError> {
+    let (where_clause, params) = where_clause(&query.filters)?;
+    Ok(SqlStatement {
+        sql: format!(
+            "SELECT COUNT(*) FROM {}{}",
+            qualified_name(query.schema.as_deref(), table_name),
+            where_clause
+        ),
+        params,
+    })
+}
+
+/// Builds the statement that deletes the row identified by `key`.
+pub fn delete_row(
+    schema: Option<&str>,
+    table_name: &str,
+    key: &RowKey,
+) -> Result<SqlStatement, KitError> {
+    let (condition, params) = key_condition(key)?;
+    Ok(SqlStatement {
+        sql: format!(
+            "DELETE FROM {}{}",
+            qualified_name(schema, table_name),
+            condition
+        ),
+        params,
+    })
+}
+
+/// Builds the statement that inserts a row. With no `columns`, the row holds one value per
+/// column of the table in order; otherwise it holds one value per column listed.
+/// An empty row inserts the default value of every column.
+pub fn insert_row(
+    schema: Option<&str>,
+    table_name: &str,
+    columns: &[String],
+    row: Vec<SerializableValue>,
+) -> Result<SqlStatement, KitError> {
+    if !columns.is_empty() && columns.len() != row.len() {
+        return Err(KitError::invalid_argument(format!(
+            "row has {} values for {} columns",
+            row.len(),
+            columns.len()
+        )));
+    }
+    let sql = if row.is_empty() {
+        format!(
+            "INSERT INTO {} DEFAULT VALUES",
+            qualified_name(schema, table_name)
+        )
+    } else if columns.is_empty() {
+        format!(
+            "INSERT INTO {} VALUES ({})",
+            qualified_name(schema, table_name),
+            vec!["?"; row.len()].join(", ")
+        )
+    } else {
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            qualified_name(schema, table_name),
+            column_list(columns),
+            vec!["?"; row.len()].join(", ")
+        )
+    };
+    Ok(SqlStatement { sql, params: row })
+}
+
+/// Builds the statement that selects `columns` of the row identified by `key`.
+pub fn select_row(
+    schema: Option<&str>,
+    table_name: &str,
+    columns: &[String],
+    key: &RowKey,
+) -> Result<SqlStatement, KitError> {
+    let (condition, params) = key_condition(key)?;
+    Ok(SqlStatement {
+        sql: format!(
+            "SELECT {} FROM {}{}",
+            column_list(columns),
+            qualified_name(schema, table_name),
+            condition
+        ),
+        params,
+    })
+}
+
+/// Builds the statement that sets `column` to `value` in the row identified by `key`.
+pub fn update_row(
+    schema: Option<&str>,
+    table_name: &str,
+    column: &str,
+    key: &RowKey,
+    value: SerializableValue,
+) -> Result<SqlStatement, KitError> {
+    let (condition, key_params) = key_condition(key)?;
+    let mut params = vec![value];
+    params.extend(key_params);
+    Ok(SqlStatement {
+        sql: format!(
+            "UPDATE {} SET {} = ?{}",
+            qualified_name(schema, table_name),
+            quote_identifier(column),
+            condition
+        ),
+        params,
+    })
+}
@@ -0,0 +1,525 @@
+/// The `lexer` module, which splits dumps into statements, and the `sql` module, which quotes identifiers.
+use crate::db_manager::{lexer, sql};
+/// The `ConnectionType` and `DbManager` structs and the `StreamItem` enum from the `db_manager` module.
+use crate::db_manager::{ConnectionType, DbManager, StreamItem};
+/// The `Backend` and `KitError` enums.
+use crate::error::{Backend, KitError};
+/// The `sql_literal` helper, which writes values as SQL, and the `PROGRESS_INTERVAL` constant.
+use crate::export::{sql_literal, PROGRESS_INTERVAL};
+/// The `QueryControl` struct and the `QueryId` type, which let a dump or restore be cancelled.
+use crate::queries::{QueryControl, QueryId};
+/// The `load_table_schema` helper from the `schema` module.
+use crate::schema::load_table_schema;
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `fs` module, used to read dump files.
+use std::fs::{self, File};
+/// The `BufWriter` type and the `Write` trait, used to write dump files.
+use std::io::{BufWriter, Write};
+/// The `Path` type.
+use std::path::Path;
+/// The `Instant` type, used to time dumps and pace progress reports.
+use std::time::Instant;
+
+/// The number of statements of a dump replayed in one request or transaction.
+pub const RESTORE_BATCH: usize = 500;
+
+/// `DumpProgress` is a struct that reports how far a dump has got.
+#[derive(Serialize, Debug, Clone)]
+pub struct DumpProgress {
+    /// The id the dump was started with, if any.
+    pub dump_id: Option<QueryId>,
+    /// The table whose rows are being written.
+    pub table_name: Option<String>,
+    /// The number of rows written so far, across every table.
+    pub rows: u64,
+    /// Whether the dump is complete. Not reported for dumps that fail.
+    pub done: bool,
+}
+
+/// `DumpSummary` is a struct that describes a finished dump.
+#[derive(Serialize, Debug, Clone)]
+pub struct DumpSummary {
+    /// The number of tables whose schema was written.
+    pub tables: usize,
+    pub rows: u64,
+    pub bytes: u64,
+    /// How long the dump took, in milliseconds.
+    pub elapsed_ms: f64,
+}
+
+/// `RestoreProgress` is a struct that reports how far the replay of a dump has got.
+#[derive(Serialize, Debug, Clone)]
+pub struct RestoreProgress {
+    /// The id the replay was started with, if any.
+    pub dump_id: Option<QueryId>,
+    pub statements_run: usize,
+    pub statements_total: usize,
+    /// Whether every statement has run. Not reported for replays that fail.
+    pub done: bool,
+}
+
+/// `RestoreSummary` is a struct that describes a replayed dump.
+#[derive(Serialize, Debug, Clone)]
+pub struct RestoreSummary {
+    pub statements: usize,
+    /// How long the replay took, in milliseconds.
+    pub elapsed_ms: f64,
+}
+
+/// `DumpObject` is a struct that holds an entry of `sqlite_master` written to a dump.
+struct DumpObject {
+    object_type: String,
+    name: String,
+    table_name: String,
+    sql: String,
+}
+
+/// `DumpObject` implementation.
+impl DumpObject {
+    /// Returns whether the object is a virtual table.
+    fn is_virtual(&self) -> bool {
+        self.object_type == "table"
+            && self
+                .sql
+                .trim_start()
+                .to_uppercase()
+                .starts_with("CREATE VIRTUAL TABLE")
+    }
+
+    /// Returns the `content` option of a full-text table, which names the table it indexes,
+    /// or is empty for a contentless table. `None` for tables that store their own rows.
+    fn fts_content(&self) -> Option<String> {
+        let sql = self.sql.to_lowercase();
+        if !self.is_virtual() || !sql.contains("using fts") {
+            return None;
+        }
+        sql.match_indices("content").find_map(|(start, keyword)| {
+            let rest = sql[start + keyword.len()..]
+                .trim_start()
+                .strip_prefix('=')?;
+            let end = rest.find([',', ')']).unwrap_or(rest.len());
+            Some(rest[..end].trim().trim_matches(['\'', '"']).to_string())
+        })
+    }
+}
+
+/// Writes the schema and the rows of the main database, or of some of its tables, to a file as
+/// an SQL script that recreates them, like the `.dump` command of the `sqlite3` shell.
+///
+/// The script creates the tables, parents before the tables whose foreign keys refer to them,
+/// then inserts their rows, then creates the indexes, views and triggers, all in one transaction.
+/// Blobs are written as blob literals. Full-text tables that index another table are rebuilt
+/// from it rather than written, and contentless ones are left empty; internal tables such as
+/// `sqlite_stat1` and the shadow tables of virtual tables are left out. On SQLite files every
+/// table is read from the same snapshot.
+///
+/// # Arguments
+///
+/// * `db_manager` - The connection to read the database from.
+/// * `tables` - The tables and views to dump, with their indexes and triggers. `None` dumps every one.
+/// * `path` - The file to write. It is replaced if it exists, and removed if the dump fails.
+/// * `control` - Stops the dump when it is cancelled.
+/// * `progress` - Called when a table is started and at most every `PROGRESS_INTERVAL` while rows are written, and once the dump is done.
+///
+/// # Returns
+///
+/// * `Result<DumpSummary, KitError>` - How many tables, rows and bytes were written.
+pub fn export_dump(
+    db_manager: &mut DbManager,
+    tables: Option<&[String]>,
+    path: &Path,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(DumpProgress),
+) -> Result<DumpSummary, KitError> {
+    // a savepoint reads every table from one snapshot, even on the writer while edits are staged
+    let snapshot = matches!(db_manager.connection_type, ConnectionType::Sqlite(_));
+    if snapshot {
+        db_manager.db.query_rows("SAVEPOINT kit_dump", &[])?;
+    }
+    let result = write_dump(db_manager, tables, path, control, progress);
+    if snapshot {
+        let _ = db_manager.db.query_rows("RELEASE kit_dump", &[]);
+    }
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Writes the dump file; `export_dump` removes it if this fails.
+fn write_dump(
+    db_manager: &mut DbManager,
+    tables: Option<&[String]>,
+    path: &Path,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(DumpProgress),
+) -> Result<DumpSummary, KitError> {
+    let started = Instant::now();
+    let objects = dump_objects(db_manager, tables)?;
+    let file = File::create(path).map_err(|e| KitError::io(path, e))?;
+    let mut out = BufWriter::new(file);
+    let mut bytes = 0u64;
+    let mut write = |text: &str, bytes: &mut u64| {
+        *bytes += text.len() as u64;
+        out.write_all(text.as_bytes())
+    };
+    let io_error = |e| KitError::io(path, e);
+
+    let table_objects: Vec<&DumpObject> = objects
+        .iter()
+        .filter(|object| object.object_type == "table")
+        .collect();
+    write(
+        "PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\nPRAGMA defer_foreign_keys=ON;\n",
+        &mut bytes,
+    )
+    .map_err(io_error)?;
+    for table in &table_objects {
+        write(&format!("{};\n", table.sql), &mut bytes).map_err(io_error)?;
+    }
+
+    let mut rows = 0u64;
+    let mut reported = Instant::now();
+    for table in table_objects
+        .iter()
+        .filter(|table| table.fts_content().is_none())
+    {
+        progress(DumpProgress {
+            dump_id: None,
+            table_name: Some(table.name.clone()),
+            rows,
+            done: false,
+        });
+        let columns = load_table_schema(db_manager.db.as_mut(), Some("main"), &table.name)?
+            .writable_columns();
+        if columns.is_empty() {
+            continue;
+        }
+        let column_list = sql::column_list(&columns);
+        let table_name = sql::quote_identifier(&table.name);
+        let query = format!("SELECT {} FROM main.{}", column_list, table_name);
+        let mut failed = None;
+        db_manager.stream_query(&query, &Default::default(), control, &mut |item| {
+            let values = match item {
+                StreamItem::Row(values) => values,
+                StreamItem::Columns(_) => return true,
+            };
+            if control.should_stop() {
+                return false;
+            }
+            let literals: Vec<String> = values.iter().map(sql_literal).collect();
+            let insert = format!(
+                "INSERT INTO {} ({}) VALUES ({});\n",
+                table_name,
+                column_list,
+                literals.join(", ")
+            );
+            if let Err(e) = write(&insert, &mut bytes) {
+                failed = Some(e);
+                return false;
+            }
+            rows += 1;
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                reported = Instant::now();
+                progress(DumpProgress {
+                    dump_id: None,
+                    table_name: Some(table.name.clone()),
+                    rows,
+                    done: false,
+                });
+            }
+            true
+        })?;
+        if let Some(e) = failed {
+            return Err(io_error(e));
+        }
+        if control.should_stop() {
+            return Err(control.interrupted(backend(db_manager), &query));
+        }
+    }
+
+    for table in &table_objects {
+        if table
+            .fts_content()
+            .is_some_and(|content| !content.is_empty())
+        {
+            let name = sql::quote_identifier(&table.name);
+            let rebuild = format!("INSERT INTO {}({}) VALUES ('rebuild');\n", name, name);
+            write(&rebuild, &mut bytes).map_err(io_error)?;
+        }
+    }
+    for statement in sequence_statements(db_manager, &table_objects)? {
+        write(&statement, &mut bytes).map_err(io_error)?;
+    }
+    for object_type in ["index", "view", "trigger"] {
+        for object in objects
+            .iter()
+            .filter(|object| object.object_type == object_type)
+        {
+            write(&format!("{};\n", object.sql), &mut bytes).map_err(io_error)?;
+        }
+    }
+    write("COMMIT;\n", &mut bytes).map_err(io_error)?;
+    out.flush().map_err(io_error)?;
+    progress(DumpProgress {
+        dump_id: None,
+        table_name: None,
+        rows,
+        done: true,
+    });
+    Ok(DumpSummary {
+        tables: table_objects.len(),
+        rows,
+        bytes,
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// Loads the objects of the main database to dump, in creation order with tables sorted so
+/// parents come before the tables referring to them.
+fn dump_objects(
+    db_manager: &mut DbManager,
+    tables: Option<&[String]>,
+) -> Result<Vec<DumpObject>, KitError> {
+    let (_, rows) = db_manager.db.query_rows(
+        "SELECT type, name, tbl_name, sql FROM main.sqlite_master WHERE sql IS NOT NULL ORDER BY rowid",
+        &[],
+    )?;
+    let text = |value: &SerializableValue| match value {
+        SerializableValue::Text(text) => text.clone(),
+        _ => String::new(),
+    };
+    let mut objects: Vec<DumpObject> = rows
+        .iter()
+        .map(|row| DumpObject {
+            object_type: text(&row[0]),
+            name: text(&row[1]),
+            table_name: text(&row[2]),
+            sql: text(&row[3]),
+        })
+        .collect();
+    // the shadow tables of virtual tables are recreated with them, so they are left out
+    let (_, shadow_rows) = db_manager.db.query_rows(
+        "SELECT name FROM pragma_table_list WHERE schema = 'main' AND type = 'shadow'",
+        &[],
+    )?;
+    let shadow_tables: Vec<String> = shadow_rows.iter().map(|row| text(&row[0])).collect();
+    objects.retain(|object| {
+        let is_shadow = object.object_type == "table" && shadow_tables.contains(&object.name);
+        !object.name.to_lowercase().starts_with("sqlite_") && !is_shadow
+    });
+
+    if let Some(chosen) = tables {
+        let is_chosen = |name: &str| chosen.iter().any(|table| table.eq_ignore_ascii_case(name));
+        for table in chosen {
+            let exists = objects.iter().any(|object| {
+                matches!(object.object_type.as_str(), "table" | "view")
+                    && object.name.eq_ignore_ascii_case(table)
+            });
+            if !exists {
+                return Err(KitError::TableNotFound {
+                    schema: None,
+                    table_name: table.clone(),
+                });
+            }
+        }
+        objects.retain(|object| match object.object_type.as_str() {
+            "table" | "view" => is_chosen(&object.name),
+            _ => is_chosen(&object.table_name),
+        });
+    }
+
+    // tables are moved after the tables they refer to; tables in a cycle keep their order
+    let mut parents = Vec::new();
+    for object in objects
+        .iter()
+        .filter(|object| object.object_type == "table")
+    {
+        let references = match object.is_virtual() {
+            true => Vec::new(),
+            false => load_table_schema(db_manager.db.as_mut(), Some("main"), &object.name)?
+                .foreign_keys
+                .into_iter()
+                .map(|foreign_key| foreign_key.table.to_lowercase())
+                .filter(|parent| *parent != object.name.to_lowercase())
+                .collect(),
+        };
+        parents.push((object.name.to_lowercase(), references));
+    }
+    let mut ordered: Vec<String> = Vec::new();
+    while ordered.len() < parents.len() {
+        let ready = parents.iter().find(|(name, references)| {
+            !ordered.contains(name)
+                && references.iter().all(|parent| {
+                    ordered.contains(parent) || !parents.iter().any(|(name, _)| name == parent)
+                })
+        });
+        let next = match ready {
+            Some((name, _)) => name.clone(),
+            None => match parents.iter().find(|(name, _)| !ordered.contains(name)) {
+                Some((name, _)) => name.clone(),
+                None => break,
+            },
+        };
+        ordered.push(next);
+    }
+    objects.sort_by_key(|object| match object.object_type.as_str() {
+        "table" => ordered
+            .iter()
+            .position(|name| *name == object.name.to_lowercase())
+            .unwrap_or(0),
+        _ => ordered.len(),
+    });
+    Ok(objects)
+}
+
+/// Returns the statements that restore the `AUTOINCREMENT` counters of the dumped tables.
+fn sequence_statements(
+    db_manager: &mut DbManager,
+    tables: &[&DumpObject],
+) -> Result<Vec<String>, KitError> {
+    let (_, exists) = db_manager.db.query_rows(
+        "SELECT 1 FROM main.sqlite_master WHERE name = 'sqlite_sequence'",
+        &[],
+    )?;
+    if exists.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (_, rows) = db_manager
+        .db
+        .query_rows("SELECT name, seq FROM main.sqlite_sequence", &[])?;
+    let statements = rows
+        .iter()
+        .filter(|row| {
+            tables.iter().any(|table| match &row[0] {
+                SerializableValue::Text(name) => table.name.eq_ignore_ascii_case(name),
+                _ => false,
+            })
+        })
+        .map(|row| {
+            let name = sql_literal(&row[0]);
+            format!(
+                "DELETE FROM sqlite_sequence WHERE name = {};\nINSERT INTO sqlite_sequence (name, seq) VALUES ({}, {});\n",
+                name,
+                name,
+                sql_literal(&row[1])
+            )
+        })
+        .collect();
+    Ok(statements)
+}
+
+/// Replays a dump file, or any SQL script, on a connection.
+///
+/// The statements run in batches of `RESTORE_BATCH`. The transaction statements of the script
+/// itself are skipped: on SQLite files the whole replay runs in one savepoint instead, so a
+/// statement that fails undoes every statement before it. libsql servers hold no transaction
+/// between requests, so there each batch is applied on its own and the batches before a failed
+/// one are kept.
+///
+/// # Arguments
+///
+/// * `db_manager` - The connection to replay the dump on.
+/// * `path` - The dump file, in UTF-8.
+/// * `control` - Stops the replay between batches, or interrupts the running statement, when it is cancelled.
+/// * `progress` - Called after each batch.
+///
+/// # Returns
+///
+/// * `Result<RestoreSummary, KitError>` - The number of statements that ran, or the error of the
+///   statement that failed.
+pub fn import_dump(
+    db_manager: &mut DbManager,
+    path: &Path,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(RestoreProgress),
+) -> Result<RestoreSummary, KitError> {
+    let started = Instant::now();
+    if db_manager.pending_edits.is_some() {
+        return Err(KitError::invalid_state(
+            "commit or roll back the staged edits before replaying a dump",
+        ));
+    }
+    let script = fs::read_to_string(path).map_err(|e| KitError::io(path, e))?;
+    let statements: Vec<&str> = lexer::split_statements(&script)
+        .into_iter()
+        .filter(|statement| !is_transaction_statement(statement))
+        .collect();
+    let atomic = matches!(db_manager.connection_type, ConnectionType::Sqlite(_));
+    if atomic {
+        db_manager.db.query_rows("SAVEPOINT kit_restore", &[])?;
+    }
+    let result = replay(db_manager, &statements, control, progress);
+    if atomic {
+        match result {
+            // an interrupted write may have rolled back the savepoint already
+            Err(_) => {
+                let _ = db_manager.db.query_rows("ROLLBACK TO kit_restore", &[]);
+                let _ = db_manager.db.query_rows("RELEASE kit_restore", &[]);
+            }
+            Ok(()) => {
+                db_manager.db.query_rows("RELEASE kit_restore", &[])?;
+            }
+        }
+    }
+    result?;
+    progress(RestoreProgress {
+        dump_id: None,
+        statements_run: statements.len(),
+        statements_total: statements.len(),
+        done: true,
+    });
+    Ok(RestoreSummary {
+        statements: statements.len(),
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// Runs the statements of a dump in batches, and stops at the first one that fails.
+fn replay(
+    db_manager: &mut DbManager,
+    statements: &[&str],
+    control: &QueryControl,
+    progress: &mut dyn FnMut(RestoreProgress),
+) -> Result<(), KitError> {
+    let mut statements_run = 0;
+    for batch in statements.chunks(RESTORE_BATCH) {
+        if control.should_stop() {
+            return Err(control.interrupted(backend(db_manager), batch[0]));
+        }
+        let result = db_manager.db.run_script(batch, true, control)?;
+        if let Some(script_error) = result.error {
+            return Err(control.explain(script_error.error));
+        }
+        statements_run += batch.len();
+        progress(RestoreProgress {
+            dump_id: None,
+            statements_run,
+            statements_total: statements.len(),
+            done: false,
+        });
+    }
+    Ok(())
+}
+
+/// Returns whether a statement begins or ends a transaction.
+fn is_transaction_statement(statement: &str) -> bool {
+    let keyword = statement
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default()
+        .to_uppercase();
+    matches!(keyword.as_str(), "BEGIN" | "COMMIT" | "END")
+        || (keyword == "ROLLBACK" && !statement.to_uppercase().contains(" TO "))
+}
+
+/// Returns the backend of a connection, for the errors of stopped dumps.
+fn backend(db_manager: &DbManager) -> Backend {
+    match db_manager.connection_type {
+        ConnectionType::Sqlite(_) => Backend::Sqlite,
+        ConnectionType::Libsql(_, _) => Backend::Libsql,
+    }
+}
@@ -0,0 +1,155 @@
+/// The `ConnectionId` type from the `connections` module.
+use crate::connections::ConnectionId;
+/// The `ConnectionError` enum from the `db_manager` module.
+use crate::db_manager::ConnectionError;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `fmt` module, used to display errors.
+use std::fmt;
+/// The `Path` type.
+use std::path::Path;
+
+/// `Backend` is an enum that represents the kind of database an error was returned by.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Sqlite,
+    Libsql,
+}
+
+/// `SqlError` is a struct that describes an error the database returned for a statement.
+#[derive(Serialize, Debug, Clone)]
+pub struct SqlError {
+    pub backend: Backend,
+    /// The SQLite extended result code, e.g. `2067` for `SQLITE_CONSTRAINT_UNIQUE`.
+    /// libsql servers only report a message, so it is `None` for them.
+    pub extended_code: Option<i32>,
+    pub message: String,
+    /// The statement that failed, if the error belongs to one.
+    pub sql: Option<String>,
+    /// The byte offset in `sql` of the token SQLite reported the error at, if any.
+    pub offset: Option<i32>,
+}
+
+/// `KitError` is an enum that represents every error a command can return.
+/// It is serialized as `{ "kind": ..., "detail": ... }` so the frontend can react to each case.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum KitError {
+    /// The connection could not be opened.
+    Connection(ConnectionError),
+    /// There is no open connection with the id.
+    UnknownConnection { connection_id: ConnectionId },
+    /// The arguments of the command are invalid, e.g. a negative page offset.
+    InvalidArgument { message: String },
+    /// The command cannot run in the current state, e.g. committing when no edits are staged.
+    InvalidState { message: String },
+    /// The table does not exist.
+    TableNotFound {
+        schema: Option<String>,
+        table_name: String,
+    },
+    /// A `UNIQUE`, `NOT NULL`, `CHECK` or foreign key constraint failed.
+    Constraint(SqlError),
+    /// The database is busy or locked by another connection.
+    Busy(SqlError),
+    /// The database is read-only.
+    ReadOnly(SqlError),
+    /// The statement is not valid SQL.
+    Syntax(SqlError),
+    /// The statement was interrupted before it finished.
+    Interrupted(SqlError),
+    /// The libsql server could not be reached or sent an invalid response.
+    Network(SqlError),
+    /// Any other error returned by the database, such as a missing column.
+    Sql(SqlError),
+    /// A file or folder could not be read or written.
+    Io { path: String, message: String },
+}
+
+/// `KitError` implementation.
+impl KitError {
+    /// Creates an `InvalidArgument` error.
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        KitError::InvalidArgument {
+            message: message.into(),
+        }
+    }
+
+    /// Creates an `InvalidState` error.
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        KitError::InvalidState {
+            message: message.into(),
+        }
+    }
+
+    /// Creates an `Io` error for the file or folder at `path`.
+    pub fn io(path: &Path, error: std::io::Error) -> Self {
+        KitError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Wraps a database error in the variant matching its cause.
+    ///
+    /// The cause is read from the primary result code when there is one. Otherwise, as for
+    /// libsql servers, it is recognized by the wording SQLite uses in the message.
+    pub fn from_sql_error(error: SqlError) -> Self {
+        let message = error.message.to_lowercase();
+        let is_syntax = message.contains("syntax error") || message.contains("incomplete input");
+        match error.extended_code.map(|code| code & 0xff) {
+            Some(19) => KitError::Constraint(error),
+            Some(5) | Some(6) => KitError::Busy(error),
+            Some(8) => KitError::ReadOnly(error),
+            Some(9) => KitError::Interrupted(error),
+            Some(1) if is_syntax => KitError::Syntax(error),
+            Some(_) => KitError::Sql(error),
+            None if message.contains("constraint failed") => KitError::Constraint(error),
+            None if message.contains("database is locked") || message.contains("busy") => {
+                KitError::Busy(error)
+            }
+            None if message.contains("readonly database") => KitError::ReadOnly(error),
+            None if message.contains("interrupted") => KitError::Interrupted(error),
+            None if is_syntax => KitError::Syntax(error),
+            None => KitError::Sql(error),
+        }
+    }
+}
+
+/// This implementation allows a `ConnectionError` to be returned where a `KitError` is expected.
+impl From<ConnectionError> for KitError {
+    fn from(error: ConnectionError) -> Self {
+        KitError::Connection(error)
+    }
+}
+
+/// This implementation allows a `KitError` to be shown as a message.
+impl fmt::Display for KitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KitError::Connection(error) => write!(f, "{}", error),
+            KitError::UnknownConnection { connection_id } => {
+                write!(f, "no open connection with id {}", connection_id)
+            }
+            KitError::InvalidArgument { message } | KitError::InvalidState { message } => {
+                write!(f, "{}", message)
+            }
+            KitError::TableNotFound {
+                schema: Some(schema),
+                table_name,
+            } => write!(f, "no such table: {}.{}", schema, table_name),
+            KitError::TableNotFound { table_name, .. } => {
+                write!(f, "no such table: {}", table_name)
+            }
+            KitError::Constraint(error)
+            | KitError::Busy(error)
+            | KitError::ReadOnly(error)
+            | KitError::Syntax(error)
+            | KitError::Interrupted(error)
+            | KitError::Network(error)
+            | KitError::Sql(error) => write!(f, "{}", error.message),
+            KitError::Io { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+/// The `sql` module, which quotes identifiers.
+use crate::db_manager::sql;
+/// The `ConnectionType` and `DbManager` structs and the `QueryParams` and `StreamItem` enums from the `db_manager` module.
+use crate::db_manager::{ConnectionType, DbManager, QueryParams, StreamItem};
+/// The `Backend` and `KitError` enums.
+use crate::error::{Backend, KitError};
+/// The `QueryControl` struct and the `QueryId` type, which let an export be cancelled.
+use crate::queries::{QueryControl, QueryId};
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Engine` trait and the standard alphabet of the `base64` crate.
+use base64::{engine::general_purpose::STANDARD, Engine};
+/// The `Deserialize` and `Serialize` derive macros.
+use serde::{Deserialize, Serialize};
+/// The `File` type.
+use std::fs::File;
+/// The `BufWriter` type and the `Write` trait, used to write the export file.
+use std::io::{BufWriter, Write};
+/// The `Path` type.
+use std::path::Path;
+/// The `Duration` and `Instant` types, used to pace progress reports.
+use std::time::{Duration, Instant};
+
+/// The least time between two progress reports of an export.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `ExportSource` is an enum that represents what is exported: every row of a table, or the
+/// result of a query.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportSource {
+    Table {
+        schema: Option<String>,
+        table_name: String,
+    },
+    Query {
+        query: String,
+        /// The values of the parameters of the query, as for `sql_query`.
+        params: Option<QueryParams>,
+    },
+}
+
+/// `ExportFormat` is an enum that represents the format of an export file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Comma-separated values, one row per line.
+    Csv,
+    /// Tab-separated values, one row per line.
+    Tsv,
+    /// A JSON array of objects keyed by column name.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// One `INSERT` statement per row.
+    Sql,
+}
+
+/// `Quoting` is an enum that represents when CSV and TSV fields are quoted.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Quoting {
+    /// Only fields containing the delimiter, the quote or a line break.
+    #[default]
+    Necessary,
+    /// Every field except `NULL`s, which are left empty so they can be told apart from empty text.
+    Always,
+    /// No field. Fields containing the delimiter or a line break will not read back correctly.
+    Never,
+}
+
+/// `BlobEncoding` is an enum that represents how blobs are written as text.
+/// Blobs in `INSERT` statements are always written as SQL blob literals.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobEncoding {
+    /// Two lowercase hexadecimal digits per byte.
+    #[default]
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+}
+
+/// `ExportOptions` is a struct that holds how an export is written. Every option has a default.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// The CSV or TSV field delimiter. Defaults to `,` for CSV and a tab for TSV.
+    pub delimiter: Option<char>,
+    /// The CSV or TSV quote character. Defaults to `"`.
+    pub quote: Option<char>,
+    pub quoting: Quoting,
+    /// Whether CSV and TSV files start with a row of column names. Defaults to `true`.
+    pub header: Option<bool>,
+    pub blob_encoding: BlobEncoding,
+    /// The table the `INSERT` statements insert into. Defaults to the exported table, or
+    /// `export` for a query.
+    pub table_name: Option<String>,
+}
+
+/// `ExportProgress` is a struct that reports how far an export has got.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportProgress {
+    /// The id the export was started with, if any.
+    pub export_id: Option<QueryId>,
+    /// The number of rows written so far.
+    pub rows: u64,
+    /// The number of bytes written so far.
+    pub bytes: u64,
+    /// Whether every row has been written. Not reported for exports that fail.
+    pub done: bool,
+}
+
+/// `ExportSummary` is a struct that describes a finished export.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportSummary {
+    pub rows: u64,
+    pub bytes: u64,
+    /// How long the export took, in milliseconds.
+    pub elapsed_ms: f64,
+}
+
+/// Exports rows to a file as they are read, so they are never all held in memory.
+///
+/// # Arguments
+///
+/// * `db_manager` - The connection to read the rows from.
+/// * `source` - The table or query to export.
+/// * `format` - The format of the file.
+/// * `options` - How the file is written.
+/// * `path` - The file to write. It is replaced if it exists, and removed if the export fails.
+/// * `control` - Stops the export when it is cancelled or times out.
+/// * `progress` - Called at most every `PROGRESS_INTERVAL` while rows are written, and once the export is done.
+///
+/// # Returns
+///
+/// * `Result<ExportSummary, KitError>` - How many rows and bytes were written.
+pub fn export_to_file(
+    db_manager: &mut DbManager,
+    source: &ExportSource,
+    format: ExportFormat,
+    options: &ExportOptions,
+    path: &Path,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(ExportProgress),
+) -> Result<ExportSummary, KitError> {
+    let result = write_export(db_manager, source, format, options, path, control, progress);
+    if result.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+/// Writes the export file; `export_to_file` removes it if this fails.
+fn write_export(
+    db_manager: &mut DbManager,
+    source: &ExportSource,
+    format: ExportFormat,
+    options: &ExportOptions,
+    path: &Path,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(ExportProgress),
+) -> Result<ExportSummary, KitError> {
+    let started = Instant::now();
+    let (query, params, table_name) = match source {
+        ExportSource::Table { schema, table_name } => (
+            format!(
+                "SELECT * FROM {}",
+                sql::qualified_name(schema.as_deref(), table_name)
+            ),
+            QueryParams::default(),
+            table_name.clone(),
+        ),
+        ExportSource::Query { query, params } => (
+            query.clone(),
+            params.clone().unwrap_or_default(),
+            "export".to_string(),
+        ),
+    };
+    let table_name = options.table_name.clone().unwrap_or(table_name);
+    let file = File::create(path).map_err(|e| KitError::io(path, e))?;
+    let mut out = BufWriter::new(file);
+    let mut writer = RowWriter::new(format, options, &table_name);
+    let mut rows = 0u64;
+    let mut bytes = 0u64;
+    let mut reported = Instant::now();
+    let mut failed: Option<std::io::Error> = None;
+    let mut write = |text: String, bytes: &mut u64| match out.write_all(text.as_bytes()) {
+        Ok(()) => {
+            *bytes += text.len() as u64;
+            true
+        }
+        Err(e) => {
+            failed = Some(e);
+            false
+        }
+    };
+    db_manager.stream_query(&query, &params, control, &mut |item| {
+        if control.should_stop() {
+            return false;
+        }
+        let text = match item {
+            StreamItem::Columns(columns) => {
+                let names = columns.into_iter().map(|column| column.name).collect();
+                writer.begin(names)
+            }
+            StreamItem::Row(values) => {
+                rows += 1;
+                writer.row(&values)
+            }
+        };
+        if !write(text, &mut bytes) {
+            return false;
+        }
+        if reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            progress(ExportProgress {
+                export_id: None,
+                rows,
+                bytes,
+                done: false,
+            });
+        }
+        true
+    })?;
+    if control.should_stop() {
+        let backend = match db_manager.connection_type {
+            ConnectionType::Sqlite(_) => Backend::Sqlite,
+            ConnectionType::Libsql(_, _) => Backend::Libsql,
+        };
+        return Err(control.interrupted(backend, &query));
+    }
+    write(writer.finish(), &mut bytes);
+    if let Some(e) = failed {
+        return Err(KitError::io(path, e));
+    }
+    out.flush().map_err(|e| KitError::io(path, e))?;
+    progress(ExportProgress {
+        export_id: None,
+        rows,
+        bytes,
+        done: true,
+    });
+    Ok(ExportSummary {
+        rows,
+        bytes,
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// `RowWriter` is a struct that formats the rows of an export as text, one record at a time.
+struct RowWriter<'a> {
+    format: ExportFormat,
+    options: &'a ExportOptions,
+    /// The quoted name of the table `INSERT` statements insert into.
+    table_name: String,
+    columns: Vec<String>,
+    /// Whether a row has been written yet, to separate the elements of a JSON array.
+    wrote_row: bool,
+}
+
+/// `RowWriter` implementation.
+impl<'a> RowWriter<'a> {
+    fn new(format: ExportFormat, options: &'a ExportOptions, table_name: &str) -> Self {
+        RowWriter {
+            format,
+            options,
+            table_name: sql::quote_identifier(table_name),
+            columns: Vec::new(),
+            wrote_row: false,
+        }
+    }
+
+    /// Returns what starts the file, given the names of the columns.
+    fn begin(&mut self, columns: Vec<String>) -> String {
+        self.columns = columns;
+        match self.format {
+            ExportFormat::Csv | ExportFormat::Tsv if self.options.header.unwrap_or(true) => {
+                let fields: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|name| self.delimited_field(name, false))
+                    .collect();
+                fields.join(&self.delimiter().to_string()) + "\n"
+            }
+            ExportFormat::Json => "[".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the record of a row.
+    fn row(&mut self, values: &[SerializableValue]) -> String {
+        let first = !self.wrote_row;
+        self.wrote_row = true;
+        match self.format {
+            ExportFormat::Csv | ExportFormat::Tsv => {
+                let fields: Vec<String> = values
+                    .iter()
+                    .map(|value| match value {
+                        SerializableValue::Null => String::new(),
+                        SerializableValue::Integer(i) => self.delimited_field(&i.to_string(), true),
+                        SerializableValue::Real(f) => {
+                            self.delimited_field(&format!("{:?}", f), true)
+                        }
+                        SerializableValue::Text(text) => self.delimited_field(text, false),
+                        SerializableValue::Blob(blob) => {
+                            self.delimited_field(&self.encode_blob(blob), false)
+                        }
+                    })
+                    .collect();
+                fields.join(&self.delimiter().to_string()) + "\n"
+            }
+            ExportFormat::Json => {
+                let separator = if first { "\n" } else { ",\n" };
+                format!("{}{}", separator, self.json_object(values))
+            }
+            ExportFormat::Ndjson => format!("{}\n", self.json_object(values)),
+            ExportFormat::Sql => {
+                let literals: Vec<String> = values.iter().map(sql_literal).collect();
+                format!(
+                    "INSERT INTO {} ({}) VALUES ({});\n",
+                    self.table_name,
+                    sql::column_list(&self.columns),
+                    literals.join(", ")
+                )
+            }
+        }
+    }
+
+    /// Returns what ends the file.
+    fn finish(&self) -> String {
+        match self.format {
+            ExportFormat::Json if self.wrote_row => "\n]\n".to_string(),
+            ExportFormat::Json => "]\n".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the CSV or TSV field delimiter.
+    fn delimiter(&self) -> char {
+        match (self.options.delimiter, self.format) {
+            (Some(delimiter), _) => delimiter,
+            (None, ExportFormat::Tsv) => '\t',
+            (None, _) => ',',
+        }
+    }
+
+    /// Quotes a CSV or TSV field as the options ask, doubling the quotes inside it.
+    /// Numbers never need quoting unless every field is quoted.
+    fn delimited_field(&self, text: &str, is_number: bool) -> String {
+        let quote = self.options.quote.unwrap_or('"');
+        let needs_quotes = match self.options.quoting {
+            Quoting::Always => true,
+            Quoting::Never => false,
+            Quoting::Necessary => {
+                !is_number
+                    && text
+                        .chars()
+                        .any(|c| c == self.delimiter() || c == quote || c == '\n' || c == '\r')
+            }
+        };
+        match needs_quotes {
+            true => {
+                let doubled = text.replace(quote, &format!("{}{}", quote, quote));
+                format!("{}{}{}", quote, doubled, quote)
+            }
+            false => text.to_string(),
+        }
+    }
+
+    /// Returns a row as a JSON object keyed by column name, with the keys in column order.
+    fn json_object(&self, values: &[SerializableValue]) -> String {
+        let members: Vec<String> = self
+            .columns
+            .iter()
+            .zip(values)
+            .map(|(name, value)| {
+                let value = match value {
+                    SerializableValue::Null => serde_json::Value::Null,
+                    SerializableValue::Integer(i) => serde_json::Value::from(*i),
+                    // NaN and infinities have no JSON form and become `null`
+                    SerializableValue::Real(f) => serde_json::Value::from(*f),
+                    SerializableValue::Text(text) => serde_json::Value::from(text.as_str()),
+                    SerializableValue::Blob(blob) => {
+                        serde_json::Value::from(self.encode_blob(blob))
+                    }
+                };
+                format!("{}:{}", serde_json::Value::from(name.as_str()), value)
+            })
+            .collect();
+        format!("{{{}}}", members.join(","))
+    }
+
+    /// Encodes a blob as text.
+    fn encode_blob(&self, blob: &[u8]) -> String {
+        match self.options.blob_encoding {
+            BlobEncoding::Hex => hex(blob),
+            BlobEncoding::Base64 => STANDARD.encode(blob),
+        }
+    }
+}
+
+/// Returns the bytes as lowercase hexadecimal digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Returns a value as an SQL literal. Reals keep a decimal point so they read back as reals;
+/// NaN has no literal and becomes `NULL`.
+pub fn sql_literal(value: &SerializableValue) -> String {
+    match value {
+        SerializableValue::Null => "NULL".to_string(),
+        SerializableValue::Integer(i) => i.to_string(),
+        SerializableValue::Real(f) if f.is_nan() => "NULL".to_string(),
+        SerializableValue::Real(f) if f.is_infinite() => {
+            if *f > 0.0 { "1e999" } else { "-1e999" }.to_string()
+        }
+        SerializableValue::Real(f) => format!("{:?}", f),
+        SerializableValue::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        SerializableValue::Blob(blob) => format!("X'{}'", hex(blob)),
+    }
+}
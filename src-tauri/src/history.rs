@@ -0,0 +1,158 @@
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `query_error` helper, which converts SQLite errors.
+use crate::native::query_error;
+/// The `params` macro and the `Connection` and `Row` types from the `rusqlite` crate.
+use rusqlite::{params, Connection, Row};
+/// The `Serialize` derive macro.
+use serde::Serialize;
+
+/// The number of entries kept in the history. The oldest entries that are not starred are
+/// removed past it; starred entries are always kept.
+pub const HISTORY_LIMIT: i64 = 10_000;
+
+/// The columns of an entry, in the order `entry_from_row` reads them.
+const ENTRY_COLUMNS: &str =
+    "id, backend, location, sql, executed_at, elapsed_ms, row_count, error, starred";
+
+/// `NewHistoryEntry` is a struct that describes a statement that was just run.
+#[derive(Debug, Clone)]
+pub struct NewHistoryEntry {
+    /// `"sqlite"` or `"libsql"`.
+    pub backend: String,
+    /// The database file path or the libsql URL, without the auth token.
+    pub location: String,
+    pub sql: String,
+    /// When the statement was run, in milliseconds since the Unix epoch.
+    pub executed_at: i64,
+    pub elapsed_ms: Option<f64>,
+    /// The number of rows returned, or changed by statements that return none.
+    pub row_count: Option<i64>,
+    /// The message of the error the statement failed with, if it failed.
+    pub error: Option<String>,
+}
+
+/// `HistoryEntry` is a struct that holds a statement recorded in the history.
+#[derive(Serialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub backend: String,
+    pub location: String,
+    pub sql: String,
+    pub executed_at: i64,
+    pub elapsed_ms: Option<f64>,
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    pub starred: bool,
+}
+
+/// Records a statement, and removes the oldest entries past `HISTORY_LIMIT`.
+pub fn record(conn: &Connection, entry: &NewHistoryEntry) -> Result<(), KitError> {
+    let insert =
+        "INSERT INTO history (backend, location, sql, executed_at, elapsed_ms, row_count, error)
+        VALUES (?, ?, ?, ?, ?, ?, ?)";
+    conn.execute(
+        insert,
+        params![
+            entry.backend,
+            entry.location,
+            entry.sql,
+            entry.executed_at,
+            entry.elapsed_ms,
+            entry.row_count,
+            entry.error
+        ],
+    )
+    .map_err(|e| query_error(e, insert))?;
+    let prune = "DELETE FROM history WHERE starred = 0 AND id <= (
+        SELECT id FROM history WHERE starred = 0 ORDER BY id DESC LIMIT 1 OFFSET ?
+    )";
+    conn.execute(prune, [HISTORY_LIMIT])
+        .map_err(|e| query_error(e, prune))?;
+    Ok(())
+}
+
+/// Lists entries from the most recent, optionally only the starred ones or those run on `location`.
+pub fn list(
+    conn: &Connection,
+    location: Option<&str>,
+    starred_only: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<HistoryEntry>, KitError> {
+    let sql = format!(
+        "SELECT {} FROM history
+        WHERE (?1 IS NULL OR location = ?1) AND (?2 = 0 OR starred = 1)
+        ORDER BY executed_at DESC, id DESC LIMIT ?3 OFFSET ?4",
+        ENTRY_COLUMNS
+    );
+    query_entries(conn, &sql, params![location, starred_only, limit, offset])
+}
+
+/// Searches the statements of the history for every word of `text`, each as a word prefix,
+/// and lists the matches from the most recent.
+pub fn search(conn: &Connection, text: &str, limit: i64) -> Result<Vec<HistoryEntry>, KitError> {
+    let pattern = match_pattern(text);
+    if pattern.is_empty() {
+        return list(conn, None, false, 0, limit);
+    }
+    let sql = format!(
+        "SELECT {} FROM history WHERE id IN (SELECT rowid FROM history_fts WHERE history_fts MATCH ?)
+        ORDER BY executed_at DESC, id DESC LIMIT ?",
+        ENTRY_COLUMNS
+    );
+    query_entries(conn, &sql, params![pattern, limit])
+}
+
+/// Stars or unstars an entry. Returns whether there was an entry with that id.
+pub fn set_starred(conn: &Connection, id: i64, starred: bool) -> Result<bool, KitError> {
+    let sql = "UPDATE history SET starred = ? WHERE id = ?";
+    conn.execute(sql, params![starred, id])
+        .map(|changed| changed > 0)
+        .map_err(|e| query_error(e, sql))
+}
+
+/// Deletes an entry. Returns whether there was an entry with that id.
+pub fn delete(conn: &Connection, id: i64) -> Result<bool, KitError> {
+    let sql = "DELETE FROM history WHERE id = ?";
+    conn.execute(sql, [id])
+        .map(|changed| changed > 0)
+        .map_err(|e| query_error(e, sql))
+}
+
+/// Builds an FTS5 query matching every word of `text` as a prefix. Words are quoted, so
+/// punctuation in SQL such as `*` or `"` is searched for rather than read as query syntax.
+fn match_pattern(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Runs a query returning `ENTRY_COLUMNS` and reads its entries.
+fn query_entries(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<HistoryEntry>, KitError> {
+    let mut stmt = conn.prepare(sql).map_err(|e| query_error(e, sql))?;
+    let entries = stmt
+        .query_map(params, entry_from_row)
+        .and_then(|rows| rows.collect());
+    entries.map_err(|e| query_error(e, sql))
+}
+
+/// Reads an entry from a row of `ENTRY_COLUMNS`.
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        backend: row.get(1)?,
+        location: row.get(2)?,
+        sql: row.get(3)?,
+        executed_at: row.get(4)?,
+        elapsed_ms: row.get(5)?,
+        row_count: row.get(6)?,
+        error: row.get(7)?,
+        starred: row.get(8)?,
+    })
+}
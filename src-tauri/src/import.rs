@@ -0,0 +1,871 @@
+/// The `sql` module, which quotes identifiers.
+use crate::db_manager::sql;
+/// The `DbManager` struct.
+use crate::db_manager::DbManager;
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `PROGRESS_INTERVAL` constant, which paces progress reports like those of exports.
+use crate::export::PROGRESS_INTERVAL;
+/// The `QueryControl` struct and the `QueryId` type, which let an import be cancelled.
+use crate::queries::{QueryControl, QueryId};
+/// The `load_table_schema_if_exists` helper and the `TableSchema` struct from the `schema` module.
+use crate::schema::{load_table_schema_if_exists, TableSchema};
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Encoding` type and the UTF-8 encoding of the `encoding_rs` crate.
+use encoding_rs::{Encoding, UTF_8};
+/// The `DecodeReaderBytesBuilder` type, which decodes files as they are read.
+use encoding_rs_io::DecodeReaderBytesBuilder;
+/// The `Deserialize` and `Serialize` derive macros.
+use serde::{Deserialize, Serialize};
+/// The `HashMap` type, which holds the declared types of created columns.
+use std::collections::HashMap;
+/// The `File` type.
+use std::fs::File;
+/// The `Read` trait, used to read JSON files whole.
+use std::io::Read;
+/// The `Path` type.
+use std::path::Path;
+/// The `Instant` type, used to time imports and pace progress reports.
+use std::time::Instant;
+
+/// The number of rows read from the start of a file to infer the types of its columns.
+pub const SAMPLE_ROWS: usize = 1000;
+
+/// The number of rows inserted in one transaction.
+pub const BATCH_ROWS: usize = 1000;
+
+/// The number of failed rows whose error is reported. Every failed row is still counted.
+pub const MAX_REPORTED_ERRORS: usize = 100;
+
+/// `ImportFormat` is an enum that represents the format of an imported file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// Comma-separated values, one row per line.
+    Csv,
+    /// Tab-separated values, one row per line.
+    Tsv,
+    /// A JSON array of objects or arrays, or one JSON object or array per line.
+    Json,
+}
+
+/// `ImportOptions` is a struct that holds how a file is read. Every option has a default.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// The format of the file. Defaults to the one its extension names, or CSV.
+    pub format: Option<ImportFormat>,
+    /// The CSV or TSV field delimiter, an ASCII character. Defaults to `,` for CSV and a tab for TSV.
+    pub delimiter: Option<char>,
+    /// The CSV or TSV quote character, an ASCII character. Defaults to `"`.
+    pub quote: Option<char>,
+    /// Whether the first row holds the column names, for CSV, TSV and JSON arrays of arrays.
+    /// Defaults to `true`.
+    pub header: Option<bool>,
+    /// The label of the encoding of the file, e.g. `utf-8`, `windows-1252` or `utf-16le`.
+    /// Defaults to UTF-8. A byte order mark always takes precedence.
+    pub encoding: Option<String>,
+}
+
+/// `ImportColumn` is a struct that describes a column of an imported file.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportColumn {
+    pub name: String,
+    /// The type inferred from the values of the first `SAMPLE_ROWS` rows: `INTEGER`, `REAL` or `TEXT`.
+    pub type_name: String,
+}
+
+/// `ImportPreview` is a struct that holds the columns of a file and its first rows.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportPreview {
+    pub columns: Vec<ImportColumn>,
+    /// The first rows, with their values converted to the inferred types.
+    pub rows: Vec<Vec<SerializableValue>>,
+    /// The number of rows the types were inferred from.
+    pub sampled_rows: usize,
+}
+
+/// `ImportTarget` is a struct that describes the table a file is imported into.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ImportTarget {
+    /// The table, in the main schema.
+    pub table_name: String,
+    /// Whether to create the table, which must not exist yet. Its columns are the mapped columns
+    /// of the file, in file order.
+    #[serde(default)]
+    pub create: bool,
+    /// For each column of the file in order, the table column it is imported into, or `None` to
+    /// skip it. Defaults to the column names of the file for a new table. For an existing table,
+    /// it defaults to the columns with the same name, or the columns in table order when the file
+    /// has no header.
+    pub mapping: Option<Vec<Option<String>>>,
+    /// The declared types of the columns of a new table, by column name. Defaults to the inferred types.
+    #[serde(default)]
+    pub column_types: HashMap<String, String>,
+}
+
+/// `ImportRowError` is a struct that describes a row that could not be inserted.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportRowError {
+    /// The 1-based position of the row among the rows of the file, not counting the header.
+    pub record: u64,
+    pub error: KitError,
+}
+
+/// `ImportProgress` is a struct that reports how far an import has got.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportProgress {
+    /// The id the import was started with, if any.
+    pub import_id: Option<QueryId>,
+    pub rows_read: u64,
+    pub rows_inserted: u64,
+    pub rows_failed: u64,
+    /// Whether the import has ended. Not reported for imports that fail.
+    pub done: bool,
+}
+
+/// `ImportSummary` is a struct that describes a finished import.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportSummary {
+    pub rows_read: u64,
+    pub rows_inserted: u64,
+    pub rows_failed: u64,
+    /// The errors of the first `MAX_REPORTED_ERRORS` rows that failed.
+    pub errors: Vec<ImportRowError>,
+    /// Whether the table was created.
+    pub created: bool,
+    /// Whether the import was cancelled. The rows of the batches inserted before are kept.
+    pub cancelled: bool,
+    /// How long the import took, in milliseconds.
+    pub elapsed_ms: f64,
+}
+
+/// Reads the columns and the first rows of a file, and infers the type of each column.
+///
+/// # Arguments
+///
+/// * `path` - The file to read.
+/// * `options` - How the file is read.
+/// * `limit` - The number of rows to return.
+///
+/// # Returns
+///
+/// * `Result<ImportPreview, KitError>` - The columns with their inferred types, and the first rows.
+pub fn preview(
+    path: &Path,
+    options: &ImportOptions,
+    limit: usize,
+) -> Result<ImportPreview, KitError> {
+    let mut file = ImportFile::open(path, options)?;
+    let sample = file.sample(SAMPLE_ROWS.max(limit))?;
+    let types = infer_types(file.columns.len(), &sample);
+    let rows = sample
+        .into_iter()
+        .take(limit)
+        .map(|row| convert_row(row, &types))
+        .collect();
+    let columns = file
+        .columns
+        .iter()
+        .zip(&types)
+        .map(|(name, column_type)| ImportColumn {
+            name: name.clone(),
+            type_name: column_type.type_name().to_string(),
+        })
+        .collect();
+    Ok(ImportPreview {
+        columns,
+        rows,
+        sampled_rows: file.sampled,
+    })
+}
+
+/// Imports the rows of a file into a new or existing table, in batches of `BATCH_ROWS` rows
+/// each inserted in one transaction.
+///
+/// Values are converted to the types inferred for their column, and the table's type affinity
+/// converts them further. Rows that fail, e.g. on a constraint, are skipped and reported; the
+/// other rows are still inserted. A table created for the import is dropped again if the import
+/// fails before any row was inserted.
+///
+/// # Arguments
+///
+/// * `db_manager` - The connection to insert the rows with.
+/// * `path` - The file to read.
+/// * `options` - How the file is read.
+/// * `target` - The table to import into and how the columns of the file map onto it.
+/// * `control` - Stops the import between batches when it is cancelled.
+/// * `progress` - Called at most every `PROGRESS_INTERVAL` while rows are inserted, and once the import has ended.
+///
+/// # Returns
+///
+/// * `Result<ImportSummary, KitError>` - How many rows were inserted and the errors of those that failed.
+pub fn import_file(
+    db_manager: &mut DbManager,
+    path: &Path,
+    options: &ImportOptions,
+    target: &ImportTarget,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(ImportProgress),
+) -> Result<ImportSummary, KitError> {
+    let started = Instant::now();
+    if db_manager.pending_edits.is_some() {
+        return Err(KitError::invalid_state(
+            "commit or roll back the staged edits before importing",
+        ));
+    }
+    let mut file = ImportFile::open(path, options)?;
+    let sample = file.sample(SAMPLE_ROWS)?;
+    let types = infer_types(file.columns.len(), &sample);
+    let existing = load_table_schema_if_exists(db_manager.db.as_mut(), None, &target.table_name)?;
+    let mapping = resolve_mapping(&file, target, existing.as_ref())?;
+    let columns: Vec<String> = mapping.iter().flatten().cloned().collect();
+    if target.create {
+        let definitions: Vec<String> = mapping
+            .iter()
+            .zip(&types)
+            .filter_map(|(column, column_type)| column.as_ref().map(|name| (name, column_type)))
+            .map(|(name, column_type)| {
+                let type_name = target
+                    .column_types
+                    .get(name)
+                    .map(String::as_str)
+                    .unwrap_or(column_type.type_name());
+                format!("{} {}", sql::quote_identifier(name), type_name)
+            })
+            .collect();
+        let create = format!(
+            "CREATE TABLE {} ({})",
+            sql::quote_identifier(&target.table_name),
+            definitions.join(", ")
+        );
+        db_manager.db.query_rows(&create, &[])?;
+    }
+
+    let mut summary = ImportSummary {
+        rows_read: 0,
+        rows_inserted: 0,
+        rows_failed: 0,
+        errors: Vec::new(),
+        created: target.create,
+        cancelled: false,
+        elapsed_ms: 0.0,
+    };
+    let rows = sample
+        .into_iter()
+        .map(Ok)
+        .chain(file.records)
+        .map(|record| {
+            record.map(|row| {
+                convert_row(row, &types)
+                    .into_iter()
+                    .zip(&mapping)
+                    .filter(|(_, column)| column.is_some())
+                    .map(|(value, _)| value)
+                    .collect()
+            })
+        });
+    let inserted = insert_batches(
+        db_manager,
+        &target.table_name,
+        &columns,
+        rows,
+        control,
+        progress,
+        &mut summary,
+    );
+    if let Err(error) = inserted {
+        // a table created for rows that never arrived would only be in the way of a retry
+        if target.create && summary.rows_inserted == 0 {
+            let drop = format!("DROP TABLE {}", sql::quote_identifier(&target.table_name));
+            let _ = db_manager.db.query_rows(&drop, &[]);
+        }
+        return Err(error);
+    }
+    summary.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    Ok(summary)
+}
+
+/// Inserts rows into a table in batches of `BATCH_ROWS` rows, and counts them in `summary`.
+///
+/// # Arguments
+///
+/// * `db_manager` - The connection to insert the rows with.
+/// * `table_name` - The table to insert into.
+/// * `columns` - The columns the values of each row are given for.
+/// * `rows` - The rows to insert, or the error that stopped reading them.
+/// * `control` - Stops the insertion between batches when it is cancelled.
+/// * `progress` - Called at most every `PROGRESS_INTERVAL` while rows are inserted, and once they all are.
+/// * `summary` - Counts the rows read, inserted and failed.
+///
+/// # Returns
+///
+/// * `Result<(), KitError>` - An error if a row could not be read or a batch could not be inserted.
+fn insert_batches(
+    db_manager: &mut DbManager,
+    table_name: &str,
+    columns: &[String],
+    mut rows: impl Iterator<Item = Result<Vec<SerializableValue>, KitError>>,
+    control: &QueryControl,
+    progress: &mut dyn FnMut(ImportProgress),
+    summary: &mut ImportSummary,
+) -> Result<(), KitError> {
+    let report = |summary: &ImportSummary, done: bool| ImportProgress {
+        import_id: None,
+        rows_read: summary.rows_read,
+        rows_inserted: summary.rows_inserted,
+        rows_failed: summary.rows_failed,
+        done,
+    };
+    let mut reported = Instant::now();
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    let mut ended = false;
+    while !ended {
+        while batch.len() < BATCH_ROWS {
+            match rows.next().transpose()? {
+                Some(values) => {
+                    batch.push(values);
+                    summary.rows_read += 1;
+                }
+                None => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            let first_record = summary.rows_read - batch.len() as u64 + 1;
+            let failed = db_manager.insert_rows(table_name, columns, &batch)?;
+            summary.rows_inserted += (batch.len() - failed.len()) as u64;
+            summary.rows_failed += failed.len() as u64;
+            for (index, error) in failed {
+                if summary.errors.len() < MAX_REPORTED_ERRORS {
+                    summary.errors.push(ImportRowError {
+                        record: first_record + index as u64,
+                        error,
+                    });
+                }
+            }
+            batch.clear();
+        }
+        if !ended && control.should_stop() {
+            summary.cancelled = true;
+            break;
+        }
+        if reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            progress(report(summary, false));
+        }
+    }
+    progress(report(summary, true));
+    Ok(())
+}
+
+/// Returns, for each column of the file, the table column it is imported into, and checks
+/// that the mapped columns exist in an existing table and are each mapped once.
+fn resolve_mapping(
+    file: &ImportFile,
+    target: &ImportTarget,
+    existing: Option<&TableSchema>,
+) -> Result<Vec<Option<String>>, KitError> {
+    let table_columns: Vec<&str> = existing
+        .map(|schema| {
+            schema
+                .columns
+                .iter()
+                .map(|column| column.name.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mapping = match (&target.mapping, existing) {
+        (_, Some(_)) if target.create => {
+            return Err(KitError::invalid_argument(format!(
+                "table {} already exists",
+                target.table_name
+            )))
+        }
+        (_, None) if !target.create => {
+            return Err(KitError::TableNotFound {
+                schema: None,
+                table_name: target.table_name.clone(),
+            })
+        }
+        (Some(mapping), _) => {
+            if mapping.len() != file.columns.len() {
+                return Err(KitError::invalid_argument(format!(
+                    "the mapping has {} entries for {} columns in the file",
+                    mapping.len(),
+                    file.columns.len()
+                )));
+            }
+            mapping.clone()
+        }
+        (None, None) => file.columns.iter().cloned().map(Some).collect(),
+        (None, Some(_)) if file.header => file
+            .columns
+            .iter()
+            .map(|name| {
+                table_columns
+                    .iter()
+                    .find(|column| column.eq_ignore_ascii_case(name))
+                    .map(|column| column.to_string())
+            })
+            .collect(),
+        (None, Some(_)) => (0..file.columns.len())
+            .map(|i| table_columns.get(i).map(|column| column.to_string()))
+            .collect(),
+    };
+    let mut seen: Vec<String> = Vec::new();
+    for column in mapping.iter().flatten() {
+        if existing.is_some() && !table_columns.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+            return Err(KitError::invalid_argument(format!(
+                "table {} has no column {}",
+                target.table_name, column
+            )));
+        }
+        if seen.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+            return Err(KitError::invalid_argument(format!(
+                "column {} is mapped more than once",
+                column
+            )));
+        }
+        seen.push(column.clone());
+    }
+    if seen.is_empty() {
+        return Err(KitError::invalid_argument(
+            "no column of the file is mapped to a column of the table",
+        ));
+    }
+    Ok(mapping)
+}
+
+/// `Records` is the type of the rows read from a file, one value per column of the file.
+type Records = Box<dyn Iterator<Item = Result<Vec<SerializableValue>, KitError>>>;
+
+/// `ImportFile` is a struct that reads the rows of a file being imported.
+struct ImportFile {
+    /// The names of the columns, from the header or `column1`, `column2`, ... without one.
+    columns: Vec<String>,
+    /// Whether the column names were read from the file.
+    header: bool,
+    records: Records,
+    /// The number of rows read by `sample`.
+    sampled: usize,
+}
+
+/// `ImportFile` implementation.
+impl ImportFile {
+    /// Opens a file and reads its column names.
+    fn open(path: &Path, options: &ImportOptions) -> Result<Self, KitError> {
+        let encoding = match &options.encoding {
+            Some(label) => Encoding::for_label(label.trim().as_bytes())
+                .ok_or_else(|| KitError::invalid_argument(format!("unknown encoding {}", label)))?,
+            None => UTF_8,
+        };
+        let file = File::open(path).map_err(|e| KitError::io(path, e))?;
+        let reader = DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding))
+            .bom_override(true)
+            .strip_bom(true)
+            .build(file);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let format = options.format.unwrap_or(match extension.as_deref() {
+            Some("tsv") | Some("tab") => ImportFormat::Tsv,
+            Some("json") | Some("ndjson") | Some("jsonl") => ImportFormat::Json,
+            _ => ImportFormat::Csv,
+        });
+        let header = options.header.unwrap_or(true);
+        let (columns, records) = match format {
+            ImportFormat::Csv | ImportFormat::Tsv => {
+                let delimiter = match (options.delimiter, format) {
+                    (Some(delimiter), _) => delimiter,
+                    (None, ImportFormat::Tsv) => '\t',
+                    (None, _) => ',',
+                };
+                let quote = options.quote.unwrap_or('"');
+                delimited_records(path, reader, ascii(delimiter)?, ascii(quote)?, header)?
+            }
+            ImportFormat::Json => json_records(path, reader, header)?,
+        };
+        Ok(ImportFile {
+            columns: unique_names(columns),
+            header,
+            records,
+            sampled: 0,
+        })
+    }
+
+    /// Reads up to `count` rows from the start of the file.
+    fn sample(&mut self, count: usize) -> Result<Vec<Vec<SerializableValue>>, KitError> {
+        let mut rows = Vec::new();
+        while rows.len() < count {
+            match self.records.next().transpose()? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        self.sampled = rows.len();
+        Ok(rows)
+    }
+}
+
+/// Returns a delimiter or quote character as the byte the CSV reader expects.
+fn ascii(c: char) -> Result<u8, KitError> {
+    match c.is_ascii() {
+        true => Ok(c as u8),
+        false => Err(KitError::invalid_argument(format!(
+            "the delimiter and quote must be ASCII characters, not {:?}",
+            c
+        ))),
+    }
+}
+
+/// Reads the column names and the rows of a CSV or TSV file. Empty fields are `NULL`; rows
+/// with fewer fields than columns are padded with `NULL`s, and extra fields are dropped.
+fn delimited_records(
+    path: &Path,
+    reader: impl Read + 'static,
+    delimiter: u8,
+    quote: u8,
+    header: bool,
+) -> Result<(Vec<String>, Records), KitError> {
+    let csv_error = {
+        let path = path.display().to_string();
+        move |e: csv::Error| KitError::Io {
+            path: path.clone(),
+            message: e.to_string(),
+        }
+    };
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader)
+        .into_records();
+    let first = records.next().transpose().map_err(csv_error.clone())?;
+    let first: Vec<String> = first
+        .map(|record| record.iter().map(String::from).collect())
+        .unwrap_or_default();
+    let width = first.len();
+    let columns = match header {
+        true => first.clone(),
+        false => (1..=width).map(|i| format!("column{}", i)).collect(),
+    };
+    let to_row = move |fields: Vec<String>| -> Vec<SerializableValue> {
+        let mut row: Vec<SerializableValue> = fields
+            .into_iter()
+            .take(width)
+            .map(|field| match field.is_empty() {
+                true => SerializableValue::Null,
+                false => SerializableValue::Text(field),
+            })
+            .collect();
+        row.resize(width, SerializableValue::Null);
+        row
+    };
+    let first_row = match header || width == 0 {
+        true => None,
+        false => Some(Ok(to_row(first))),
+    };
+    let rest = records.map(move |record| {
+        record
+            .map(|record| to_row(record.iter().map(String::from).collect()))
+            .map_err(csv_error.clone())
+    });
+    Ok((columns, Box::new(first_row.into_iter().chain(rest))))
+}
+
+/// Reads the column names and the rows of a JSON file, which is read whole.
+///
+/// The file holds an array of rows or one row per line. Rows are objects, whose keys are the
+/// columns in order of first appearance, or arrays, whose first one is the header when `header`
+/// is set. Booleans become `0` and `1`, and nested arrays and objects are kept as JSON text.
+fn json_records(
+    path: &Path,
+    mut reader: impl Read,
+    header: bool,
+) -> Result<(Vec<String>, Records), KitError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|e| KitError::io(path, e))?;
+    let json_error =
+        |e: serde_json::Error| KitError::invalid_argument(format!("{}: {}", path.display(), e));
+    let mut values: Vec<serde_json::Value> = serde_json::Deserializer::from_str(&text)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .map_err(json_error)?;
+    // a file holding one array of rows, rather than one row per line
+    let is_array_of_rows = |value: &serde_json::Value| match value {
+        serde_json::Value::Array(rows) => rows.iter().all(|row| row.is_array() || row.is_object()),
+        _ => false,
+    };
+    if values.len() == 1 && is_array_of_rows(&values[0]) {
+        if let Some(serde_json::Value::Array(rows)) = values.pop() {
+            values = rows;
+        }
+    }
+    let mut columns: Vec<String> = Vec::new();
+    let mut rows = Vec::with_capacity(values.len());
+    let mut arrays = values.into_iter().peekable();
+    if header && matches!(arrays.peek(), Some(serde_json::Value::Array(_))) {
+        if let Some(serde_json::Value::Array(names)) = arrays.next() {
+            columns = names
+                .into_iter()
+                .map(|name| match name {
+                    serde_json::Value::String(name) => name,
+                    name => name.to_string(),
+                })
+                .collect();
+        }
+    }
+    for value in arrays {
+        match value {
+            serde_json::Value::Object(object) => {
+                let mut row = vec![SerializableValue::Null; columns.len()];
+                for (key, value) in object {
+                    let index = match columns.iter().position(|column| *column == key) {
+                        Some(index) => index,
+                        None => {
+                            columns.push(key);
+                            row.push(SerializableValue::Null);
+                            columns.len() - 1
+                        }
+                    };
+                    row[index] = json_value(value);
+                }
+                rows.push(row);
+            }
+            serde_json::Value::Array(values) => {
+                for i in columns.len()..values.len() {
+                    columns.push(format!("column{}", i + 1));
+                }
+                rows.push(values.into_iter().map(json_value).collect());
+            }
+            value => {
+                return Err(KitError::invalid_argument(format!(
+                    "{}: expected rows as objects or arrays, found {}",
+                    path.display(),
+                    value
+                )))
+            }
+        }
+    }
+    let width = columns.len();
+    let rows = rows
+        .into_iter()
+        .map(move |mut row: Vec<SerializableValue>| {
+            row.resize(width, SerializableValue::Null);
+            Ok(row)
+        });
+    Ok((columns, Box::new(rows)))
+}
+
+/// Converts a JSON value to the value it is imported as.
+fn json_value(value: serde_json::Value) -> SerializableValue {
+    match value {
+        serde_json::Value::Null => SerializableValue::Null,
+        serde_json::Value::Bool(b) => SerializableValue::Integer(b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => SerializableValue::Integer(i),
+            None => SerializableValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(text) => SerializableValue::Text(text),
+        value => SerializableValue::Text(value.to_string()),
+    }
+}
+
+/// Makes column names usable: blank names become `column1`, `column2`, ... by position, and
+/// repeated names, compared without case as SQLite does, get a `_2`, `_3`, ... suffix.
+fn unique_names(names: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::with_capacity(names.len());
+    for (i, name) in names.into_iter().enumerate() {
+        let name = match name.trim() {
+            "" => format!("column{}", i + 1),
+            name => name.to_string(),
+        };
+        let taken = |candidate: &str, unique: &[String]| {
+            unique
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(candidate))
+        };
+        let mut candidate = name.clone();
+        let mut n = 1;
+        while taken(&candidate, &unique) {
+            n += 1;
+            candidate = format!("{}_{}", name, n);
+        }
+        unique.push(candidate);
+    }
+    unique
+}
+
+/// `ColumnType` is an enum that represents the type inferred for a column. Types are ordered
+/// so that the type of a column is the greatest type of its values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ColumnType {
+    /// Only `NULL`s were seen.
+    Empty,
+    Integer,
+    Real,
+    Text,
+}
+
+/// `ColumnType` implementation.
+impl ColumnType {
+    /// Returns the declared type of a column of this type. Columns of `NULL`s are `TEXT`.
+    fn type_name(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Empty | ColumnType::Text => "TEXT",
+        }
+    }
+
+    /// Returns the type of a value. Text holding a number is a number, unless it has leading
+    /// zeros, as codes such as `007` or `01234` do, which would be lost.
+    fn of(value: &SerializableValue) -> Self {
+        match value {
+            SerializableValue::Null => ColumnType::Empty,
+            SerializableValue::Integer(_) => ColumnType::Integer,
+            SerializableValue::Real(_) => ColumnType::Real,
+            SerializableValue::Text(text) if parse_integer(text).is_some() => ColumnType::Integer,
+            SerializableValue::Text(text) if parse_real(text).is_some() => ColumnType::Real,
+            SerializableValue::Text(_) | SerializableValue::Blob(_) => ColumnType::Text,
+        }
+    }
+}
+
+/// Infers the type of each column from the rows of a sample.
+fn infer_types(width: usize, rows: &[Vec<SerializableValue>]) -> Vec<ColumnType> {
+    let mut types = vec![ColumnType::Empty; width];
+    for row in rows {
+        for (column_type, value) in types.iter_mut().zip(row) {
+            *column_type = (*column_type).max(ColumnType::of(value));
+        }
+    }
+    types
+}
+
+/// Converts the text values of a row to the types inferred for their columns. Values that do
+/// not hold a number of their column's type are kept as they are.
+fn convert_row(row: Vec<SerializableValue>, types: &[ColumnType]) -> Vec<SerializableValue> {
+    row.into_iter()
+        .zip(types)
+        .map(|(value, column_type)| match (value, column_type) {
+            (SerializableValue::Text(text), ColumnType::Integer) => match parse_integer(&text) {
+                Some(i) => SerializableValue::Integer(i),
+                None => SerializableValue::Text(text),
+            },
+            (SerializableValue::Text(text), ColumnType::Real) => match parse_real(&text) {
+                Some(f) => SerializableValue::Real(f),
+                None => SerializableValue::Text(text),
+            },
+            (value, _) => value,
+        })
+        .collect()
+}
+
+/// Parses text holding an integer without leading zeros or a sign other than `-`.
+fn parse_integer(text: &str) -> Option<i64> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let plain = !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (digits == "0" || !digits.starts_with('0'));
+    plain.then(|| text.parse().ok()).flatten()
+}
+
+/// Parses text holding a finite decimal number, e.g. `-1.5` or `2e10`, without leading zeros.
+fn parse_real(text: &str) -> Option<f64> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let plain = digits
+        .bytes()
+        .next()
+        .is_some_and(|b| b.is_ascii_digit() || b == b'.')
+        && digits
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+'))
+        && !(digits.starts_with('0') && digits.as_bytes().get(1).is_some_and(u8::is_ascii_digit));
+    plain
+        .then(|| text.parse::<f64>().ok())
+        .flatten()
+        .filter(|f| f.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_manager::ConnectionType;
+    use crate::native::NativeDbManager;
+
+    /// Returns a manager of an in-memory database set up with `sql`.
+    fn db_manager(sql: &str) -> DbManager {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        DbManager::new(
+            Box::new(NativeDbManager::new(conn)),
+            ConnectionType::Sqlite(":memory:".to_string()),
+        )
+    }
+
+    #[test]
+    fn import_keeps_the_rows_around_a_failed_one() {
+        let mut db_manager =
+            db_manager("CREATE TABLE t (a INTEGER UNIQUE, b TEXT); INSERT INTO t VALUES (2, 'x');");
+        let path = std::env::temp_dir().join(format!("import-unique-{}.csv", std::process::id()));
+        std::fs::write(&path, "a,b\n1,one\n2,two\n3,three\n").unwrap();
+        let target = ImportTarget {
+            table_name: "t".to_string(),
+            create: false,
+            mapping: None,
+            column_types: HashMap::new(),
+        };
+        let summary = import_file(
+            &mut db_manager,
+            &path,
+            &ImportOptions::default(),
+            &target,
+            &QueryControl::default(),
+            &mut |_| {},
+        );
+        std::fs::remove_file(&path).unwrap();
+        let summary = summary.unwrap();
+
+        assert_eq!(
+            (
+                summary.rows_read,
+                summary.rows_inserted,
+                summary.rows_failed
+            ),
+            (3, 2, 1)
+        );
+        assert_eq!(summary.errors.len(), 1);
+        assert_eq!(summary.errors[0].record, 2);
+        assert!(matches!(summary.errors[0].error, KitError::Constraint(_)));
+        let (_, rows) = db_manager
+            .db
+            .query_rows("SELECT a, b FROM t ORDER BY a", &[])
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    SerializableValue::Integer(1),
+                    SerializableValue::Text("one".to_string())
+                ],
+                vec![
+                    SerializableValue::Integer(2),
+                    SerializableValue::Text("x".to_string())
+                ],
+                vec![
+                    SerializableValue::Integer(3),
+                    SerializableValue::Text("three".to_string())
+                ],
+            ]
+        );
+    }
+}
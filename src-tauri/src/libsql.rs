@@ -1,10 +1,37 @@
+/// The `HashMap` type.
+use std::collections::HashMap;
+/// The `Error` trait, used to find what caused an error of the libsql client.
+use std::error::Error;
+/// The channel types, used to wait for a request that may be abandoned.
+use std::sync::mpsc::{self, RecvTimeoutError};
+/// The `Arc` type, which shares the client with the thread that sends a request.
+use std::sync::Arc;
+/// The `thread` module, used to send requests that may be abandoned.
+use std::thread;
+/// The `Duration` and `Instant` types, used to time statements and poll for cancellation.
+use std::time::{Duration, Instant};
+
+/// The `StatusCode` type, which tells why the server refused a request.
+use reqwest::StatusCode;
 /// The `rusqlite::Result` type.
 use rusqlite::Result;
 
-/// The `DbManagerTrait` trait from the `db_manager` module.
-use crate::db_manager::DbManagerTrait;
-/// The `ColumnInfo` struct.
-use crate::ColumnInfo;
+/// The `CommitListener` type, which receives the rows a transaction changed.
+use crate::changes::CommitListener;
+/// The `DbManagerTrait` trait and its helpers from the `db_manager` module.
+use crate::db_manager::{
+    column_infos, elapsed_ms, lexer, sql, sql::SqlStatement, ConnectionError, DbManagerTrait,
+    KeyShape, ResultRows, RowKey, ScriptError, ScriptResult, StatementResult, StreamItem,
+    StreamSink, TableQuery, WriteResult,
+};
+/// The `KitError` enum and the types it is built from.
+use crate::error::{Backend, KitError, SqlError};
+/// The `QueryControl` struct, which cancels and times out queries.
+use crate::queries::QueryControl;
+/// The `RowCountKind` enum, which tells whether a row count is exact.
+use crate::row_counts::RowCountKind;
+/// The `pragma_call` helper from the `schema` module.
+use crate::schema::pragma_call;
 /// The `SerializableValue` enum.
 use crate::SerializableValue;
 /// The `TableRequest` struct.
@@ -13,7 +40,182 @@ use crate::TableRequest;
 /// The `LibsqlDbManager` struct, which represents a connection to a SQLite database.
 pub struct LibsqlDbManager {
     /// The SQLite connection.
-    libsqlite_conn: libsql_client::SyncClient,
+    libsqlite_conn: Arc<libsql_client::SyncClient>,
+    /// The statements queued since `begin_edits`, `None` when edits are not staged.
+    /// They are sent in one interactive transaction on commit.
+    staged: Option<Vec<SqlStatement>>,
+    /// The number of statements sent by this manager that may have changed rows.
+    total_changes: u64,
+}
+
+/// This implementation allows a `SerializableValue` to be sent as a statement argument.
+impl From<SerializableValue> for libsql_client::Value {
+    fn from(value: SerializableValue) -> Self {
+        match value {
+            SerializableValue::Null => libsql_client::Value::Null,
+            SerializableValue::Integer(value) => libsql_client::Value::Integer { value },
+            SerializableValue::Real(value) => libsql_client::Value::Float { value },
+            SerializableValue::Text(value) => libsql_client::Value::Text { value },
+            SerializableValue::Blob(value) => libsql_client::Value::Blob { value },
+        }
+    }
+}
+
+/// This implementation allows for conversion from a libsql value to a `SerializableValue`.
+impl From<libsql_client::Value> for SerializableValue {
+    fn from(value: libsql_client::Value) -> Self {
+        match value {
+            libsql_client::Value::Null => SerializableValue::Null,
+            libsql_client::Value::Integer { value } => SerializableValue::Integer(value),
+            libsql_client::Value::Float { value } => SerializableValue::Real(value),
+            libsql_client::Value::Text { value } => SerializableValue::Text(value),
+            libsql_client::Value::Blob { value } => SerializableValue::Blob(value),
+        }
+    }
+}
+
+/// Converts a libsql result set into column names and rows, keeping the column order of the query.
+fn result_rows(data: libsql_client::ResultSet) -> ResultRows {
+    let rows = data
+        .rows
+        .into_iter()
+        .map(|row| {
+            row.values
+                .into_iter()
+                .map(SerializableValue::from)
+                .collect()
+        })
+        .collect();
+    (data.columns, rows)
+}
+
+/// Converts a generated statement into a libsql statement, sending its values as arguments.
+fn libsql_statement(statement: &SqlStatement) -> libsql_client::Statement {
+    let args: Vec<libsql_client::Value> = statement
+        .params
+        .iter()
+        .cloned()
+        .map(libsql_client::Value::from)
+        .collect();
+    libsql_client::Statement::with_args(&statement.sql, &args)
+}
+
+/// Converts the result set of a statement that changed rows into a `WriteResult`.
+/// The rowid is only meaningful after an insert, so it is only kept when `inserted` is set.
+fn write_result(data: libsql_client::ResultSet, inserted: bool) -> WriteResult {
+    WriteResult {
+        rows_affected: data.rows_affected,
+        last_insert_rowid: if inserted {
+            data.last_insert_rowid
+        } else {
+            None
+        },
+        staged: false,
+    }
+}
+
+/// `TransactionOutcome` is a struct that holds what the statements of a transaction did.
+struct TransactionOutcome {
+    /// The result set of each statement that ran, and how long it took in milliseconds.
+    results: Vec<(libsql_client::ResultSet, f64)>,
+    /// The statement that failed, if any, in which case the transaction was not committed. The
+    /// index is `None` if the transaction failed to begin or to commit.
+    error: Option<ScriptError>,
+    /// Whether the statements that ran were rolled back. It is not known when the commit was
+    /// lost to a network error.
+    rolled_back: bool,
+}
+
+/// Runs statements one request at a time in an interactive transaction, which is committed once
+/// they all succeed and rolled back as soon as one fails or the query is stopped.
+///
+/// The client runs a batch between `BEGIN` and `END` without conditions, so the statements after
+/// a failed one still run and are committed; an interactive transaction is the only way to roll
+/// them all back.
+///
+/// # Arguments
+///
+/// * `client` - The client to send the requests with.
+/// * `statements` - The statements to run, in order.
+/// * `control` - Rolls the transaction back before the next statement when the query is stopped.
+///
+/// # Returns
+///
+/// * `TransactionOutcome` - The result of each statement that ran, and the error that kept the
+///   transaction from committing.
+fn run_transaction(
+    client: &libsql_client::SyncClient,
+    statements: &[SqlStatement],
+    control: &QueryControl,
+) -> TransactionOutcome {
+    let mut outcome = TransactionOutcome {
+        results: Vec::with_capacity(statements.len()),
+        error: None,
+        rolled_back: false,
+    };
+    let transaction = match client.transaction() {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            outcome.error = Some(ScriptError {
+                index: None,
+                error: query_error(e, "BEGIN"),
+            });
+            return outcome;
+        }
+    };
+    for (index, statement) in statements.iter().enumerate() {
+        let started = Instant::now();
+        let result = if control.should_stop() {
+            Err(control.interrupted(Backend::Libsql, &statement.sql))
+        } else {
+            transaction
+                .execute(libsql_statement(statement))
+                .map_err(|e| query_error(e, &statement.sql))
+        };
+        match result {
+            Ok(data) => outcome.results.push((data, elapsed_ms(started))),
+            Err(error) => {
+                // the server also rolls back the transaction of a stream it closes
+                let _ = transaction.rollback();
+                outcome.error = Some(ScriptError {
+                    index: Some(index),
+                    error,
+                });
+                outcome.rolled_back = true;
+                return outcome;
+            }
+        }
+    }
+    if let Err(e) = transaction.commit() {
+        let error = query_error(e, "COMMIT");
+        outcome.rolled_back = !matches!(error, KitError::Network(_));
+        outcome.error = Some(ScriptError { index: None, error });
+    }
+    outcome
+}
+
+/// How often a request that is waited for checks whether its query was cancelled or timed out.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Converts the result set of a statement of a script into a `StatementResult`.
+fn statement_result(
+    sql: &str,
+    data: libsql_client::ResultSet,
+    elapsed_ms: Option<f64>,
+) -> StatementResult {
+    let rows_affected = data.rows_affected;
+    let last_insert_rowid = data
+        .last_insert_rowid
+        .filter(|_| rows_affected > 0 && lexer::is_insert(sql));
+    let (names, rows) = result_rows(data);
+    StatementResult {
+        sql: sql.to_string(),
+        column_names: column_infos(&names, &[], &rows),
+        rows,
+        rows_affected,
+        last_insert_rowid,
+        elapsed_ms,
+    }
 }
 
 /// Implementation of `LibsqlDbManager`.
@@ -29,219 +231,347 @@ impl LibsqlDbManager {
     /// * `LibsqlDbManager` - The new `LibsqlDbManager`.
     pub fn new(lsql: libsql_client::SyncClient) -> Self {
         LibsqlDbManager {
-            libsqlite_conn: lsql,
+            libsqlite_conn: Arc::new(lsql),
+            staged: None,
+            total_changes: 0,
         }
     }
-}
 
-/// Implementation of `DbManagerTrait` for `LibsqlDbManager`.
-impl DbManagerTrait for LibsqlDbManager {
-    /// Gets all table names from the SQLite database.
+    /// Connects to a libsql server and checks that it accepts queries.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The `libsql://` URL of the database.
+    /// * `auth_token` - The auth token, if the server requires one.
     ///
     /// # Returns
     ///
-    /// * `Result<Vec<String>, String>` - A `Result` containing a `Vec` of table names if successful, or an error message if not.
-    fn get_all_tables(&mut self) -> Result<Vec<String>, String> {
-        let mut results = Vec::new();
-        let query = "SELECT name FROM sqlite_master WHERE type='table'";
-        let result = self.libsqlite_conn.execute(query);
-        match result {
-            Ok(data) => {
-                for row in data.rows {
-                    let mut table_name = row.value_map.get("name").unwrap().to_string();
-                    // trim quotes
-                    table_name = table_name.trim_matches('\"').to_string();
-                    // if starts with _ skip
-                    if table_name.starts_with('_') {
-                        continue;
-                    }
-                    results.push(table_name);
+    /// * `Result<LibsqlDbManager, ConnectionError>` - The new `LibsqlDbManager`, or why the connection failed.
+    pub fn connect(url: &str, auth_token: Option<String>) -> Result<Self, ConnectionError> {
+        let client = libsql_client::SyncClient::from_config(libsql_client::Config {
+            url: url.try_into().map_err(|e| ConnectionError::BadUrl {
+                url: url.to_string(),
+                message: format!("{}", e),
+            })?,
+            auth_token,
+        })
+        .map_err(|e| remote_error(url, e))?;
+        // the client connects lazily, so a cheap query surfaces auth and network failures now
+        client
+            .execute("SELECT 1")
+            .map_err(|e| remote_error(url, e))?;
+        Ok(LibsqlDbManager::new(client))
+    }
+
+    /// Sends a request for the statement `sql` on a separate thread and waits for its response.
+    ///
+    /// The client blocks until the server responds, so when the query is cancelled or times out
+    /// the request is abandoned instead: an `Interrupted` error is returned right away and the
+    /// response is dropped when it arrives. The server may still finish running the statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The statement the request runs, used in errors.
+    /// * `control` - Abandons the request when the query is cancelled or times out.
+    /// * `request` - Sends the request with the client.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<T, KitError>` - The response, or the error of the request.
+    fn send<T, E>(
+        &mut self,
+        sql: &str,
+        control: &QueryControl,
+        request: impl FnOnce(&libsql_client::SyncClient) -> Result<T, E> + Send + 'static,
+    ) -> Result<T, KitError>
+    where
+        T: Send + 'static,
+        E: AsRef<dyn Error + Send + Sync> + Send + 'static,
+    {
+        let client = self.libsqlite_conn.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // the receiver is gone if the request was abandoned
+            let _ = sender.send(request(&client));
+        });
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(response) => return response.map_err(|e| query_error(e, sql)),
+                Err(RecvTimeoutError::Timeout) if control.should_stop() => {
+                    // the server may still change rows, so the request is counted as a change
+                    self.total_changes += 1;
+                    return Err(control.interrupted(Backend::Libsql, sql));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(KitError::Network(SqlError {
+                        backend: Backend::Libsql,
+                        extended_code: None,
+                        message: "the request failed without a response".to_string(),
+                        sql: Some(sql.to_string()),
+                        offset: None,
+                    }))
                 }
             }
-            Err(e) => return Err(e.to_string()),
         }
-        println!("Rows: {:?}", results);
+    }
 
-        Ok(results)
+    /// Executes a generated statement, sending its values as statement arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - The statement and the values bound to its placeholders.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<libsql_client::ResultSet, KitError>` - The result set of the statement.
+    fn execute(&mut self, statement: &SqlStatement) -> Result<libsql_client::ResultSet, KitError> {
+        let data = self
+            .libsqlite_conn
+            .execute(libsql_statement(statement))
+            .map_err(|e| query_error(e, &statement.sql))?;
+        self.tally(&data);
+        Ok(data)
     }
 
-    /// Gets data from a specific table in the SQLite database.
+    /// Counts the result of a statement in `total_changes` if it may have changed rows: it
+    /// changed some, or it returned no columns, as every statement but a query does.
     ///
     /// # Arguments
     ///
-    /// * `table_name` - The name of the table.
+    /// * `data` - The result set of the statement.
+    fn tally(&mut self, data: &libsql_client::ResultSet) {
+        if data.rows_affected > 0 || data.columns.is_empty() {
+            self.total_changes += 1;
+        }
+    }
+
+    /// Executes a generated statement that changes rows, or queues it while edits are staged.
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - The statement and the values bound to its placeholders.
+    /// * `inserted` - Whether the statement inserts a row, so its rowid is reported.
     ///
     /// # Returns
     ///
-    /// * `Result<TableRequest, String>` - A `Result` containing a `TableRequest` if successful, or an error message if not.
-    fn get_table_data(&mut self, table_name: &str) -> Result<TableRequest, String> {
-        println!("Getting libsql table data for: {:?}", table_name);
-        let query = format!("SELECT * FROM {}", table_name);
-        let result = self.libsqlite_conn.execute(query);
-        match result {
-            Ok(data) => {
-                let mut column_names = Vec::new();
-                let mut rows = Vec::new();
-                if let Some(first_row) = data.rows.first() {
-                    column_names = first_row
-                        .value_map
-                        .keys()
-                        .map(|key| ColumnInfo {
-                            name: key.to_string(),
-                            type_name: "TEXT".to_string(),
-                        })
-                        .collect();
-                }
-                for row in data.rows {
-                    let mut td = Vec::new();
-                    for col in &column_names {
-                        let value = row.value_map.get(&col.name).unwrap();
-                        td.push(SerializableValue::Text(value.to_string()));
-                    }
-                    rows.push(td);
-                }
+    /// * `Result<WriteResult, KitError>` - The effect of the statement, or a `staged` result if it was queued.
+    fn write(&mut self, statement: SqlStatement, inserted: bool) -> Result<WriteResult, KitError> {
+        if let Some(staged) = self.staged.as_mut() {
+            staged.push(statement);
+            return Ok(WriteResult {
+                staged: true,
+                ..WriteResult::default()
+            });
+        }
+        let data = self.execute(&statement)?;
+        Ok(write_result(data, inserted))
+    }
+}
 
-                // ensure that id column is always first
-                let mut id_column_index = 0;
-                for (i, col) in column_names.iter().enumerate() {
-                    if col.name == "id" {
-                        id_column_index = i;
-                        break;
-                    }
-                }
+/// `ClientFailure` is an enum that tells where an error returned by the libsql client came from.
+enum ClientFailure {
+    /// The request could not be sent, or its response could not be read.
+    Transport,
+    /// The server refused the request with this HTTP status.
+    Status(StatusCode),
+    /// The server ran the request and returned an error for a statement.
+    Statement,
+}
 
-                if id_column_index != 0 {
-                    let id_column = column_names.remove(id_column_index);
-                    column_names.insert(0, id_column);
-                    for row in &mut rows {
-                        let id_value = row.remove(id_column_index);
-                        row.insert(0, id_value);
-                    }
-                }
+/// Tells where an error returned by the libsql client came from.
+///
+/// The client wraps every error in an `anyhow::Error`, but keeps the `reqwest`, `serde_json` and
+/// I/O errors of the transport as its cause. A request the server refused is reported with its
+/// HTTP status alone, e.g. `401 Unauthorized`. Any other error was returned by the server.
+fn client_failure(e: &(dyn Error + 'static)) -> ClientFailure {
+    let mut cause = Some(e);
+    while let Some(error) = cause {
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            return match error.status() {
+                Some(status) => ClientFailure::Status(status),
+                None => ClientFailure::Transport,
+            };
+        }
+        if error.is::<serde_json::Error>() || error.is::<std::io::Error>() {
+            return ClientFailure::Transport;
+        }
+        cause = error.source();
+    }
+    let message = e.to_string();
+    let status = message
+        .split_once(' ')
+        .and_then(|(code, _)| code.parse::<StatusCode>().ok());
+    match status {
+        Some(status) if status.to_string() == message => ClientFailure::Status(status),
+        _ => ClientFailure::Statement,
+    }
+}
 
-                Ok(TableRequest {
-                    column_names,
-                    rows,
-                    row_count: 0,
-                })
+/// Classifies an error returned by the libsql client while connecting.
+///
+/// A request refused with `401 Unauthorized` or `403 Forbidden` means the auth token was rejected.
+fn remote_error(url: &str, e: impl AsRef<dyn Error + Send + Sync>) -> ConnectionError {
+    let e = e.as_ref();
+    match client_failure(e) {
+        ClientFailure::Status(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            ConnectionError::AuthFailure {
+                url: url.to_string(),
+                message: e.to_string(),
             }
-            Err(e) => Err(e.to_string()),
         }
+        _ => ConnectionError::Failed {
+            location: url.to_string(),
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Converts an error returned by the libsql client for the statement `sql` into a `KitError`.
+///
+/// Errors the server returned for the statement are SQL errors; errors of the transport and
+/// requests the server refused are network errors.
+fn query_error(e: impl AsRef<dyn Error + Send + Sync>, sql: &str) -> KitError {
+    let e = e.as_ref();
+    let error = SqlError {
+        backend: Backend::Libsql,
+        extended_code: None,
+        message: e.to_string(),
+        sql: Some(sql.to_string()),
+        offset: None,
+    };
+    match client_failure(e) {
+        ClientFailure::Statement => KitError::from_sql_error(error),
+        ClientFailure::Transport | ClientFailure::Status(_) => KitError::Network(error),
+    }
+}
+
+/// Implementation of `DbManagerTrait` for `LibsqlDbManager`.
+impl DbManagerTrait for LibsqlDbManager {
+    /// Gets one filtered and sorted page of data from a specific table in the SQLite database.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `query` - The page window, filters and sort order to apply.
+    /// * `key` - The key of the table, selected after the table columns if given.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TableRequest, KitError>` - A `Result` containing a `TableRequest` if successful, or the error if not.
+    fn get_table_data(
+        &mut self,
+        table_name: &str,
+        query: &TableQuery,
+        key: Option<&KeyShape>,
+    ) -> Result<TableRequest, KitError> {
+        println!("Getting libsql table data for: {:?}", table_name);
+        // result sets carry no declared types, so they are looked up from the table definition
+        let (table_info, table_info_args) =
+            pragma_call("table_xinfo", query.schema.as_deref(), table_name);
+        let declared: HashMap<String, String> = self
+            .query_rows(
+                &format!("SELECT name, type FROM {}", table_info),
+                &table_info_args,
+            )?
+            .1
+            .into_iter()
+            .filter_map(|row| match (row.first(), row.get(1)) {
+                (Some(SerializableValue::Text(name)), Some(SerializableValue::Text(decl))) => {
+                    Some((name.clone(), decl.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        let page = sql::select_page(table_name, query, key)?;
+        let (names, rows) = result_rows(self.execute(&page)?);
+        let declared_types: Vec<Option<String>> = names
+            .iter()
+            .map(|name| declared.get(name).cloned())
+            .collect();
+        let column_names = column_infos(&names, &declared_types, &rows);
+
+        // the row count is filled in by the caller, which knows whether counting is worth it
+        Ok(TableRequest {
+            column_names,
+            rows,
+            row_count: 0,
+            row_count_kind: RowCountKind::AtLeast,
+            offset: query.offset,
+            limit: query.limit,
+            row_keys: vec![],
+            elapsed_ms: None,
+            stats: None,
+        })
     }
 
     /// Removes a specific row from a table in the SQLite database.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - The name of the table.
-    /// * `col_name` - The name of the column.
-    /// * `row_id` - The ID of the row.
+    /// * `key` - The key of the row.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - A `Result` containing a success message if successful, or an error message if not.
+    /// * `Result<WriteResult, KitError>` - A `Result` containing the number of rows removed if successful, or the error if not.
     fn remove_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
-        col_name: &str,
-        row_id: i64,
-    ) -> Result<String, String> {
-        let sql = format!("DELETE FROM {} WHERE {} = {}", table_name, col_name, row_id);
-        match self.libsqlite_conn.execute(sql) {
-            Ok(_) => Ok("Row removed successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+        key: &RowKey,
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::delete_row(schema, table_name, key)?;
+        self.write(statement, false)
     }
 
     /// Inserts a new row into a table in the SQLite database.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - The name of the table.
+    /// * `columns` - The columns the values are given for, or an empty slice for every column in order.
     /// * `row` - A `Vec` of `SerializableValue`s representing the row.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - A `Result` containing a success message if successful, or an error message if not.
+    /// * `Result<WriteResult, KitError>` - A `Result` containing the number of rows inserted and the rowid of the new row if successful, or the error if not.
     fn insert_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
+        columns: &[String],
         row: Vec<SerializableValue>,
-    ) -> Result<String, String> {
-        let mut sql = format!("INSERT INTO {} VALUES (", table_name);
-        for (i, value) in row.iter().enumerate() {
-            match value {
-                SerializableValue::Text(text) => {
-                    sql.push_str(&format!("'{}'", text));
-                }
-                SerializableValue::Blob(_blob) => {
-                    sql.push_str(&format!("'{}'", "blob"));
-                }
-                SerializableValue::Null => {
-                    sql.push_str(&format!("'{}'", "null"));
-                }
-                SerializableValue::Integer(int) => {
-                    sql.push_str(&format!("{}", int));
-                }
-                SerializableValue::Real(real) => {
-                    sql.push_str(&format!("{}", real));
-                }
-            }
-            if i < row.len() - 1 {
-                sql.push_str(", ");
-            }
-        }
-        sql.push(')');
-        match self.libsqlite_conn.execute(sql) {
-            Ok(_) => Ok("Row inserted successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::insert_row(schema, table_name, columns, row)?;
+        self.write(statement, true)
     }
 
     /// Updates a specific row in a table in the SQLite database.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - The name of the table.
     /// * `col_name` - The name of the column.
-    /// * `index_col_name` - The name of the index column.
-    /// * `id` - The ID of the row.
+    /// * `key` - The key of the row.
     /// * `value` - A `SerializableValue` representing the new value.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - A `Result` containing a success message if successful, or an error message if not.
+    /// * `Result<WriteResult, KitError>` - A `Result` containing the number of rows updated if successful, or the error if not.
     fn update_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
         col_name: &str,
-        _index_col_name: &str,
-        id: i64,
+        key: &RowKey,
         value: SerializableValue,
-    ) -> Result<String, String> {
-        let mut sql = format!("UPDATE {} SET ", table_name);
-        match value {
-            SerializableValue::Text(text) => {
-                sql.push_str(&format!("{} = '{}'", col_name, text));
-            }
-            SerializableValue::Blob(_blob) => {
-                sql.push_str(&format!("{} = '{}'", col_name, "blob"));
-            }
-            SerializableValue::Null => {
-                sql.push_str(&format!("{} = '{}'", col_name, "null"));
-            }
-            SerializableValue::Integer(int) => {
-                sql.push_str(&format!("{} = {}", col_name, int));
-            }
-            SerializableValue::Real(real) => {
-                sql.push_str(&format!("{} = {}", col_name, real));
-            }
-        }
-        sql.push_str(&format!(" WHERE id = {}", id));
-        match self.libsqlite_conn.execute(sql) {
-            Ok(_) => Ok("Row updated successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::update_row(schema, table_name, col_name, key, value)?;
+        self.write(statement, false)
     }
 
     /// Runs a query on the SQLite database.
@@ -249,60 +579,362 @@ impl DbManagerTrait for LibsqlDbManager {
     /// # Arguments
     ///
     /// * `query` - The query to run.
+    /// * `params` - The values bound to the parameters of the query, one per parameter index.
+    /// * `control` - Abandons the request when the query is cancelled or times out.
     ///
     /// # Returns
     ///
-    /// * `Result<TableRequest, String>` - A `Result` containing a `TableRequest` if successful, or an error message if not.    
-    fn run_query(&mut self, query: &str) -> Result<TableRequest, String> {
-        let result = self.libsqlite_conn.execute(query.to_string());
+    /// * `Result<TableRequest, KitError>` - A `Result` containing a `TableRequest` if successful, or the error if not.
+    fn run_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+    ) -> Result<TableRequest, KitError> {
+        let args: Vec<libsql_client::Value> = params
+            .iter()
+            .cloned()
+            .map(libsql_client::Value::from)
+            .collect();
+        let statement = libsql_client::Statement::with_args(query, &args);
+        let result = self.send(query, control, move |client| client.execute(statement));
         match result {
             Ok(data) => {
-                let mut column_names = Vec::new();
-                let mut rows = Vec::new();
-                if let Some(first_row) = data.rows.first() {
-                    column_names = first_row
-                        .value_map
-                        .keys()
-                        .map(|key| ColumnInfo {
-                            name: key.to_string(),
-                            type_name: "TEXT".to_string(),
-                        })
-                        .collect();
-                }
-                for row in data.rows {
-                    let mut td = Vec::new();
-                    for col in &column_names {
-                        let value = row.value_map.get(&col.name).unwrap();
-                        td.push(SerializableValue::Text(value.to_string()));
-                    }
-                    rows.push(td);
-                }
-
-                // ensure that id column is always first
-                let mut id_column_index = 0;
-                for (i, col) in column_names.iter().enumerate() {
-                    if col.name == "id" {
-                        id_column_index = i;
-                        break;
-                    }
-                }
-
-                if id_column_index != 0 {
-                    let id_column = column_names.remove(id_column_index);
-                    column_names.insert(0, id_column);
-                    for row in &mut rows {
-                        let id_value = row.remove(id_column_index);
-                        row.insert(0, id_value);
-                    }
-                }
+                self.tally(&data);
+                let (names, rows) = result_rows(data);
+                let column_names = column_infos(&names, &[], &rows);
 
                 Ok(TableRequest {
                     column_names,
+                    offset: 0,
+                    limit: rows.len() as i64,
+                    row_count: rows.len() as i64,
+                    row_count_kind: RowCountKind::Exact,
                     rows,
-                    row_count: 0,
+                    row_keys: vec![],
+                    elapsed_ms: None,
+                    stats: None,
+                })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Runs a query and hands its rows to `sink` one at a time.
+    ///
+    /// The server sends the whole result in one response, so the rows are held in memory until
+    /// `sink` has taken them; they are still handed out one at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to run.
+    /// * `params` - The values bound to the parameters of the query, one per parameter index.
+    /// * `control` - Abandons the request when the query is cancelled or times out.
+    /// * `sink` - Receives the columns, then each row; no more rows are handed out once it returns `false`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if the query failed.
+    fn stream_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+        sink: &mut StreamSink,
+    ) -> Result<(), KitError> {
+        let args: Vec<libsql_client::Value> = params
+            .iter()
+            .cloned()
+            .map(libsql_client::Value::from)
+            .collect();
+        let statement = libsql_client::Statement::with_args(query, &args);
+        let data = self.send(query, control, move |client| client.execute(statement))?;
+        self.tally(&data);
+        let (names, rows) = result_rows(data);
+        if !sink(StreamItem::Columns(column_infos(&names, &[], &rows))) {
+            return Ok(());
+        }
+        for row in rows {
+            if !sink(StreamItem::Row(row)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the statements of a script in order and stops at the first one that fails.
+    ///
+    /// In a transaction the statements are sent one request at a time in an interactive
+    /// transaction, which is rolled back when one fails. When the script is cancelled, the
+    /// transaction is rolled back before its next statement, but it is not waited for, so the
+    /// result does not report it as rolled back.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements to run.
+    /// * `transaction` - Whether to roll every statement back if one fails.
+    /// * `control` - Abandons the running request when the script is cancelled or times out.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ScriptResult, KitError>` - The result of each statement that ran.
+    fn run_script(
+        &mut self,
+        statements: &[&str],
+        transaction: bool,
+        control: &QueryControl,
+    ) -> Result<ScriptResult, KitError> {
+        let mut result = ScriptResult::default();
+        if transaction {
+            let batch: Vec<SqlStatement> = statements
+                .iter()
+                .map(|sql| SqlStatement {
+                    sql: sql.to_string(),
+                    params: vec![],
                 })
+                .collect();
+            let sql = statements.join(";\n");
+            let stop = control.clone();
+            let response = self.send(&sql, control, move |client| {
+                Ok::<_, Box<dyn Error + Send + Sync>>(run_transaction(client, &batch, &stop))
+            });
+            let results = match response {
+                Ok(outcome) => {
+                    if !outcome.rolled_back {
+                        outcome
+                            .results
+                            .iter()
+                            .for_each(|(data, _)| self.tally(data));
+                    }
+                    result.error = outcome.error;
+                    result.rolled_back = outcome.rolled_back;
+                    outcome.results
+                }
+                Err(error) => {
+                    result.error = Some(ScriptError { index: None, error });
+                    vec![]
+                }
+            };
+            result.statements = statements
+                .iter()
+                .zip(results)
+                .map(|(sql, (data, elapsed))| statement_result(sql, data, Some(elapsed)))
+                .collect();
+            return Ok(result);
+        }
+        for (index, sql) in statements.iter().enumerate() {
+            let started = Instant::now();
+            let statement = libsql_client::Statement::from(*sql);
+            match self.send(sql, control, move |client| client.execute(statement)) {
+                Ok(data) => {
+                    self.tally(&data);
+                    result
+                        .statements
+                        .push(statement_result(sql, data, Some(elapsed_ms(started))))
+                }
+                Err(error) => {
+                    result.error = Some(ScriptError {
+                        index: Some(index),
+                        error,
+                    });
+                    break;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the names of the parameters of a statement.
+    ///
+    /// The server cannot prepare a statement without running it, so the parameters are
+    /// read from the SQL text, numbered the way SQLite numbers them.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The statement to inspect.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Option<String>>, KitError>` - The name of each parameter in index order, `None` for anonymous parameters.
+    fn parameter_names(&mut self, sql: &str) -> Result<Vec<Option<String>>, KitError> {
+        Ok(lexer::parameter_names(sql))
+    }
+
+    /// Runs a single statement with bound parameters on the SQLite database.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The statement to run.
+    /// * `params` - The values bound to the placeholders of the statement, in order.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ResultRows, KitError>` - A `Result` containing the column names and rows if successful, or the error if not.
+    fn query_rows(
+        &mut self,
+        sql: &str,
+        params: &[SerializableValue],
+    ) -> Result<ResultRows, KitError> {
+        let args: Vec<libsql_client::Value> = params
+            .iter()
+            .cloned()
+            .map(libsql_client::Value::from)
+            .collect();
+        match self
+            .libsqlite_conn
+            .execute(libsql_client::Statement::with_args(sql, &args))
+        {
+            Ok(data) => {
+                self.tally(&data);
+                Ok(result_rows(data))
+            }
+            Err(e) => Err(query_error(e, sql)),
+        }
+    }
+
+    /// Starts queueing edits, so they only take effect once committed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - Always `Ok`; nothing is sent to the server until the commit.
+    fn begin_edits(&mut self) -> Result<(), KitError> {
+        self.staged = Some(vec![]);
+        Ok(())
+    }
+
+    /// Sends the queued edits in one interactive transaction, which is rolled back if an edit
+    /// fails.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - The error of the edit that failed, or of the commit. None of the
+    ///   edits took effect and they stay queued.
+    fn commit_edits(&mut self) -> Result<(), KitError> {
+        let staged = self.staged.take().unwrap_or_default();
+        if staged.is_empty() {
+            return Ok(());
+        }
+        let outcome = run_transaction(&self.libsqlite_conn, &staged, &QueryControl::default());
+        match outcome.error {
+            None => {
+                outcome
+                    .results
+                    .iter()
+                    .for_each(|(data, _)| self.tally(data));
+                Ok(())
+            }
+            Some(failure) => {
+                self.staged = Some(staged);
+                Err(failure.error)
+            }
+        }
+    }
+
+    /// Discards the queued edits.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - Always `Ok`; nothing was sent to the server.
+    fn rollback_edits(&mut self) -> Result<(), KitError> {
+        self.staged = None;
+        Ok(())
+    }
+
+    /// Shares the client with a second manager, whose requests are sent independently.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Box<dyn DbManagerTrait + Send>>, ConnectionError>` - The new manager.
+    fn open_reader(&self) -> Result<Option<Box<dyn DbManagerTrait + Send>>, ConnectionError> {
+        Ok(Some(Box::new(LibsqlDbManager {
+            libsqlite_conn: self.libsqlite_conn.clone(),
+            staged: None,
+            total_changes: 0,
+        })))
+    }
+
+    /// Inserts rows into a table with batch requests. The server runs the statements of a batch
+    /// one after the other, each in its own transaction, and reports the error of each one that
+    /// fails, so every row is inserted or fails on its own and no row is sent twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `columns` - The columns the values are given for.
+    /// * `rows` - The rows to insert, with one value per column.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<(usize, KitError)>, KitError>` - The index and error of each row that failed.
+    ///   A request that fails as a whole, such as on a network error, stops the insertion and is
+    ///   returned instead; the rows it sent may have been inserted in part.
+    fn insert_rows(
+        &mut self,
+        table_name: &str,
+        columns: &[String],
+        rows: &[Vec<SerializableValue>],
+    ) -> Result<Vec<(usize, KitError)>, KitError> {
+        let mut failed = Vec::new();
+        let mut pending = Vec::with_capacity(rows.len());
+        for (index, row) in rows.iter().enumerate() {
+            match sql::insert_row(None, table_name, columns, row.clone()) {
+                Ok(statement) => pending.push((index, statement)),
+                Err(e) => failed.push((index, e)),
             }
-            Err(e) => Err(e.to_string()),
         }
+        if !pending.is_empty() {
+            self.total_changes += 1;
+        }
+        let mut next = 0;
+        while next < pending.len() {
+            let batch: Vec<libsql_client::Statement> = pending[next..]
+                .iter()
+                .map(|(_, statement)| libsql_statement(statement))
+                .collect();
+            let result = self
+                .libsqlite_conn
+                .raw_batch(batch)
+                .map_err(|e| query_error(e, &pending[next].1.sql))?;
+            // a server may stop at a statement it cannot run, so the ones after it are sent again
+            let ran = result.step_errors.len();
+            if ran == 0 {
+                return Err(KitError::invalid_state(
+                    "the server ran none of the rows of the batch",
+                ));
+            }
+            for (offset, error) in result.step_errors.into_iter().enumerate() {
+                if let Some(error) = error {
+                    let (index, statement) = &pending[next + offset];
+                    let error: Box<dyn Error + Send + Sync> = error.message.into();
+                    failed.push((*index, query_error(error, &statement.sql)));
+                }
+            }
+            next += ran;
+        }
+        failed.sort_by_key(|(index, _)| *index);
+        Ok(failed)
+    }
+
+    /// Returns the number of statements sent by this manager that may have changed rows. The
+    /// server cannot tell the changes of one client apart, so they are counted as they return.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u64, KitError>` - Always `Ok` with the count.
+    fn total_changes(&mut self) -> Result<u64, KitError> {
+        Ok(self.total_changes)
+    }
+
+    /// libsql servers do not report which rows their transactions change.
+    ///
+    /// # Arguments
+    ///
+    /// * `_listener` - The listener, which is dropped.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Always `false`.
+    fn watch_changes(&mut self, _listener: CommitListener) -> bool {
+        false
     }
 }
@@ -1,20 +1,54 @@
-use db_manager::DbManager;
-use rusqlite::{types::Value, Result};
+use changes::{ChangeEvent, ChangeListener};
+use connections::{Connection, ConnectionId, ConnectionInfo, ConnectionRegistry, StatusListener};
+use cursors::{Cursor, CursorBatch, CursorId, CursorRegistry, OpenedCursor};
+use db_manager::explain::QueryPlan;
+use db_manager::journal::JournalEntry;
+use db_manager::{
+    Filter, OrderBy, PendingEdit, QueryParams, QueryStats, RowKey, ScriptResult, TableQuery,
+    WriteResult,
+};
+use dump::{DumpProgress, DumpSummary, RestoreProgress, RestoreSummary};
+use error::KitError;
+use export::{ExportFormat, ExportOptions, ExportProgress, ExportSource, ExportSummary};
+use history::{HistoryEntry, NewHistoryEntry};
+use import::{ImportOptions, ImportPreview, ImportProgress, ImportSummary, ImportTarget};
+use queries::{QueryControl, QueryId, RunningQueries};
+use row_counts::RowCountKind;
+use rusqlite::types::{ToSql, ToSqlOutput, Value};
+use rusqlite::Result;
+use saved_queries::{SavedQuery, SavedQueryInput};
+use schema::{SchemaObject, TableSchema};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::sync::Arc;
-use std::time::Duration;
-use std::{collections::HashMap, sync::Mutex};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use store::AppStore;
 use tauri::{Manager, PhysicalSize, Size, State};
 use window_shadows::set_shadow;
 
+mod changes;
+mod connections;
+mod cursors;
 mod db_manager;
+mod dump;
+mod error;
+mod export;
+mod history;
+mod import;
 mod libsql;
 mod native;
+mod queries;
+mod row_counts;
+mod saved_queries;
+mod schema;
+mod store;
+mod worker;
 
 /// SerializableValue is an enum that represents a value that can be serialized.
 /// It can be one of five types: Null, Integer, Real, Text, or Blob.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum SerializableValue {
     Null,
     Integer(i64),
@@ -36,6 +70,19 @@ impl From<Value> for SerializableValue {
     }
 }
 
+/// This implementation allows a SerializableValue to be bound as a query parameter.
+impl ToSql for SerializableValue {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            SerializableValue::Null => ToSqlOutput::Owned(Value::Null),
+            SerializableValue::Integer(i) => ToSqlOutput::from(*i),
+            SerializableValue::Real(f) => ToSqlOutput::from(*f),
+            SerializableValue::Text(s) => ToSqlOutput::from(s.as_str()),
+            SerializableValue::Blob(b) => ToSqlOutput::from(b.as_slice()),
+        })
+    }
+}
+
 /// ColumnInfo is a struct that represents information about a column in a database.
 /// It contains the name of the column and the type of the column.
 #[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone)]
@@ -45,239 +92,1674 @@ struct ColumnInfo {
 }
 
 /// ConnectionResponse is a struct that represents the response from a connection to a database.
-/// It contains a list of tables, a list of column names, a list of preview rows, and a row count.
+/// It contains the id of the new connection, the names of the browsable tables and views of the main schema, the catalog of
+/// all schema objects, a list of column names, a list of preview rows and their keys, and a row count and how exact it is.
 #[derive(Serialize, Debug)]
 struct ConnectionResponse {
+    connection_id: ConnectionId,
     tables: Vec<String>,
+    objects: Vec<SchemaObject>,
     column_names: Vec<ColumnInfo>,
     preview_rows: Vec<Vec<SerializableValue>>,
+    preview_row_keys: Vec<Option<RowKey>>,
     row_count: i64,
+    row_count_kind: RowCountKind,
 }
 
 /// This implementation allows for the creation of a default ConnectionResponse.
 impl Default for ConnectionResponse {
     fn default() -> Self {
         ConnectionResponse {
+            connection_id: 0,
             tables: vec![],
+            objects: vec![],
             column_names: vec![],
             preview_rows: vec![],
+            preview_row_keys: vec![],
             row_count: 0,
+            row_count_kind: RowCountKind::default(),
         }
     }
 }
 
+/// The number of rows fetched per page when the caller does not ask for a specific limit.
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+
 /// TableRequest is a struct that represents a request for a table from a database.
-/// It contains a list of column names, a list of rows, and a row count.
+/// It contains a list of column names, a list of rows, a row count and how exact it is, and the
+/// page window (`offset` and `limit`) that the rows were fetched with.
+/// Rows fetched from a table carry their key in `row_keys`, `None` for rows that cannot be
+/// identified; it is empty for views and query results.
+/// Query results also carry how long the query took and, on SQLite files, its statement counters.
 #[derive(Serialize)]
 pub struct TableRequest {
     column_names: Vec<ColumnInfo>,
     rows: Vec<Vec<SerializableValue>>,
+    row_keys: Vec<Option<RowKey>>,
     row_count: i64,
+    row_count_kind: RowCountKind,
+    offset: i64,
+    limit: i64,
+    elapsed_ms: Option<f64>,
+    stats: Option<QueryStats>,
 }
 
 /// AppState is a struct that represents the state of the application.
-/// It contains a database manager and a list of callbacks.
+/// It contains the registry of open connections, the queries that can be cancelled, the open cursors
+/// and the event bus.
 struct AppState {
-    db: Mutex<DbManager>,
-    callbacks: Arc<Mutex<HashMap<String, Box<dyn FnMut(String) + Send>>>>,
+    connections: Mutex<ConnectionRegistry>,
+    cursors: Mutex<CursorRegistry>,
+    /// The running queries have their own lock, so they can be cancelled without waiting for the connection.
+    queries: RunningQueries,
+    /// Shared with the connections' workers and the periodic loop, which send events from their own threads.
+    events: Arc<EventBus>,
+}
+
+/// EventTopic is an enum of the kinds of events a window can subscribe to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+enum EventTopic {
+    /// `rows-changed` and `database-changed` events.
+    DataChanged,
+    /// `query-progress` events.
+    QueryProgress,
+    /// `connection-state` events.
+    ConnectionState,
+    /// `export-progress` events.
+    ExportProgress,
+    /// `import-progress` events.
+    ImportProgress,
+    /// `dump-progress` and `restore-progress` events.
+    DumpProgress,
+    /// `mac-address` events, sent every second.
+    MacAddress,
+    /// `free-trial-popup` events, sent when the free trial pop-up is due.
+    FreeTrialPopup,
+}
+
+/// QueryStatus is an enum that tells where a query stands.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum QueryStatus {
+    Running,
+    Finished,
+    Failed,
+    /// The query was cancelled or timed out.
+    Interrupted,
+}
+
+/// QueryProgress is the payload of the `query-progress` event, sent when a query or script
+/// started with an id starts and when it ends.
+#[derive(Serialize, Debug, Clone)]
+struct QueryProgress {
+    query_id: QueryId,
+    connection_id: ConnectionId,
+    status: QueryStatus,
+    /// How long the query took, once it has ended.
+    elapsed_ms: Option<f64>,
+}
+
+/// ConnectionState is the payload of the `connection-state` event, sent when a connection is
+/// opened or closed and when its editing state changes.
+#[derive(Serialize, Debug, Clone)]
+struct ConnectionState {
+    #[serde(flatten)]
+    connection: ConnectionInfo,
+    /// Whether the connection is open; `false` once it was closed.
+    open: bool,
+}
+
+/// AppEvent is an enum that holds an event sent to the windows, with its typed payload.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+enum AppEvent {
+    DataChanged(ChangeEvent),
+    QueryProgress(QueryProgress),
+    ConnectionState(ConnectionState),
+    ExportProgress(ExportProgress),
+    ImportProgress(ImportProgress),
+    DumpProgress(DumpProgress),
+    RestoreProgress(RestoreProgress),
+    /// The MAC address of the machine.
+    MacAddress(String),
+    /// The time the pop-up is due, in seconds since the UNIX epoch.
+    FreeTrialPopup(u64),
+}
+
+/// This implementation names the events and sorts them into topics.
+impl AppEvent {
+    /// Returns the topic a window subscribes to for the event.
+    fn topic(&self) -> EventTopic {
+        match self {
+            AppEvent::DataChanged(_) => EventTopic::DataChanged,
+            AppEvent::QueryProgress(_) => EventTopic::QueryProgress,
+            AppEvent::ConnectionState(_) => EventTopic::ConnectionState,
+            AppEvent::ExportProgress(_) => EventTopic::ExportProgress,
+            AppEvent::ImportProgress(_) => EventTopic::ImportProgress,
+            AppEvent::DumpProgress(_) | AppEvent::RestoreProgress(_) => EventTopic::DumpProgress,
+            AppEvent::MacAddress(_) => EventTopic::MacAddress,
+            AppEvent::FreeTrialPopup(_) => EventTopic::FreeTrialPopup,
+        }
+    }
+
+    /// Returns the name the event is emitted as.
+    fn name(&self) -> &'static str {
+        match self {
+            AppEvent::DataChanged(event) => event.name(),
+            AppEvent::QueryProgress(_) => "query-progress",
+            AppEvent::ConnectionState(_) => "connection-state",
+            AppEvent::ExportProgress(_) => "export-progress",
+            AppEvent::ImportProgress(_) => "import-progress",
+            AppEvent::DumpProgress(_) => "dump-progress",
+            AppEvent::RestoreProgress(_) => "restore-progress",
+            AppEvent::MacAddress(_) => "mac-address",
+            AppEvent::FreeTrialPopup(_) => "free-trial-popup",
+        }
+    }
+}
+
+/// EventBus is a struct that sends events to the windows subscribed to their topic.
+///
+/// Events can be sent from any thread. Sending never fails: errors are printed, and windows
+/// that were closed are unsubscribed. Events sent before the application has started are dropped.
+#[derive(Default)]
+struct EventBus {
+    app: Mutex<Option<tauri::AppHandle>>,
+    /// The topics each window is subscribed to, keyed by window label.
+    subscriptions: Mutex<HashMap<String, HashSet<EventTopic>>>,
+}
+
+/// This implementation manages the subscriptions and sends the events.
+impl EventBus {
+    /// Sets the handle events are sent with, once the application has started.
+    fn attach(&self, app: tauri::AppHandle) {
+        *lock(&self.app) = Some(app);
+    }
+
+    /// Subscribes a window to topics, in addition to those it is subscribed to already.
+    fn subscribe(&self, window: &str, topics: &[EventTopic]) {
+        lock(&self.subscriptions)
+            .entry(window.to_string())
+            .or_default()
+            .extend(topics.iter().copied());
+    }
+
+    /// Unsubscribes a window from topics, or from every topic when `topics` is `None`.
+    fn unsubscribe(&self, window: &str, topics: Option<&[EventTopic]>) {
+        let mut subscriptions = lock(&self.subscriptions);
+        if let (Some(topics), Some(subscribed)) = (topics, subscriptions.get_mut(window)) {
+            subscribed.retain(|topic| !topics.contains(topic));
+        }
+        if topics.is_none() || subscriptions.get(window).is_some_and(HashSet::is_empty) {
+            subscriptions.remove(window);
+        }
+    }
+
+    /// Returns whether any window is subscribed to a topic.
+    fn has_subscribers(&self, topic: EventTopic) -> bool {
+        lock(&self.subscriptions)
+            .values()
+            .any(|topics| topics.contains(&topic))
+    }
+
+    /// Sends an event to every window subscribed to its topic.
+    fn emit(&self, event: AppEvent) {
+        let app = match lock(&self.app).clone() {
+            Some(app) => app,
+            None => return,
+        };
+        let topic = event.topic();
+        let windows: Vec<String> = lock(&self.subscriptions)
+            .iter()
+            .filter(|(_, topics)| topics.contains(&topic))
+            .map(|(window, _)| window.clone())
+            .collect();
+        for label in windows {
+            match app.get_window(&label) {
+                Some(window) => {
+                    if let Err(e) = window.emit(event.name(), &event) {
+                        println!(
+                            "[events] could not send {} to {}: {}",
+                            event.name(),
+                            label,
+                            e
+                        );
+                    }
+                }
+                None => self.unsubscribe(&label, None),
+            }
+        }
+    }
+}
+
+/// Locks a mutex of the event bus. A thread that panicked while holding it cannot have left
+/// it half updated, so the poisoning is ignored rather than spreading the panic.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-/// Connects to the database at the given path and returns a `ConnectionResponse`.
+/// Opens a new connection to the database at the given path and returns a `ConnectionResponse`.
+///
+/// This function connects to the database on a blocking thread and adds the connection to the
+/// `AppState`'s connection registry. Connections that are already open stay open. If the connection
+/// is successful, it fetches all schema objects from the database and populates the `ConnectionResponse` with the id of the new connection
+/// and the data from the first table or view of the main schema, if any.
 ///
-/// This function locks the `AppState`'s database manager and attempts to connect to the database.
-/// If the connection is successful, it fetches all tables from the database and populates the `ConnectionResponse`
-/// with the data from the first table, if any.
+/// The changes made to the database are then sent to the windows subscribed to `data_changed`:
+/// a `rows-changed` event carrying a `RowsChanged` for each transaction committed through the
+/// connection, and a `database-changed` event carrying a `DatabaseChanged` when another process
+/// writes to the database file. A `connection-state` event is sent once the connection is open
+/// and whenever its editing state changes.
 ///
 /// # Arguments
 ///
 /// * `path` - A string slice that holds the path to the database.
-/// * `state` - The `AppState` containing the database manager.
+/// * `create` - Whether to create a new database if the file does not exist. Defaults to `false`.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
 ///
 /// # Returns
 ///
 /// * `Ok(ConnectionResponse)` - If the connection is successful.
-/// * `Err(String)` - If the connection fails, with the error message.
-#[tauri::command]
-fn connect_to_db(path: String, state: State<'_, AppState>) -> Result<ConnectionResponse, String> {
-    let mut db_manager: std::sync::MutexGuard<'_, DbManager> = state.db.lock().unwrap();
-    match db_manager.connect_to_db(&path) {
-        Ok(_) => {
-            let tables = db_manager.get_all_tables()?;
-            let mut response = ConnectionResponse::default();
-            if !tables.is_empty() {
-                response.tables = tables.clone();
-                let table_data = db_manager.get_table_data(&tables[0])?;
-                response.column_names = table_data.column_names;
-                response.preview_rows = table_data.rows;
-                response.row_count = table_data.row_count;
-            }
-            Ok(response)
-        }
-        Err(e) => Err(e.to_string()),
+/// * `Err(KitError)` - If the connection fails, tagged with the kind of failure.
+#[tauri::command]
+async fn open_connection(
+    path: String,
+    create: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<ConnectionResponse, KitError> {
+    let connection =
+        tokio::task::spawn_blocking(move || Connection::open(&path, create.unwrap_or(false)))
+            .await
+            .map_err(|e| KitError::invalid_state(e.to_string()))??;
+    let connection_id = state.connections.lock().unwrap().insert(connection.clone());
+    let response = connection_preview(connection_id, &connection).await;
+    // a connection the UI never hears about could not be closed again
+    if response.is_err() {
+        let _ = state.connections.lock().unwrap().close(connection_id);
+        return response;
+    }
+    let events = state.events.clone();
+    let (backend, location) = connection.describe();
+    let state_changed: StatusListener = Arc::new(move |status| {
+        let connection =
+            ConnectionInfo::new(connection_id, backend.clone(), location.clone(), status);
+        events.emit(AppEvent::ConnectionState(ConnectionState {
+            connection,
+            open: true,
+        }));
+    });
+    state_changed(connection.status());
+    connection.on_status_change(state_changed);
+    let events = state.events.clone();
+    let listener: ChangeListener =
+        Arc::new(move |event: ChangeEvent| events.emit(AppEvent::DataChanged(event)));
+    // the connection is still usable without change events
+    if let Err(e) = connection.watch_changes(connection_id, listener).await {
+        println!("[changes] {:?}", e);
+    }
+    response
+}
+
+/// Builds the `ConnectionResponse` for a freshly opened connection.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the new connection.
+/// * `connection` - The new connection.
+///
+/// # Returns
+///
+/// * `Result<ConnectionResponse, KitError>` - The schema objects and a preview of the first table.
+async fn connection_preview(
+    connection_id: ConnectionId,
+    connection: &Connection,
+) -> Result<ConnectionResponse, KitError> {
+    let objects = connection
+        .read(|db_manager| db_manager.get_schema_objects())
+        .await?;
+    let tables: Vec<String> = objects
+        .iter()
+        .filter(|object| object.schema == "main" && object.is_browsable())
+        .map(|object| object.name.clone())
+        .collect();
+    let mut response = ConnectionResponse {
+        connection_id,
+        tables: tables.clone(),
+        objects,
+        ..ConnectionResponse::default()
+    };
+    if !tables.is_empty() {
+        let table_data = connection
+            .table_data(tables[0].clone(), TableQuery::default())
+            .await?;
+        response.column_names = table_data.column_names;
+        response.preview_rows = table_data.rows;
+        response.preview_row_keys = table_data.row_keys;
+        response.row_count = table_data.row_count;
+        response.row_count_kind = table_data.row_count_kind;
     }
+    Ok(response)
+}
+
+/// Closes an open connection.
+///
+/// This function locks the `AppState`'s connection registry and removes the connection from it.
+/// The underlying database handles are closed once the jobs already sent to the connection have run.
+/// The cursors opened from the connection are closed too, and a `connection-state` event is sent.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to close.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the connection was closed.
+/// * `Err(KitError)` - If there is no open connection with that id.
+#[tauri::command]
+fn close_connection(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<(), KitError> {
+    let mut connections = state.connections.lock().unwrap();
+    let (backend, location) = connections.get(connection_id)?.describe();
+    connections.close(connection_id)?;
+    state
+        .cursors
+        .lock()
+        .unwrap()
+        .close_connection(connection_id);
+    let connection = ConnectionInfo::new(connection_id, backend, location, Default::default());
+    state
+        .events
+        .emit(AppEvent::ConnectionState(ConnectionState {
+            connection,
+            open: false,
+        }));
+    Ok(())
+}
+
+/// Lists the open connections.
+///
+/// # Arguments
+///
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Vec<ConnectionInfo>` - The open connections in the order they were opened.
+#[tauri::command]
+fn list_connections(state: State<'_, AppState>) -> Vec<ConnectionInfo> {
+    let connections = state.connections.lock().unwrap();
+    connections.list()
 }
 
-/// Fetches data from the specified table and returns a `TableRequest`.
+/// Fetches a filtered and sorted page of data from the specified table and returns a `TableRequest`.
 ///
-/// This function locks the `AppState`'s database manager and fetches data from the specified table.
+/// This function fetches data from the specified table on the connection's reader, so it does not wait
+/// for a query running on the SQL console. Temporary tables are read on the writer, which owns them.
+/// The `row_count` of the result is the number of rows matching the filters when its `row_count_kind`
+/// is `exact`. Otherwise it is an estimate, or the number of rows seen so far, and the exact count
+/// is started in the background; `get_row_count` returns it once it is done.
 ///
 /// # Arguments
 ///
-/// * `table_name` - The name of the table to fetch data from.
-/// * `state` - The `AppState` containing the database manager.
+/// * `connection_id` - The id of the connection to use.
+/// * `table_name` - The name of the table or view to fetch data from.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `offset` - The number of rows to skip, defaults to `0`.
+/// * `limit` - The maximum number of rows to return, defaults to `DEFAULT_PAGE_SIZE`.
+/// * `filters` - The conditions the rows must match, combined with `AND`.
+/// * `order_by` - The columns to sort the rows by.
+/// * `state` - The `AppState` containing the connection registry.
 ///
 /// # Returns
 ///
 /// * `Ok(TableRequest)` - If the data fetch is successful.
-/// * `Err(String)` - If the data fetch fails, with the error message.
+/// * `Err(KitError)` - If the data fetch fails, with the kind of failure.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn get_table_data(
+    connection_id: ConnectionId,
+    table_name: String,
+    schema: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+    filters: Option<Vec<Filter>>,
+    order_by: Option<Vec<OrderBy>>,
+    state: State<'_, AppState>,
+) -> Result<TableRequest, KitError> {
+    let query = TableQuery {
+        schema,
+        offset: offset.unwrap_or(0),
+        limit: limit.unwrap_or(DEFAULT_PAGE_SIZE),
+        filters: filters.unwrap_or_default(),
+        order_by: order_by.unwrap_or_default(),
+    };
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection.table_data(table_name, query).await
+}
+
+/// Counts the rows of the specified table that match the filters.
+///
+/// This function returns the count cached for the connection if nothing changed since it was
+/// taken, or else counts the rows on the connection's reader and caches the count. It may take
+/// long on large tables, so `get_table_data` shows an estimate meanwhile.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `table_name` - The name of the table or view to count the rows of.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `filters` - The conditions the rows must match, combined with `AND`.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(i64)` - The number of matching rows.
+/// * `Err(KitError)` - If the count fails, with the kind of failure.
+#[tauri::command]
+async fn get_row_count(
+    connection_id: ConnectionId,
+    table_name: String,
+    schema: Option<String>,
+    filters: Option<Vec<Filter>>,
+    state: State<'_, AppState>,
+) -> Result<i64, KitError> {
+    let query = TableQuery {
+        schema,
+        filters: filters.unwrap_or_default(),
+        ..TableQuery::default()
+    };
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection.count_rows(table_name, query).await
+}
+
+/// Fetches the declared schema of the specified table and returns a `TableSchema`.
+///
+/// This function introspects the specified table on the connection's reader, or on the writer
+/// for temporary tables.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `table_name` - The name of the table to introspect.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(TableSchema)` - The columns, foreign keys and indexes of the table.
+/// * `Err(KitError)` - If the table does not exist or the introspection fails, with the kind of failure.
+#[tauri::command]
+async fn get_table_schema(
+    connection_id: ConnectionId,
+    table_name: String,
+    schema: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<TableSchema, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let table_schema = schema.clone();
+    connection
+        .read_schema(table_schema.as_deref(), move |db_manager| {
+            db_manager.get_table_schema(schema.as_deref(), &table_name)
+        })
+        .await
+}
+
+/// Fetches the catalog of schema objects and returns a list of `SchemaObject`.
+///
+/// This function runs on the connection's writer, which owns the temporary objects, and lists every
+/// table, view, virtual table, index and trigger of the main, temp and attached schemas, along with
+/// their `CREATE` statements.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(Vec<SchemaObject>)` - If the catalog could be read.
+/// * `Err(KitError)` - If reading the catalog fails, with the kind of failure.
 #[tauri::command]
-fn get_table_data(table_name: String, state: State<'_, AppState>) -> Result<TableRequest, String> {
-    let mut db_manager = state.db.lock().unwrap();
-    db_manager.get_table_data(&table_name)
+async fn get_schema_objects(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<Vec<SchemaObject>, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .read_on_writer(|db_manager| db_manager.get_schema_objects())
+        .await
 }
 
 /// Removes a row from the specified table.
 ///
-/// This function locks the `AppState`'s database manager and removes a row from the specified table.
+/// This function removes a row from the specified table on the connection's writer.
 ///
 /// # Arguments
 ///
+/// * `connection_id` - The id of the connection to use.
 /// * `table_name` - The name of the table to remove a row from.
-/// * `row_id` - The ID of the row to remove.
-/// * `col_name` - The name of the column where the row is located.
-/// * `state` - The `AppState` containing the database manager.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `row_key` - The key of the row to remove, as returned with the table data.
+/// * `state` - The `AppState` containing the connection registry.
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - If the row removal is successful.
-/// * `Err(String)` - If the row removal fails, with the error message.
+/// * `Ok(WriteResult)` - The number of rows removed.
+/// * `Err(KitError)` - If the row removal fails, with the kind of failure.
 #[tauri::command]
-fn remove_row(
+async fn remove_row(
+    connection_id: ConnectionId,
     table_name: String,
-    row_id: i64,
-    col_name: String,
+    schema: Option<String>,
+    row_key: RowKey,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    let mut db_manager = state.db.lock().unwrap();
-    db_manager.remove_row(&table_name, &col_name, row_id)
+) -> Result<WriteResult, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(move |db_manager| db_manager.remove_row(schema.as_deref(), &table_name, &row_key))
+        .await
 }
 
 /// Inserts a row into the specified table.
 ///
-/// This function locks the `AppState`'s database manager and inserts a row into the specified table.
+/// This function inserts a row into the specified table on the connection's writer.
 ///
 /// # Arguments
 ///
+/// * `connection_id` - The id of the connection to use.
 /// * `table_name` - The name of the table to insert a row into.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `columns` - The columns the values are given for, or `None` for every column in table order.
 /// * `row` - The row data to insert, represented as a vector of `SerializableValue`.
-/// * `state` - The `AppState` containing the database manager.
+/// * `state` - The `AppState` containing the connection registry.
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - If the row insertion is successful.
-/// * `Err(String)` - If the row insertion fails, with the error message.
+/// * `Ok(WriteResult)` - The number of rows inserted and the rowid of the new row.
+/// * `Err(KitError)` - If the row insertion fails, with the kind of failure.
 #[tauri::command]
-fn insert_row(
+async fn insert_row(
+    connection_id: ConnectionId,
     table_name: String,
+    schema: Option<String>,
+    columns: Option<Vec<String>>,
     row: Vec<SerializableValue>,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    let mut db_manager = state.db.lock().unwrap();
-    db_manager.insert_row(&table_name, row)
+) -> Result<WriteResult, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(move |db_manager| {
+            db_manager.insert_row(
+                schema.as_deref(),
+                &table_name,
+                columns.unwrap_or_default(),
+                row,
+            )
+        })
+        .await
 }
 
 /// Updates a row in the specified table.
 ///
-/// This function locks the `AppState`'s database manager and updates a row in the specified table.
+/// This function updates a row in the specified table on the connection's writer.
 ///
 /// # Arguments
 ///
+/// * `connection_id` - The id of the connection to use.
 /// * `table_name` - The name of the table to update a row in.
-/// * `col_name` - The name of the column where the row is located.
-/// * `index_col_name` - The name of the index column.
-/// * `id` - The ID of the row to update.
+/// * `schema` - The attached schema of the table, if it is not found by the default search order.
+/// * `col_name` - The name of the column to update.
+/// * `row_key` - The key of the row to update, as returned with the table data.
 /// * `value` - The new value to update the row with.
-/// * `state` - The `AppState` containing the database manager.
+/// * `state` - The `AppState` containing the connection registry.
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - If the row update is successful.
-/// * `Err(String)` - If the row update fails, with the error message.
+/// * `Ok(WriteResult)` - The number of rows updated.
+/// * `Err(KitError)` - If the row update fails, with the kind of failure.
 #[tauri::command]
-fn update_row(
+async fn update_row(
+    connection_id: ConnectionId,
     table_name: String,
+    schema: Option<String>,
     col_name: String,
-    index_col_name: String,
-    id: i64,
+    row_key: RowKey,
     value: SerializableValue,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    let mut db_manager = state.db.lock().unwrap();
-    db_manager.update_row(&table_name, &col_name, &index_col_name, id, value)
+) -> Result<WriteResult, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(move |db_manager| {
+            db_manager.update_row(schema.as_deref(), &table_name, &col_name, &row_key, value)
+        })
+        .await
+}
+
+/// Undoes the most recent edit made on a connection.
+///
+/// This function applies the inverse of the last edit made with `insert_row`, `update_row` or
+/// `remove_row`, whichever table it was made on.
+/// The history is kept per connection, so it survives switching tables.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(JournalEntry)` - The edit that was undone.
+/// * `Err(KitError)` - If there is no edit to undo, the row has changed since, or the inverse edit fails.
+#[tauri::command]
+async fn undo(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<JournalEntry, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection.write(|db_manager| db_manager.undo()).await
+}
+
+/// Redoes the most recently undone edit on a connection.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(JournalEntry)` - The edit that was redone.
+/// * `Err(KitError)` - If there is no edit to redo, the row has changed since, or the edit fails.
+#[tauri::command]
+async fn redo(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<JournalEntry, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection.write(|db_manager| db_manager.redo()).await
+}
+
+/// Starts staging edits on a connection.
+///
+/// This function starts a pending-changes session on the connection's writer.
+/// Until it is committed, edits made with `insert_row`, `update_row` and `remove_row` can be
+/// listed and rolled back. On SQLite files the edits run in a transaction; on libsql servers they
+/// are queued and sent as one batch on commit.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(())` - If staging started.
+/// * `Err(KitError)` - If edits are already being staged, or the transaction could not be started.
+#[tauri::command]
+async fn begin_edits(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<(), KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(|db_manager| db_manager.begin_edits())
+        .await
+}
+
+/// Lists the edits staged on a connection.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(Vec<PendingEdit>)` - The staged edits in the order they were made, empty if edits are not staged.
+/// * `Err(KitError)` - If there is no open connection with that id.
+#[tauri::command]
+async fn list_pending_edits(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<Vec<PendingEdit>, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .read_on_writer(|db_manager| Ok(db_manager.pending_edits()))
+        .await
+}
+
+/// Commits the edits staged on a connection.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(usize)` - The number of edits committed.
+/// * `Err(KitError)` - If no edits are being staged, or the commit fails; the edits then stay staged.
+#[tauri::command]
+async fn commit_edits(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<usize, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(|db_manager| db_manager.commit_edits())
+        .await
+}
+
+/// Rolls back the edits staged on a connection.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the edits were discarded.
+/// * `Err(KitError)` - If no edits are being staged, or the rollback fails.
+#[tauri::command]
+async fn rollback_edits(
+    connection_id: ConnectionId,
+    state: State<'_, AppState>,
+) -> Result<(), KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .write(|db_manager| db_manager.rollback_edits())
+        .await
 }
 
 /// Runs a query on the database.
 ///
-/// This function locks the `AppState`'s database manager and runs a query on the database.
+/// This function runs a query on the connection's writer.
+/// It is async, so it does not block the main thread and the query can be cancelled with `cancel_query`.
 ///
 /// # Arguments
 ///
+/// * `connection_id` - The id of the connection to use.
 /// * `query` - The query to run on the database.
-/// * `state` - The `AppState` containing the database manager.
-///     
+/// * `params` - The values of the parameters of the query: an array for `?` and `?NNN`
+///   parameters, or an object keyed by name for `:name`, `@name` and `$name` parameters.
+/// * `query_id` - The id to cancel the query with and to tell its `query-progress` events apart, chosen by the caller.
+///   Queries without an id cannot be cancelled and send no progress events.
+/// * `timeout_ms` - The time after which the query is stopped, in milliseconds, if any.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+/// * `store` - The `AppStore` the query is recorded in, whether it succeeds or fails.
+///
 /// # Returns
 ///
-/// * `Ok(TableRequest)` - If the query is successful.
-/// * `Err(String)` - If the query fails, with the error message.
+/// * `Ok(TableRequest)` - If the query is successful, with how long it took and its statement counters.
+/// * `Err(KitError)` - If the query fails, with the kind of failure, or `Interrupted` if it was cancelled or timed out.
 #[tauri::command]
-fn sql_query(query: String, state: State<'_, AppState>) -> Result<TableRequest, String> {
-    let mut db_manager = state.db.lock().unwrap();
-    db_manager.run_query(&query)
+async fn sql_query(
+    connection_id: ConnectionId,
+    query: String,
+    params: Option<QueryParams>,
+    query_id: Option<QueryId>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+    store: State<'_, AppStore>,
+) -> Result<TableRequest, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::new(timeout_ms.map(Duration::from_millis));
+    let _running = state.queries.start(query_id.clone(), &control);
+    let executed_at = store::now_ms();
+    let started = Instant::now();
+    let sql = query.clone();
+    emit_query_progress(
+        &state.events,
+        &query_id,
+        connection_id,
+        None::<&Result<(), _>>,
+        started,
+    );
+    let result = connection
+        .write(move |db_manager| {
+            db_manager.run_query(&query, &params.unwrap_or_default(), &control)
+        })
+        .await;
+    emit_query_progress(
+        &state.events,
+        &query_id,
+        connection_id,
+        Some(&result),
+        started,
+    );
+    let (backend, location) = connection.describe();
+    let entry = NewHistoryEntry {
+        backend,
+        location,
+        sql,
+        executed_at,
+        elapsed_ms: match &result {
+            Ok(table) => table.elapsed_ms,
+            Err(_) => Some(started.elapsed().as_secs_f64() * 1000.0),
+        },
+        row_count: result.as_ref().ok().map(|table| table.row_count),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    record_history(&store, vec![entry]);
+    result
+}
+
+/// Sends the `query-progress` event of a query started with an id: before it runs when `result`
+/// is `None`, and once it has ended otherwise.
+fn emit_query_progress<T>(
+    events: &EventBus,
+    query_id: &Option<QueryId>,
+    connection_id: ConnectionId,
+    result: Option<&Result<T, KitError>>,
+    started: Instant,
+) {
+    let query_id = match query_id {
+        Some(query_id) => query_id.clone(),
+        None => return,
+    };
+    let status = match result {
+        None => QueryStatus::Running,
+        Some(Ok(_)) => QueryStatus::Finished,
+        Some(Err(KitError::Interrupted(_))) => QueryStatus::Interrupted,
+        Some(Err(_)) => QueryStatus::Failed,
+    };
+    events.emit(AppEvent::QueryProgress(QueryProgress {
+        query_id,
+        connection_id,
+        status,
+        elapsed_ms: result.map(|_| started.elapsed().as_secs_f64() * 1000.0),
+    }));
 }
 
-/// Subscribes to changes in the database.
+/// Records statements in the history. The history is a convenience, so failing to record
+/// them does not fail the statements; the error is only printed.
+fn record_history(store: &AppStore, entries: Vec<NewHistoryEntry>) {
+    let recorded = store.run(|conn| {
+        entries
+            .iter()
+            .try_for_each(|entry| history::record(conn, entry))
+    });
+    if let Err(e) = recorded {
+        println!("Could not record the query history: {}", e);
+    }
+}
+
+/// Cancels a running query or script.
+///
+/// Queries on SQLite files are interrupted. Requests to libsql servers cannot be aborted,
+/// so they are abandoned: the query returns at once and the server's response is dropped.
 ///
-/// This function takes a callback function as an argument.
-/// The callback function is invoked whenever a change is detected in the database.
+/// # Arguments
+///
+/// * `query_id` - The id the query was started with.
+/// * `state` - The `AppState` containing the running queries.
+///
+/// # Returns
+///
+/// * `bool` - Whether a query with that id was running.
 #[tauri::command]
-fn subscribe(
-    handler: usize,
-    function_name: String,
-    _state: State<'_, AppState>,
-    window: tauri::Window,
-) {
-    let msg = "dummy".to_string();
-    window
-        .eval(&format!("window._{}({:?})", handler, msg))
-        .unwrap();
-    println!("[callback] {} invoked", function_name);
+fn cancel_query(query_id: QueryId, state: State<'_, AppState>) -> bool {
+    state.queries.cancel(&query_id)
 }
 
-/// Registers a callback function.
+/// Runs a script of several statements on the database.
 ///
-/// This function takes a callback function as an argument and stores it in the application state.
-/// The callback function is invoked whenever a change is detected in the database.
+/// This function splits the script into complete statements (trigger bodies, string literals and
+/// comments are kept whole) and runs them in order on the connection's writer.
+/// It stops at the first statement that fails, or when it is cancelled with `cancel_query` or times out.
 ///
 /// # Arguments
 ///
-/// * `handler` - The handler ID for the callback function.
-/// * `function_name` - The name of the callback function.
-/// * `state` - The `AppState` containing the database manager.
-/// * `window` - The `tauri::Window` object representing the application window.
+/// * `connection_id` - The id of the connection to use.
+/// * `script` - The statements to run, separated by semicolons.
+/// * `transaction` - Whether to run the statements in one transaction that is rolled back if one fails. Defaults to `false`.
+/// * `query_id` - The id to cancel the script with and to tell its `query-progress` events apart, chosen by the caller.
+///   Scripts without an id cannot be cancelled and send no progress events.
+/// * `timeout_ms` - The time after which the script is stopped, in milliseconds, if any.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+/// * `store` - The `AppStore` each statement that ran, and the one that failed, is recorded in.
+///
+/// # Returns
+///
+/// * `Ok(ScriptResult)` - The rows, changes, last rowid and time of each statement that ran, and the error of the one that failed, if any.
+/// * `Err(KitError)` - If the transaction could not be started or ended, with the kind of failure.
 #[tauri::command]
-fn register_callback(
-    handler: usize,
-    function_name: String,
+async fn run_script(
+    connection_id: ConnectionId,
+    script: String,
+    transaction: Option<bool>,
+    query_id: Option<QueryId>,
+    timeout_ms: Option<u64>,
     state: State<'_, AppState>,
-    window: tauri::Window,
-) {
-    let mut callbacks = state.callbacks.lock().unwrap();
-    let callback = move |msg: String| {
-        window
-            .eval(&format!("window._{}({:?})", handler, msg))
-            .unwrap();
+    store: State<'_, AppStore>,
+) -> Result<ScriptResult, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::new(timeout_ms.map(Duration::from_millis));
+    let _running = state.queries.start(query_id.clone(), &control);
+    let executed_at = store::now_ms();
+    let started = Instant::now();
+    let sql = script.clone();
+    emit_query_progress(
+        &state.events,
+        &query_id,
+        connection_id,
+        None::<&Result<(), _>>,
+        started,
+    );
+    let result = connection
+        .write(move |db_manager| {
+            db_manager.run_script(&script, transaction.unwrap_or(false), &control)
+        })
+        .await;
+    // a script that stopped at a failed statement still returns its results
+    let status =
+        result
+            .as_ref()
+            .map_err(Clone::clone)
+            .and_then(|script_result| match &script_result.error {
+                Some(script_error) => Err(script_error.error.clone()),
+                None => Ok(()),
+            });
+    emit_query_progress(
+        &state.events,
+        &query_id,
+        connection_id,
+        Some(&status),
+        started,
+    );
+    let (backend, location) = connection.describe();
+    let entry = |sql: &str, elapsed_ms, row_count, error| NewHistoryEntry {
+        backend: backend.clone(),
+        location: location.clone(),
+        sql: sql.to_string(),
+        executed_at,
+        elapsed_ms,
+        row_count,
+        error,
+    };
+    let entries = match &result {
+        Ok(script_result) => {
+            let mut entries: Vec<NewHistoryEntry> = script_result
+                .statements
+                .iter()
+                .map(|statement| {
+                    let row_count = if statement.column_names.is_empty() {
+                        statement.rows_affected as i64
+                    } else {
+                        statement.rows.len() as i64
+                    };
+                    entry(&statement.sql, statement.elapsed_ms, Some(row_count), None)
+                })
+                .collect();
+            if let Some(script_error) = &script_result.error {
+                let statements = db_manager::lexer::split_statements(&sql);
+                let failed = script_error
+                    .index
+                    .and_then(|index| statements.get(index).copied())
+                    .unwrap_or(&sql);
+                entries.push(entry(
+                    failed,
+                    None,
+                    None,
+                    Some(script_error.error.to_string()),
+                ));
+            }
+            entries
+        }
+        Err(e) => vec![entry(&sql, None, None, Some(e.to_string()))],
     };
-    callbacks.insert(function_name.to_string(), Box::new(callback));
+    record_history(&store, entries);
+    result
+}
+
+/// Lists the parameters of a statement, so their values can be asked for before running it.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `query` - The statement to inspect.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Option<String>>)` - The name of each parameter in index order, e.g. `":id"` or `"?2"`,
+///   and `None` for anonymous `?` parameters.
+/// * `Err(KitError)` - If the statement is not valid, with the kind of failure.
+#[tauri::command]
+async fn query_parameters(
+    connection_id: ConnectionId,
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<Option<String>>, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .read(move |db_manager| db_manager.parameter_names(&query))
+        .await
+}
+
+/// Explains how a query would run, without running it.
+///
+/// This function runs `EXPLAIN QUERY PLAN` and `EXPLAIN` on the connection's writer, where the
+/// query itself would run from the SQL console. libsql servers that do not support `EXPLAIN`
+/// report an error.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `query` - The query to explain.
+/// * `params` - The values of the parameters of the query, as for `sql_query`. Without them,
+///   every parameter is `NULL`.
+/// * `state` - The `AppState` containing the connection registry.
+///
+/// # Returns
+///
+/// * `Ok(QueryPlan)` - The query plan as a tree of steps, and the bytecode as a table.
+/// * `Err(KitError)` - If the query cannot be prepared, with the kind of failure.
+#[tauri::command]
+async fn explain_query(
+    connection_id: ConnectionId,
+    query: String,
+    params: Option<QueryParams>,
+    state: State<'_, AppState>,
+) -> Result<QueryPlan, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    connection
+        .read_on_writer(move |db_manager| db_manager.explain_query(&query, params.as_ref()))
+        .await
+}
+
+/// Opens a cursor over the result of a query, so it can be fetched in batches.
+///
+/// This function opens a connection of the cursor's own to the same database and starts the query
+/// there, so the cursor does not hold up the connection it was opened from. The query reads ahead
+/// by at most `CURSOR_BUFFER` rows and then waits for them to be fetched.
+/// Like browsing, a cursor does not see staged edits or temporary tables, and cannot write.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to open the cursor from.
+/// * `query` - The query to run.
+/// * `params` - The values of the parameters of the query, as for `sql_query`.
+/// * `state` - The `AppState` containing the connection registry and the open cursors.
+///
+/// # Returns
+///
+/// * `Ok(OpenedCursor)` - The id of the cursor and the columns of the result.
+/// * `Err(KitError)` - If the database cannot be opened a second time, e.g. in memory, or the query fails.
+#[tauri::command]
+async fn open_cursor(
+    connection_id: ConnectionId,
+    query: String,
+    params: Option<QueryParams>,
+    state: State<'_, AppState>,
+) -> Result<OpenedCursor, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let db_manager = connection
+        .read_on_writer(|db_manager| db_manager.open_reader().map_err(KitError::from))
+        .await?
+        .ok_or_else(|| {
+            KitError::invalid_state("cursors need a database that can be opened a second time")
+        })?;
+    let (cursor, column_names) =
+        Cursor::open(db_manager, query, params.unwrap_or_default()).await?;
+    let cursor_id = state.cursors.lock().unwrap().insert(connection_id, cursor);
+    Ok(OpenedCursor {
+        cursor_id,
+        column_names,
+    })
+}
+
+/// Fetches the next rows of a cursor.
+///
+/// # Arguments
+///
+/// * `cursor_id` - The id of the cursor.
+/// * `count` - The maximum number of rows to fetch, defaults to `DEFAULT_PAGE_SIZE`. Fewer rows
+///   are returned when their cell values exceed `MAX_BATCH_BYTES`.
+/// * `state` - The `AppState` containing the open cursors.
+///
+/// # Returns
+///
+/// * `Ok(CursorBatch)` - The rows, and whether the result has ended.
+/// * `Err(KitError)` - If there is no open cursor with that id, or the query failed.
+#[tauri::command]
+async fn fetch_cursor(
+    cursor_id: CursorId,
+    count: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<CursorBatch, KitError> {
+    let cursor = state.cursors.lock().unwrap().get(cursor_id)?;
+    let mut cursor = cursor.lock().await;
+    cursor
+        .fetch(count.unwrap_or(DEFAULT_PAGE_SIZE as usize))
+        .await
+}
+
+/// Closes a cursor and stops its query.
+///
+/// # Arguments
+///
+/// * `cursor_id` - The id of the cursor.
+/// * `state` - The `AppState` containing the open cursors.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the cursor was closed.
+/// * `Err(KitError)` - If there is no open cursor with that id.
+#[tauri::command]
+fn close_cursor(cursor_id: CursorId, state: State<'_, AppState>) -> Result<(), KitError> {
+    state.cursors.lock().unwrap().close(cursor_id)
+}
+
+/// Lists the statements recorded in the query history, from the most recent.
+///
+/// Every statement run from `sql_query` and `run_script` is recorded, with the connection it ran
+/// on, when it ran, how long it took, how many rows it returned or changed, and its error if it failed.
+///
+/// # Arguments
+///
+/// * `location` - Only list the statements run on this database file path or libsql URL, if any.
+/// * `starred_only` - Whether to only list starred statements. Defaults to `false`.
+/// * `offset` - The number of entries to skip. Defaults to `0`.
+/// * `limit` - The maximum number of entries to return. Defaults to `100`.
+/// * `store` - The `AppStore` holding the history.
+///
+/// # Returns
+///
+/// * `Ok(Vec<HistoryEntry>)` - The entries, from the most recent.
+/// * `Err(KitError)` - If the history cannot be read.
+#[tauri::command]
+fn list_history(
+    location: Option<String>,
+    starred_only: Option<bool>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+    store: State<'_, AppStore>,
+) -> Result<Vec<HistoryEntry>, KitError> {
+    store.run(|conn| {
+        history::list(
+            conn,
+            location.as_deref(),
+            starred_only.unwrap_or(false),
+            offset.unwrap_or(0),
+            limit.unwrap_or(100),
+        )
+    })
+}
+
+/// Searches the query history for statements containing every word of `text`.
+///
+/// Words match as prefixes of the words of a statement, so `sel us` finds `SELECT * FROM users`.
+///
+/// # Arguments
+///
+/// * `text` - The words to search for. Without any, the most recent entries are returned.
+/// * `limit` - The maximum number of entries to return. Defaults to `100`.
+/// * `store` - The `AppStore` holding the history.
+///
+/// # Returns
+///
+/// * `Ok(Vec<HistoryEntry>)` - The matching entries, from the most recent.
+/// * `Err(KitError)` - If the history cannot be read.
+#[tauri::command]
+fn search_history(
+    text: String,
+    limit: Option<i64>,
+    store: State<'_, AppStore>,
+) -> Result<Vec<HistoryEntry>, KitError> {
+    store.run(|conn| history::search(conn, &text, limit.unwrap_or(100)))
+}
+
+/// Stars or unstars an entry of the query history. Starred entries are never removed to make
+/// room for new ones.
+///
+/// # Arguments
+///
+/// * `id` - The id of the entry.
+/// * `starred` - Whether the entry should be starred.
+/// * `store` - The `AppStore` holding the history.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the entry was updated.
+/// * `Err(KitError)` - If there is no entry with that id, or the history cannot be written.
+#[tauri::command]
+fn set_history_starred(id: i64, starred: bool, store: State<'_, AppStore>) -> Result<(), KitError> {
+    match store.run(|conn| history::set_starred(conn, id, starred))? {
+        true => Ok(()),
+        false => Err(KitError::invalid_argument(format!(
+            "there is no history entry {}",
+            id
+        ))),
+    }
+}
+
+/// Deletes an entry of the query history.
+///
+/// # Arguments
+///
+/// * `id` - The id of the entry.
+/// * `store` - The `AppStore` holding the history.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the entry was deleted.
+/// * `Err(KitError)` - If there is no entry with that id, or the history cannot be written.
+#[tauri::command]
+fn delete_history_entry(id: i64, store: State<'_, AppStore>) -> Result<(), KitError> {
+    match store.run(|conn| history::delete(conn, id))? {
+        true => Ok(()),
+        false => Err(KitError::invalid_argument(format!(
+            "there is no history entry {}",
+            id
+        ))),
+    }
+}
+
+/// Exports a table or the result of a query to a file.
+///
+/// Rows are written as they are read, on the connection's reader like browsing, so exports of any
+/// size hold little memory. `export-progress` events carrying an `ExportProgress` are sent to
+/// the windows subscribed to `export_progress` while rows are written and once the export is
+/// done. The export can be stopped with `cancel_query`; the file is removed if the export fails
+/// or is stopped.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `source` - The table, or the query and its parameters, to export.
+/// * `path` - The file to write, replaced if it exists.
+/// * `format` - `"csv"`, `"tsv"`, `"json"`, `"ndjson"` or `"sql"`.
+/// * `options` - The delimiter, quoting and header of CSV and TSV files, how blobs are encoded,
+///   and the table `INSERT` statements insert into. Every option has a default.
+/// * `export_id` - The id to cancel the export with and to tell its progress events apart,
+///   chosen by the caller.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+///
+/// # Returns
+///
+/// * `Ok(ExportSummary)` - The number of rows and bytes written, and how long it took.
+/// * `Err(KitError)` - If the query fails, the file cannot be written, or the export was
+///   cancelled.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn export_data(
+    connection_id: ConnectionId,
+    source: ExportSource,
+    path: PathBuf,
+    format: ExportFormat,
+    options: Option<ExportOptions>,
+    export_id: Option<QueryId>,
+    state: State<'_, AppState>,
+) -> Result<ExportSummary, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::default();
+    let _running = state.queries.start(export_id.clone(), &control);
+    let events = state.events.clone();
+    let schema = match &source {
+        ExportSource::Table { schema, .. } => schema.clone(),
+        ExportSource::Query { .. } => None,
+    };
+    connection
+        .read_schema(schema.as_deref(), move |db_manager| {
+            export::export_to_file(
+                db_manager,
+                &source,
+                format,
+                &options.unwrap_or_default(),
+                &path,
+                &control,
+                &mut |progress| {
+                    let progress = ExportProgress {
+                        export_id: export_id.clone(),
+                        ..progress
+                    };
+                    events.emit(AppEvent::ExportProgress(progress));
+                },
+            )
+        })
+        .await
+}
+
+/// Previews a CSV, TSV or JSON file before it is imported.
+///
+/// # Arguments
+///
+/// * `path` - The file to read.
+/// * `options` - The format, delimiter, quote, header and encoding of the file. Every option has
+///   a default.
+/// * `limit` - The number of rows to return. Defaults to `DEFAULT_PAGE_SIZE`.
+///
+/// # Returns
+///
+/// * `Ok(ImportPreview)` - The columns of the file with their inferred types, and its first rows.
+/// * `Err(KitError)` - If the file cannot be read or parsed.
+#[tauri::command]
+async fn preview_import(
+    path: PathBuf,
+    options: Option<ImportOptions>,
+    limit: Option<usize>,
+) -> Result<ImportPreview, KitError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE as usize);
+    tokio::task::spawn_blocking(move || import::preview(&path, &options.unwrap_or_default(), limit))
+        .await
+        .map_err(|e| KitError::invalid_state(e.to_string()))?
+}
+
+/// Imports a CSV, TSV or JSON file into a new or existing table.
+///
+/// This function reads the file as it inserts its rows on the connection's writer, in batches
+/// that are each inserted in one transaction. Rows that fail are skipped and reported. Imported
+/// rows are not recorded in the journal, so they cannot be undone, and edits cannot be staged
+/// while importing. `import-progress` events carrying an `ImportProgress` are sent to the
+/// windows subscribed to `import_progress` while rows are inserted and once the import has
+/// ended. The import can be stopped between batches with `cancel_query`.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `path` - The file to read.
+/// * `options` - The format, delimiter, quote, header and encoding of the file. Every option has
+///   a default.
+/// * `target` - The table to import into, whether to create it, and how the columns of the file
+///   map onto its columns.
+/// * `import_id` - The id to cancel the import with and to tell its progress events apart,
+///   chosen by the caller.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+///
+/// # Returns
+///
+/// * `Ok(ImportSummary)` - The number of rows read, inserted and failed, and the errors of the
+///   first rows that failed.
+/// * `Err(KitError)` - If the file cannot be read, the mapping is invalid, or the table cannot be
+///   created.
+#[tauri::command]
+async fn import_data(
+    connection_id: ConnectionId,
+    path: PathBuf,
+    options: Option<ImportOptions>,
+    target: ImportTarget,
+    import_id: Option<QueryId>,
+    state: State<'_, AppState>,
+) -> Result<ImportSummary, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::default();
+    let _running = state.queries.start(import_id.clone(), &control);
+    let events = state.events.clone();
+    connection
+        .write(move |db_manager| {
+            import::import_file(
+                db_manager,
+                &path,
+                &options.unwrap_or_default(),
+                &target,
+                &control,
+                &mut |progress| {
+                    let progress = ImportProgress {
+                        import_id: import_id.clone(),
+                        ..progress
+                    };
+                    events.emit(AppEvent::ImportProgress(progress));
+                },
+            )
+        })
+        .await
+}
+
+/// Writes the schema and rows of a database, or of some of its tables, to a file as an SQL
+/// script that recreates them.
+///
+/// This function reads every table from the same snapshot, on a reader of the connection when
+/// it has one. Tables come first, parents before the tables referring to them, then their rows,
+/// then indexes, views and triggers, all in one transaction. `dump-progress` events carrying a
+/// `DumpProgress` are sent to the windows subscribed to `dump_progress` while rows are written and
+/// once the dump is done. The dump can be stopped with `cancel_query`.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `path` - The file to write. It is replaced if it exists.
+/// * `tables` - The tables and views to dump, with their indexes and triggers. Without it, the
+///   whole database is dumped.
+/// * `dump_id` - The id to cancel the dump with and to tell its progress events apart, chosen by
+///   the caller.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+///
+/// # Returns
+///
+/// * `Ok(DumpSummary)` - The number of tables, rows and bytes written.
+/// * `Err(KitError)` - If a table does not exist, the file cannot be written, or the dump was
+///   cancelled.
+#[tauri::command]
+async fn export_sql_dump(
+    connection_id: ConnectionId,
+    path: PathBuf,
+    tables: Option<Vec<String>>,
+    dump_id: Option<QueryId>,
+    state: State<'_, AppState>,
+) -> Result<DumpSummary, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::default();
+    let _running = state.queries.start(dump_id.clone(), &control);
+    let events = state.events.clone();
+    connection
+        .read(move |db_manager| {
+            dump::export_dump(
+                db_manager,
+                tables.as_deref(),
+                &path,
+                &control,
+                &mut |progress| {
+                    let progress = DumpProgress {
+                        dump_id: dump_id.clone(),
+                        ..progress
+                    };
+                    events.emit(AppEvent::DumpProgress(progress));
+                },
+            )
+        })
+        .await
+}
+
+/// Replays an SQL dump, or any SQL script, on a connection.
+///
+/// This function runs the statements of the file on the connection's writer in batches. On
+/// SQLite files they all run in one transaction, so nothing is changed if a statement fails; on
+/// libsql servers the batches before a failed one are kept. Edits cannot be staged while a dump
+/// is replayed. `restore-progress` events carrying a `RestoreProgress` are sent to the windows
+/// subscribed to `dump_progress` after each batch. The replay can be stopped with `cancel_query`.
+///
+/// # Arguments
+///
+/// * `connection_id` - The id of the connection to use.
+/// * `path` - The dump file, in UTF-8.
+/// * `dump_id` - The id to cancel the replay with and to tell its progress events apart, chosen
+///   by the caller.
+/// * `state` - The `AppState` containing the connection registry and the event bus.
+///
+/// # Returns
+///
+/// * `Ok(RestoreSummary)` - The number of statements that ran.
+/// * `Err(KitError)` - If the file cannot be read, or the error of the statement that failed.
+#[tauri::command]
+async fn import_sql_dump(
+    connection_id: ConnectionId,
+    path: PathBuf,
+    dump_id: Option<QueryId>,
+    state: State<'_, AppState>,
+) -> Result<RestoreSummary, KitError> {
+    let connection = state.connections.lock().unwrap().get(connection_id)?;
+    let control = QueryControl::default();
+    let _running = state.queries.start(dump_id.clone(), &control);
+    let events = state.events.clone();
+    connection
+        .write(move |db_manager| {
+            dump::import_dump(db_manager, &path, &control, &mut |progress| {
+                let progress = RestoreProgress {
+                    dump_id: dump_id.clone(),
+                    ..progress
+                };
+                events.emit(AppEvent::RestoreProgress(progress));
+            })
+        })
+        .await
+}
+
+/// Lists the queries saved in the library, by name.
+///
+/// # Arguments
+///
+/// * `location` - Only list the queries bound to this database file path or libsql URL, and
+///   the ones bound to none. Without it, every query is listed.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(Vec<SavedQuery>)` - The saved queries.
+/// * `Err(KitError)` - If the library cannot be read.
+#[tauri::command]
+fn list_saved_queries(
+    location: Option<String>,
+    store: State<'_, AppStore>,
+) -> Result<Vec<SavedQuery>, KitError> {
+    store.run(|conn| saved_queries::list(conn, location.as_deref()))
+}
+
+/// Saves a query in the library.
+///
+/// # Arguments
+///
+/// * `query` - The name, SQL, description, parameter defaults and database of the query.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(SavedQuery)` - The saved query.
+/// * `Err(KitError)` - If the name is empty or taken, or the library cannot be written.
+#[tauri::command]
+fn create_saved_query(
+    query: SavedQueryInput,
+    store: State<'_, AppStore>,
+) -> Result<SavedQuery, KitError> {
+    store.run(|conn| saved_queries::create(conn, &query))
+}
+
+/// Replaces what is saved for a query in the library.
+///
+/// # Arguments
+///
+/// * `id` - The id of the saved query.
+/// * `query` - The name, SQL, description, parameter defaults and database of the query.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(SavedQuery)` - The updated query.
+/// * `Err(KitError)` - If there is no query with that id, the name is empty or taken, or the library cannot be written.
+#[tauri::command]
+fn update_saved_query(
+    id: i64,
+    query: SavedQueryInput,
+    store: State<'_, AppStore>,
+) -> Result<SavedQuery, KitError> {
+    store.run(|conn| saved_queries::update(conn, id, &query))
+}
+
+/// Deletes a query from the library.
+///
+/// # Arguments
+///
+/// * `id` - The id of the saved query.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the query was deleted.
+/// * `Err(KitError)` - If there is no query with that id, or the library cannot be written.
+#[tauri::command]
+fn delete_saved_query(id: i64, store: State<'_, AppStore>) -> Result<(), KitError> {
+    match store.run(|conn| saved_queries::delete(conn, id))? {
+        true => Ok(()),
+        false => Err(KitError::invalid_argument(format!(
+            "there is no saved query {}",
+            id
+        ))),
+    }
+}
+
+/// Exports the library to a folder, one `.sql` file per query, so it can be shared in git.
+///
+/// Each file starts with `-- name:`, `-- description:`, `-- connection:` and `-- params:`
+/// comments, followed by the query.
+///
+/// # Arguments
+///
+/// * `dir` - The folder to write to, created if needed.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(usize)` - The number of queries written.
+/// * `Err(KitError)` - If the folder or a file cannot be written.
+#[tauri::command]
+fn export_saved_queries(dir: PathBuf, store: State<'_, AppStore>) -> Result<usize, KitError> {
+    store.run(|conn| saved_queries::export_folder(conn, &dir))
+}
+
+/// Imports the `.sql` files of a folder into the library.
+///
+/// Files exported with `export_saved_queries` keep their name, description, database and
+/// parameter defaults; other files are named after the file. An imported query replaces the
+/// saved query with the same name. Nothing is imported if one of the files cannot be read.
+///
+/// # Arguments
+///
+/// * `dir` - The folder to read from.
+/// * `store` - The `AppStore` holding the library.
+///
+/// # Returns
+///
+/// * `Ok(Vec<SavedQuery>)` - The imported queries.
+/// * `Err(KitError)` - If the folder or a file cannot be read, or the library cannot be written.
+#[tauri::command]
+fn import_saved_queries(
+    dir: PathBuf,
+    store: State<'_, AppStore>,
+) -> Result<Vec<SavedQuery>, KitError> {
+    store.run(|conn| saved_queries::import_folder(conn, &dir))
+}
+
+/// Subscribes the calling window to topics of events.
+///
+/// Events are sent with `Window::emit`, under the names listed on `EventTopic`, only to the
+/// windows subscribed to their topic. Subscribing again adds topics to those of the window.
+/// A window is unsubscribed from everything when it is closed.
+///
+/// # Arguments
+///
+/// * `topics` - The topics to subscribe to.
+/// * `state` - The `AppState` containing the event bus.
+/// * `window` - The `tauri::Window` the events are sent to.
+#[tauri::command]
+fn subscribe(topics: Vec<EventTopic>, state: State<'_, AppState>, window: tauri::Window) {
+    state.events.subscribe(window.label(), &topics);
+}
+
+/// Unsubscribes the calling window from topics of events.
+///
+/// # Arguments
+///
+/// * `topics` - The topics to unsubscribe from. Without them, the window is unsubscribed from every topic.
+/// * `state` - The `AppState` containing the event bus.
+/// * `window` - The `tauri::Window` the events were sent to.
+#[tauri::command]
+fn unsubscribe(topics: Option<Vec<EventTopic>>, state: State<'_, AppState>, window: tauri::Window) {
+    state.events.unsubscribe(window.label(), topics.as_deref());
 }
 
 /// Returns the current time in seconds since the UNIX epoch.
@@ -288,51 +1770,42 @@ fn register_callback(
 fn current_time() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
+        .unwrap_or_default()
         .as_secs()
 }
 
-/// Periodically invokes the registered callback functions.
+/// Periodically sends the `mac-address` and `free-trial-popup` events.
 ///
 /// This function is an asynchronous function that runs in an infinite loop.
-/// It invokes the registered callback functions every `seconds` seconds.
+/// Every `seconds` seconds, it sends the MAC address of the machine to the windows subscribed to
+/// it, and, unless the user has a premium subscription, the free trial pop-up when it is due.
+/// Nothing in the loop can panic: a MAC address that cannot be read is printed and skipped.
 ///
 /// # Arguments
 ///
-/// * `seconds` - The interval in seconds at which to invoke the callback functions.
-/// * `callback_mutex_clone` - A clone of the mutex protecting the callback functions.
+/// * `seconds` - The interval in seconds at which to send the events.
+/// * `events` - The event bus the events are sent on.
 /// * `is_premium` - A boolean indicating whether the user has a premium subscription.
-async fn periodic_callback(
-    seconds: u64,
-    callback_mutex_clone: Arc<Mutex<HashMap<String, Box<dyn FnMut(String) + Send>>>>,
-    is_premium: bool,
-) {
+async fn periodic_callback(seconds: u64, events: Arc<EventBus>, is_premium: bool) {
     let mut interval = tokio::time::interval(Duration::from_secs(seconds));
-    let call_back_mutex_clone = callback_mutex_clone.clone();
-    let last_shown = Arc::new(Mutex::new(current_time()));
     loop {
         interval.tick().await;
-        let mut callbacks = call_back_mutex_clone.lock().unwrap();
-        for (key, callback) in callbacks.iter_mut() {
-            if key == "macAddress" {
-                let mac_address = mac_address::get_mac_address().unwrap().unwrap();
-                callback(mac_address.to_string());
-            }
-            if key == "freeTrialPopup" {
-                let current_time = current_time();
-                let condition_every_tenth = current_time % 60 == 10;
-                let condition_every_thirtieth = current_time % 60 == 30;
-                let condition_every_fiftieth = current_time % 60 == 50;
-                if !is_premium
-                    && (condition_every_tenth
-                        || condition_every_thirtieth
-                        || condition_every_fiftieth)
-                {
-                    callback(current_time.to_string());
-                    *last_shown.lock().unwrap() = current_time;
-                }
+        if events.has_subscribers(EventTopic::MacAddress) {
+            match mac_address::get_mac_address() {
+                Ok(Some(mac_address)) => events.emit(AppEvent::MacAddress(mac_address.to_string())),
+                Ok(None) => println!("[events] this machine has no MAC address"),
+                Err(e) => println!("[events] could not read the MAC address: {}", e),
             }
         }
+        let current_time = current_time();
+        let condition_every_tenth = current_time % 60 == 10;
+        let condition_every_thirtieth = current_time % 60 == 30;
+        let condition_every_fiftieth = current_time % 60 == 50;
+        if !is_premium
+            && (condition_every_tenth || condition_every_thirtieth || condition_every_fiftieth)
+        {
+            events.emit(AppEvent::FreeTrialPopup(current_time));
+        }
     }
 }
 
@@ -402,14 +1875,16 @@ async fn check_if_premium() -> Result<bool, Box<dyn Error>> {
 async fn main() {
     #[cfg(any(windows, target_os = "macos"))]
     let app_state = AppState {
-        db: Mutex::new(DbManager::new()),
-        callbacks: Arc::new(Mutex::new(HashMap::new())),
+        connections: Mutex::new(ConnectionRegistry::default()),
+        queries: RunningQueries::default(),
+        cursors: Mutex::new(CursorRegistry::default()),
+        events: Arc::new(EventBus::default()),
     };
 
     let is_premium = check_if_premium().await.unwrap_or(false);
-    let callback_mutex_clone = app_state.callbacks.clone();
+    let events = app_state.events.clone();
     tokio::spawn(async move {
-        periodic_callback(1, callback_mutex_clone, is_premium).await;
+        periodic_callback(1, events, is_premium).await;
     });
 
     tauri::Builder::default()
@@ -428,18 +1903,61 @@ async fn main() {
                 .unwrap();
 
             set_shadow(&window, true).expect("Unsupported platform!");
+
+            let store_dir = app.path_resolver().app_data_dir();
+            app.manage(AppStore::open(store_dir.as_deref()));
+            app.state::<AppState>().events.attach(app.handle());
             Ok(())
         })
+        .on_window_event(|event| {
+            if let tauri::WindowEvent::Destroyed = event.event() {
+                let state = event.window().state::<AppState>();
+                state.events.unsubscribe(event.window().label(), None);
+            }
+        })
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
-            connect_to_db,
+            open_connection,
+            close_connection,
+            list_connections,
             get_table_data,
+            get_row_count,
+            get_table_schema,
+            get_schema_objects,
             remove_row,
             insert_row,
             update_row,
+            begin_edits,
+            list_pending_edits,
+            commit_edits,
+            rollback_edits,
+            undo,
+            redo,
             subscribe,
-            register_callback,
-            sql_query
+            unsubscribe,
+            sql_query,
+            query_parameters,
+            explain_query,
+            run_script,
+            cancel_query,
+            open_cursor,
+            fetch_cursor,
+            close_cursor,
+            list_history,
+            search_history,
+            set_history_starred,
+            delete_history_entry,
+            list_saved_queries,
+            create_saved_query,
+            update_saved_query,
+            delete_saved_query,
+            export_saved_queries,
+            import_saved_queries,
+            export_data,
+            preview_import,
+            import_data,
+            export_sql_dump,
+            import_sql_dump
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,18 +1,45 @@
-/// Importing the `DbManagerTrait` trait from the `db_manager` module.
-use crate::db_manager::DbManagerTrait;
+/// Importing the change types, which report the rows a transaction changed.
+use crate::changes::{ChangeOperation, ChangeTracker, CommitListener};
+/// Importing the `DbManagerTrait` trait and its helpers from the `db_manager` module.
+use crate::db_manager::{
+    column_infos, elapsed_ms, lexer, sql, sql::SqlStatement, ConnectionError, DbManagerTrait,
+    KeyShape, QueryStats, ResultRows, RowKey, ScriptError, ScriptResult, StatementResult,
+    StreamItem, StreamSink, TableQuery, WriteResult,
+};
+/// Importing the `KitError` enum and the types it is built from.
+use crate::error::{Backend, KitError, SqlError};
+/// Importing the `QueryControl` struct, which cancels and times out queries.
+use crate::queries::QueryControl;
+/// Importing the `RowCountKind` enum, which tells whether a row count is exact.
+use crate::row_counts::RowCountKind;
 /// Importing the `ColumnInfo` struct.
 use crate::ColumnInfo;
 /// Importing the `SerializableValue` enum.
 use crate::SerializableValue;
 /// Importing the `TableRequest` struct.
 use crate::TableRequest;
+/// Importing the `Action` enum, which tells how a row was changed, from the `rusqlite` crate.
+use rusqlite::hooks::Action;
 /// Importing the `Connection` and `Result` types from the `rusqlite` crate.
-use rusqlite::{Connection, Result};
+use rusqlite::{
+    params_from_iter, Connection, ErrorCode, OpenFlags, Result, Statement, StatementStatus,
+};
+/// Importing the `Path` type.
+use std::path::Path;
+/// Importing the `Arc` and `Mutex` types, which share the change tracker between hooks.
+use std::sync::{Arc, Mutex};
+/// Importing the `Instant` type, used to time statements.
+use std::time::Instant;
+
+/// The number of virtual machine instructions between two checks of whether a query should stop.
+const PROGRESS_INTERVAL: i32 = 1000;
 
 /// The `NativeDbManager` struct, which represents a connection to a SQLite database.
 pub struct NativeDbManager {
     /// The SQLite connection.
     conn: Connection,
+    /// Follows the rows changed by each transaction once `watch_changes` is called.
+    changes: Option<Arc<Mutex<ChangeTracker>>>,
 }
 
 /// Implementation of `NativeDbManager`.
@@ -27,34 +54,312 @@ impl NativeDbManager {
     ///
     /// * `NativeDbManager` - The new `NativeDbManager`.
     pub fn new(conn: Connection) -> Self {
-        NativeDbManager { conn }
+        NativeDbManager {
+            conn,
+            changes: None,
+        }
+    }
+
+    /// Opens the SQLite database file at `path`.
+    ///
+    /// The file is only created when `create` is set. The schema is read once after opening,
+    /// because SQLite opens files lazily and would otherwise accept a file that is not a database.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the database file.
+    /// * `create` - Whether to create a new, empty database if the file does not exist.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<NativeDbManager, ConnectionError>` - The new `NativeDbManager`, or why the file could not be opened.
+    pub fn open(path: &str, create: bool) -> Result<Self, ConnectionError> {
+        let file = Path::new(path);
+        if file.exists() {
+            if let Err(e) = std::fs::File::open(file) {
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    return Err(ConnectionError::PermissionDenied {
+                        path: path.to_string(),
+                    });
+                }
+            }
+        } else if !create {
+            return Err(ConnectionError::NotFound {
+                path: path.to_string(),
+            });
+        }
+
+        let mut flags = OpenFlags::SQLITE_OPEN_READ_WRITE;
+        if create {
+            flags |= OpenFlags::SQLITE_OPEN_CREATE;
+        }
+        NativeDbManager::open_with_flags(path, flags)
+    }
+
+    /// Opens a read-only connection to the SQLite database file at `path`, which must exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the database file.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<NativeDbManager, ConnectionError>` - The new `NativeDbManager`, or why the file could not be opened.
+    pub fn open_read_only(path: &str) -> Result<Self, ConnectionError> {
+        NativeDbManager::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+    }
+
+    /// Opens the SQLite database file at `path` with `flags` and reads its schema once.
+    fn open_with_flags(path: &str, flags: OpenFlags) -> Result<Self, ConnectionError> {
+        let conn = Connection::open_with_flags(path, flags)
+            .and_then(|conn| {
+                conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+                    row.get::<_, i64>(0)
+                })?;
+                Ok(conn)
+            })
+            .map_err(|e| match e.sqlite_error_code() {
+                Some(ErrorCode::NotADatabase) => ConnectionError::NotADatabase {
+                    path: path.to_string(),
+                },
+                Some(ErrorCode::PermissionDenied) => ConnectionError::PermissionDenied {
+                    path: path.to_string(),
+                },
+                Some(ErrorCode::CannotOpen) if !Path::new(path).exists() => {
+                    ConnectionError::NotFound {
+                        path: path.to_string(),
+                    }
+                }
+                _ => ConnectionError::Failed {
+                    location: path.to_string(),
+                    message: e.to_string(),
+                },
+            })?;
+        Ok(NativeDbManager::new(conn))
+    }
+
+    /// Executes a generated statement that does not return rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - The statement and the values bound to its placeholders.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u64, KitError>` - The number of rows the statement changed.
+    fn execute(&mut self, statement: &SqlStatement) -> Result<u64, KitError> {
+        self.tracked(&statement.sql, |conn| {
+            match conn.execute(&statement.sql, params_from_iter(statement.params.iter())) {
+                Ok(rows_affected) => Ok(rows_affected as u64),
+                Err(e) => Err(query_error(e, &statement.sql)),
+            }
+        })
+    }
+
+    /// Executes statements that take no parameters and return no rows, such as the ones that
+    /// open and close savepoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - A string slice that holds the statements.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if a statement failed.
+    fn execute_batch(&self, sql: &str) -> Result<(), KitError> {
+        self.tracked(sql, |conn| {
+            conn.execute_batch(sql).map_err(|e| query_error(e, sql))
+        })
+    }
+
+    /// Runs `run` on the connection, then tells the change tracker, if changes are watched,
+    /// that the statements in `sql` returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The statements `run` runs.
+    /// * `run` - Runs the statements.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<T, KitError>` - What `run` returned.
+    fn tracked<T>(
+        &self,
+        sql: &str,
+        run: impl FnOnce(&Connection) -> Result<T, KitError>,
+    ) -> Result<T, KitError> {
+        let result = run(&self.conn);
+        if let Some(changes) = &self.changes {
+            if let Ok(mut changes) = changes.lock() {
+                changes.statement_returned(sql, result.is_ok(), self.conn.is_autocommit());
+            }
+        }
+        result
+    }
+
+    /// Runs one statement of a script and collects its rows and the rows it changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - A string slice that holds the statement.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<StatementResult, KitError>` - The result of the statement.
+    fn execute_statement(&self, sql: &str) -> Result<StatementResult, KitError> {
+        self.tracked(sql, |conn| {
+            let started = Instant::now();
+            // `changes()` keeps the count of the last INSERT, UPDATE or DELETE, so it is only
+            // read if the statement changed rows at all
+            let total_changes = |conn: &Connection| {
+                conn.query_row("SELECT total_changes()", [], |row| row.get::<_, i64>(0))
+                    .map_err(|e| query_error(e, sql))
+            };
+            let changes_before = total_changes(conn)?;
+            let mut stmt = match conn.prepare(sql) {
+                Ok(stmt) => stmt,
+                Err(e) => return Err(query_error(e, sql)),
+            };
+            let total_cols = stmt.column_count();
+            let rows: Result<Vec<Vec<SerializableValue>>, _> = stmt
+                .query_map([], |row| {
+                    let mut cols = Vec::new();
+                    for i in 0..total_cols {
+                        let value: rusqlite::types::Value = row.get(i)?;
+                        cols.push(SerializableValue::from(value));
+                    }
+                    Ok(cols)
+                })
+                .and_then(|rows| rows.collect());
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(e) => return Err(query_error(e, sql)),
+            };
+            let (names, declared_types) = column_descriptions(&stmt);
+            drop(stmt);
+            let rows_affected = if total_changes(conn)? > changes_before {
+                conn.changes()
+            } else {
+                0
+            };
+            Ok(StatementResult {
+                sql: sql.to_string(),
+                column_names: column_infos(&names, &declared_types, &rows),
+                rows,
+                rows_affected,
+                last_insert_rowid: (rows_affected > 0 && lexer::is_insert(sql))
+                    .then(|| conn.last_insert_rowid()),
+                elapsed_ms: Some(elapsed_ms(started)),
+            })
+        })
+    }
+}
+
+/// `Watch` is a guard that stops the statements of a connection when a query is cancelled
+/// or times out, until it is dropped.
+///
+/// Cancelling interrupts the connection through its `InterruptHandle`. Timeouts are checked
+/// by a progress handler, which also catches a query that was cancelled before it started.
+struct Watch<'a> {
+    conn: &'a Connection,
+}
+
+/// `Watch` implementation.
+impl<'a> Watch<'a> {
+    /// Starts watching the queries run on `conn`.
+    fn new(conn: &'a Connection, control: &QueryControl) -> Self {
+        let handle = conn.get_interrupt_handle();
+        control.on_cancel(move || handle.interrupt());
+        let control = control.clone();
+        conn.progress_handler(PROGRESS_INTERVAL, Some(move || control.should_stop()));
+        Watch { conn }
+    }
+}
+
+/// This implementation removes the progress handler once the query has finished.
+impl Drop for Watch<'_> {
+    fn drop(&mut self) {
+        self.conn.progress_handler(0, None::<fn() -> bool>);
+    }
+}
+
+/// Converts an error returned by SQLite for the statement `sql` into a `KitError`.
+///
+/// The extended result code is kept, and so is the offset of the offending token when SQLite reports one.
+pub fn query_error(e: rusqlite::Error, sql: &str) -> KitError {
+    let (extended_code, message, offset) = match &e {
+        rusqlite::Error::SqlInputError {
+            error, msg, offset, ..
+        } => (Some(error.extended_code), msg.clone(), Some(*offset)),
+        rusqlite::Error::SqliteFailure(error, Some(msg)) => {
+            (Some(error.extended_code), msg.clone(), None)
+        }
+        rusqlite::Error::SqliteFailure(error, None) => {
+            (Some(error.extended_code), e.to_string(), None)
+        }
+        _ => (None, e.to_string(), None),
+    };
+    KitError::from_sql_error(SqlError {
+        backend: Backend::Sqlite,
+        extended_code,
+        message,
+        sql: Some(sql.to_string()),
+        offset,
+    })
+}
+
+/// Returns the names and declared types of the result columns of a prepared statement.
+fn column_descriptions(stmt: &Statement) -> (Vec<String>, Vec<Option<String>>) {
+    stmt.columns()
+        .iter()
+        .map(|column| {
+            (
+                column.name().to_string(),
+                column.decl_type().map(|decl_type| decl_type.to_string()),
+            )
+        })
+        .unzip()
+}
+
+/// Reads the counters SQLite keeps for a statement that has run.
+fn statement_stats(stmt: &Statement) -> QueryStats {
+    QueryStats {
+        fullscan_steps: stmt.get_status(StatementStatus::FullscanStep),
+        sorts: stmt.get_status(StatementStatus::Sort),
+        autoindexes: stmt.get_status(StatementStatus::AutoIndex),
+        vm_steps: stmt.get_status(StatementStatus::VmStep),
     }
 }
 
 /// Implementation of `DbManagerTrait` for `NativeDbManager`.
 impl DbManagerTrait for NativeDbManager {
-    /// Fetches the table data for a given table name.
+    /// Fetches one filtered and sorted page of table data for a given table name.
     ///
     /// # Arguments
     ///
     /// * `table_name` - A string slice that holds the name of the table.
+    /// * `query` - The page window, filters and sort order to apply.
+    /// * `key` - The key of the table, selected after the table columns if given.
     ///
     /// # Returns
     ///
-    /// * `Result<TableRequest, String>` - The result of the table request.
-    fn get_table_data(&mut self, table_name: &str) -> Result<TableRequest, String> {
+    /// * `Result<TableRequest, KitError>` - The result of the table request.
+    fn get_table_data(
+        &mut self,
+        table_name: &str,
+        query: &TableQuery,
+        key: Option<&KeyShape>,
+    ) -> Result<TableRequest, KitError> {
         println!("Getting Native table data for: {:?}", table_name);
-        let mut stmt = match self
-            .conn
-            .prepare(&format!("SELECT * FROM '{}' LIMIT 100", table_name))
-        {
+        let page = sql::select_page(table_name, query, key)?;
+        let mut stmt = match self.conn.prepare(&page.sql) {
             Ok(stmt) => stmt,
-            Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(query_error(e, &page.sql)),
         };
         println!("Got Native table data for: {:?}", table_name);
         let total_cols = stmt.column_count();
         let rows: Result<Vec<Vec<SerializableValue>>, _> = stmt
-            .query_map([], |row| {
+            .query_map(params_from_iter(page.params.iter()), |row| {
                 let mut cols = Vec::new();
                 for i in 0..total_cols {
                     let value: rusqlite::types::Value = row.get(i)?;
@@ -62,180 +367,112 @@ impl DbManagerTrait for NativeDbManager {
                 }
                 Ok(cols)
             })
-            .unwrap()
-            .collect();
-
-        match rows.as_ref() {
-            Ok(rows) => match rows.first() {
-                Some(first_item) => {
-                    let column_names: Vec<ColumnInfo> = stmt
-                        .column_names()
-                        .iter()
-                        .zip(first_item)
-                        .map(|(str, value)| ColumnInfo {
-                            name: str.to_string(),
-                            type_name: match value {
-                                SerializableValue::Null => "NULL".to_string(),
-                                SerializableValue::Integer(_) => "INTEGER".to_string(),
-                                SerializableValue::Real(_) => "REAL".to_string(),
-                                SerializableValue::Text(_) => "TEXT".to_string(),
-                                SerializableValue::Blob(_) => "BLOB".to_string(),
-                            },
-                        })
-                        .collect();
-
-                    let total_rows_in_table_from_query = match self.conn.query_row(
-                        &format!("SELECT COUNT(*) FROM '{}'", table_name),
-                        [],
-                        |row| row.get(0),
-                    ) {
-                        Ok(count) => count,
-                        Err(e) => return Err(e.to_string()),
-                    };
-
-                    println!(
-                        "Total rows in table from query: {:?}",
-                        total_rows_in_table_from_query
-                    );
-
-                    Ok(TableRequest {
-                        column_names,
-                        rows: rows.clone(),
-                        row_count: total_rows_in_table_from_query,
-                    })
-                }
-                None => Ok(TableRequest {
-                    column_names: vec![],
-                    rows: vec![],
-                    row_count: 0,
-                }),
-            },
-            Err(e) => Err(e.to_string()),
-        }
-    }
-
-    /// Fetches all table names from the SQLite database.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<Vec<String>, String>` - The result of the table names request.
-    fn get_all_tables(&mut self) -> Result<Vec<String>, String> {
-        let mut stmt = match self
-            .conn
-            .prepare("SELECT name FROM sqlite_master WHERE type='table'")
-        {
-            Ok(stmt) => stmt,
-            Err(e) => return Err(e.to_string()),
+            .and_then(|rows| rows.collect());
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => return Err(query_error(e, &page.sql)),
         };
-        let rows: Result<Vec<String>, _> = stmt.query_map([], |row| row.get(0)).unwrap().collect();
 
-        println!("Rows: {:?}", rows);
+        let (names, declared_types) = column_descriptions(&stmt);
+        let column_names = column_infos(&names, &declared_types, &rows);
 
-        match rows {
-            Ok(rows) => Ok(rows),
-            Err(e) => Err(e.to_string()),
-        }
+        // the row count is filled in by the caller, which knows whether counting is worth it
+        Ok(TableRequest {
+            column_names,
+            rows,
+            row_count: 0,
+            row_count_kind: RowCountKind::AtLeast,
+            offset: query.offset,
+            limit: query.limit,
+            row_keys: vec![],
+            elapsed_ms: None,
+            stats: None,
+        })
     }
 
     /// Removes a row from a table.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - A string slice that holds the name of the table.
-    /// * `col_name` - A string slice that holds the name of the column.
-    /// * `row_id` - The id of the row to be removed.
+    /// * `key` - The key of the row to be removed.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - The result of the row removal operation.
+    /// * `Result<WriteResult, KitError>` - The number of rows removed.
     fn remove_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
-        col_name: &str,
-        row_id: i64,
-    ) -> Result<String, String> {
-        let sql = format!("DELETE FROM {} WHERE {} = {}", table_name, col_name, row_id);
-        match self.conn.execute(&sql, []) {
-            Ok(_) => Ok("Row removed successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+        key: &RowKey,
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::delete_row(schema, table_name, key)?;
+        let rows_affected = self.execute(&statement)?;
+        Ok(WriteResult {
+            rows_affected,
+            last_insert_rowid: None,
+            staged: false,
+        })
     }
 
     /// Inserts a row into a table.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - A string slice that holds the name of the table.
+    /// * `columns` - The columns the values are given for, or an empty slice for every column in order.
     /// * `row` - A vector of `SerializableValue` that represents the row to be inserted.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - The result of the row insertion operation.
+    /// * `Result<WriteResult, KitError>` - The number of rows inserted and the rowid of the new row.
     fn insert_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
+        columns: &[String],
         row: Vec<SerializableValue>,
-    ) -> Result<String, String> {
-        let placeholders: Vec<String> = row.iter().map(|_| "?".to_string()).collect();
-        let sql = format!(
-            "INSERT INTO {} VALUES ({})",
-            table_name,
-            placeholders.join(", ")
-        );
-
-        let params: Vec<&dyn rusqlite::ToSql> = row
-            .iter()
-            .map(|value| match value {
-                SerializableValue::Text(text) => text as &dyn rusqlite::ToSql,
-                SerializableValue::Integer(int) => int as &dyn rusqlite::ToSql,
-                SerializableValue::Real(real) => real as &dyn rusqlite::ToSql,
-                SerializableValue::Blob(blob) => blob as &dyn rusqlite::ToSql,
-                SerializableValue::Null => &rusqlite::types::Null as &dyn rusqlite::ToSql,
-            })
-            .collect();
-        match self.conn.execute(&sql, params.as_slice()) {
-            Ok(_) => Ok("Row added successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::insert_row(schema, table_name, columns, row)?;
+        let rows_affected = self.execute(&statement)?;
+        Ok(WriteResult {
+            rows_affected,
+            last_insert_rowid: Some(self.conn.last_insert_rowid()),
+            staged: false,
+        })
     }
 
     /// Updates a row in a table.
     ///
     /// # Arguments
     ///
+    /// * `schema` - The attached schema of the table, `None` for the default search order.
     /// * `table_name` - A string slice that holds the name of the table.
     /// * `col_name` - A string slice that holds the name of the column.
-    /// * `index_col_name` - A string slice that holds the name of the index column.
-    /// * `id` - The id of the row to be updated.
+    /// * `key` - The key of the row to be updated.
     /// * `value` - A `SerializableValue` that represents the new value.
     ///
     /// # Returns
     ///
-    /// * `Result<String, String>` - The result of the row update operation.
+    /// * `Result<WriteResult, KitError>` - The number of rows updated.
     fn update_row(
         &mut self,
+        schema: Option<&str>,
         table_name: &str,
         col_name: &str,
-        index_col_name: &str,
-        id: i64,
+        key: &RowKey,
         value: SerializableValue,
-    ) -> Result<String, String> {
-        let sql = format!(
-            "UPDATE {} SET {} = ? WHERE {} = {}",
-            table_name, col_name, index_col_name, id
-        );
-        println!("SQL: {}", sql);
-        let params: Vec<&dyn rusqlite::ToSql> = vec![match &value {
-            SerializableValue::Text(text) => text as &dyn rusqlite::ToSql,
-            SerializableValue::Integer(int) => int as &dyn rusqlite::ToSql,
-            SerializableValue::Real(real) => real as &dyn rusqlite::ToSql,
-            SerializableValue::Blob(blob) => blob as &dyn rusqlite::ToSql,
-            SerializableValue::Null => &rusqlite::types::Null as &dyn rusqlite::ToSql,
-        }];
-        match self.conn.execute(&sql, params.as_slice()) {
-            Ok(_) => Ok("Row updated successfully".to_string()),
-            Err(e) => Err(e.to_string()),
-        }
+    ) -> Result<WriteResult, KitError> {
+        let statement = sql::update_row(schema, table_name, col_name, key, value)?;
+        println!("SQL: {}", statement.sql);
+        let rows_affected = self.execute(&statement)?;
+        Ok(WriteResult {
+            rows_affected,
+            last_insert_rowid: None,
+            staged: false,
+        })
     }
 
     /// Runs a query on the database.
@@ -243,74 +480,408 @@ impl DbManagerTrait for NativeDbManager {
     /// # Arguments
     ///
     /// * `query` - A string slice that holds the query to be run.
+    /// * `params` - The values bound to the parameters of the query, one per parameter index.
+    /// * `control` - Stops the query when it is cancelled or times out.
     ///
     /// # Returns
     ///
-    /// * `Result<TableRequest, String>` - The result of the query.
-    fn run_query(&mut self, query: &str) -> Result<TableRequest, String> {
-        let mut stmt = match self.conn.prepare(query) {
-            Ok(stmt) => stmt,
-            Err(e) => return Err(e.to_string()),
-        };
-        let total_cols = stmt.column_count();
-        let rows: Result<Vec<Vec<SerializableValue>>, _> = stmt
-            .query_map([], |row| {
-                let mut cols = Vec::new();
+    /// * `Result<TableRequest, KitError>` - The result of the query.
+    fn run_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+    ) -> Result<TableRequest, KitError> {
+        let _watch = Watch::new(&self.conn, control);
+        self.tracked(query, |conn| {
+            let mut stmt = match conn.prepare(query) {
+                Ok(stmt) => stmt,
+                Err(e) => return Err(query_error(e, query)),
+            };
+            let total_cols = stmt.column_count();
+            let rows: Result<Vec<Vec<SerializableValue>>, _> = stmt
+                .query_map(params_from_iter(params.iter()), |row| {
+                    let mut cols = Vec::new();
+                    for i in 0..total_cols {
+                        let value: rusqlite::types::Value = row.get(i)?;
+                        cols.push(SerializableValue::from(value));
+                    }
+                    Ok(cols)
+                })
+                .and_then(|rows| rows.collect());
+
+            match rows {
+                Ok(rows) => match rows.first() {
+                    Some(_) => {
+                        let (names, declared_types) = column_descriptions(&stmt);
+                        let column_names: Vec<ColumnInfo> =
+                            column_infos(&names, &declared_types, &rows);
+
+                        // every row was fetched, so the query is not run again to count them:
+                        // it may have side effects, and would take as long again
+                        Ok(TableRequest {
+                            column_names,
+                            limit: rows.len() as i64,
+                            row_count: rows.len() as i64,
+                            row_count_kind: RowCountKind::Exact,
+                            rows,
+                            offset: 0,
+                            row_keys: vec![],
+                            elapsed_ms: None,
+                            stats: Some(statement_stats(&stmt)),
+                        })
+                    }
+                    None => {
+                        let (names, declared_types) = column_descriptions(&stmt);
+                        Ok(TableRequest {
+                            column_names: column_infos(&names, &declared_types, &[]),
+                            rows: vec![],
+                            row_count: 0,
+                            row_count_kind: RowCountKind::Exact,
+                            offset: 0,
+                            limit: 0,
+                            row_keys: vec![],
+                            elapsed_ms: None,
+                            stats: Some(statement_stats(&stmt)),
+                        })
+                    }
+                },
+                Err(e) => Err(query_error(e, query)),
+            }
+        })
+    }
+
+    /// Runs a query and hands its rows to `sink` as SQLite steps through them.
+    ///
+    /// The types of expression columns are taken from the first row.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to run.
+    /// * `params` - The values bound to the parameters of the query, one per parameter index.
+    /// * `control` - Stops the query when it is cancelled or times out.
+    /// * `sink` - Receives the columns, then each row; the query stops when it returns `false`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if the query failed.
+    fn stream_query(
+        &mut self,
+        query: &str,
+        params: &[SerializableValue],
+        control: &QueryControl,
+        sink: &mut StreamSink,
+    ) -> Result<(), KitError> {
+        let _watch = Watch::new(&self.conn, control);
+        self.tracked(query, |conn| {
+            let mut stmt = match conn.prepare(query) {
+                Ok(stmt) => stmt,
+                Err(e) => return Err(query_error(e, query)),
+            };
+            let (names, declared_types) = column_descriptions(&stmt);
+            let total_cols = stmt.column_count();
+            let mut rows = match stmt.query(params_from_iter(params.iter())) {
+                Ok(rows) => rows,
+                Err(e) => return Err(query_error(e, query)),
+            };
+            let mut columns_sent = false;
+            loop {
+                let row = match rows.next() {
+                    Ok(Some(row)) => row,
+                    Ok(None) => break,
+                    Err(e) => return Err(query_error(e, query)),
+                };
+                let mut cols = Vec::with_capacity(total_cols);
                 for i in 0..total_cols {
-                    let value: rusqlite::types::Value = row.get(i)?;
+                    let value: rusqlite::types::Value = match row.get(i) {
+                        Ok(value) => value,
+                        Err(e) => return Err(query_error(e, query)),
+                    };
                     cols.push(SerializableValue::from(value));
                 }
-                Ok(cols)
-            })
-            .unwrap()
-            .collect();
-
-        match rows.as_ref() {
-            Ok(rows) => match rows.first() {
-                Some(first_item) => {
-                    let column_names: Vec<ColumnInfo> = stmt
-                        .column_names()
-                        .iter()
-                        .zip(first_item)
-                        .map(|(str, value)| ColumnInfo {
-                            name: str.to_string(),
-                            type_name: match value {
-                                SerializableValue::Null => "NULL".to_string(),
-                                SerializableValue::Integer(_) => "INTEGER".to_string(),
-                                SerializableValue::Real(_) => "REAL".to_string(),
-                                SerializableValue::Text(_) => "TEXT".to_string(),
-                                SerializableValue::Blob(_) => "BLOB".to_string(),
-                            },
-                        })
-                        .collect();
-
-                    let total_rows_in_table_from_query = match self.conn.query_row(
-                        &format!("SELECT COUNT(*) FROM ({})", query),
-                        [],
-                        |row| row.get(0),
-                    ) {
-                        Ok(count) => count,
-                        Err(e) => return Err(e.to_string()),
-                    };
+                if !columns_sent {
+                    columns_sent = true;
+                    let columns =
+                        column_infos(&names, &declared_types, std::slice::from_ref(&cols));
+                    if !sink(StreamItem::Columns(columns)) {
+                        return Ok(());
+                    }
+                }
+                if !sink(StreamItem::Row(cols)) {
+                    return Ok(());
+                }
+            }
+            if !columns_sent {
+                sink(StreamItem::Columns(column_infos(
+                    &names,
+                    &declared_types,
+                    &[],
+                )));
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs the statements of a script in order and stops at the first one that fails.
+    ///
+    /// The transaction is a savepoint, so a script can also run while edits are being staged.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements to run.
+    /// * `transaction` - Whether to roll every statement back if one fails.
+    /// * `control` - Stops the script when it is cancelled or times out.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ScriptResult, KitError>` - The result of each statement that ran, or an error if the transaction could not be started or ended.
+    fn run_script(
+        &mut self,
+        statements: &[&str],
+        transaction: bool,
+        control: &QueryControl,
+    ) -> Result<ScriptResult, KitError> {
+        if transaction {
+            self.execute_batch("SAVEPOINT kit_script")?;
+        }
+        let mut result = ScriptResult::default();
+        let watch = Watch::new(&self.conn, control);
+        for (index, sql) in statements.iter().enumerate() {
+            match self.execute_statement(sql) {
+                Ok(statement) => result.statements.push(statement),
+                Err(error) => {
+                    result.error = Some(ScriptError {
+                        index: Some(index),
+                        error,
+                    });
+                    break;
+                }
+            }
+        }
+        drop(watch);
+        if transaction {
+            let end = if result.error.is_some() {
+                "ROLLBACK TO kit_script; RELEASE kit_script"
+            } else {
+                "RELEASE kit_script"
+            };
+            // an interrupted write rolls back the whole transaction, savepoint included
+            let rolled_back_already = result.error.is_some() && self.conn.is_autocommit();
+            if !rolled_back_already {
+                self.execute_batch(end)?;
+            }
+            result.rolled_back = result.error.is_some();
+        }
+        Ok(result)
+    }
+
+    /// Returns the names of the parameters of a statement, as SQLite reports them.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - A string slice that holds the statement.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Option<String>>, KitError>` - The name of each parameter in index order, `None` for anonymous parameters.
+    fn parameter_names(&mut self, sql: &str) -> Result<Vec<Option<String>>, KitError> {
+        let stmt = match self.conn.prepare(sql) {
+            Ok(stmt) => stmt,
+            Err(e) => return Err(query_error(e, sql)),
+        };
+        Ok((1..=stmt.parameter_count())
+            .map(|i| stmt.parameter_name(i).map(|name| name.to_string()))
+            .collect())
+    }
+
+    /// Runs a single statement with bound parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - A string slice that holds the statement.
+    /// * `params` - The values bound to the placeholders of the statement, in order.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ResultRows, KitError>` - The column names and rows of the result.
+    fn query_rows(
+        &mut self,
+        sql: &str,
+        params: &[SerializableValue],
+    ) -> Result<ResultRows, KitError> {
+        self.tracked(sql, |conn| {
+            let mut stmt = match conn.prepare(sql) {
+                Ok(stmt) => stmt,
+                Err(e) => return Err(query_error(e, sql)),
+            };
+            let total_cols = stmt.column_count();
+            let names = stmt
+                .column_names()
+                .iter()
+                .map(|name| name.to_string())
+                .collect();
+            let rows: Result<Vec<Vec<SerializableValue>>, _> = stmt
+                .query_map(params_from_iter(params.iter()), |row| {
+                    let mut cols = Vec::new();
+                    for i in 0..total_cols {
+                        let value: rusqlite::types::Value = row.get(i)?;
+                        cols.push(SerializableValue::from(value));
+                    }
+                    Ok(cols)
+                })
+                .and_then(|rows| rows.collect());
+            match rows {
+                Ok(rows) => Ok((names, rows)),
+                Err(e) => Err(query_error(e, sql)),
+            }
+        })
+    }
+
+    /// Starts a transaction, so edits only take effect once it is committed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if a transaction is already open.
+    fn begin_edits(&mut self) -> Result<(), KitError> {
+        self.execute_batch("BEGIN")
+    }
+
+    /// Commits the transaction started by `begin_edits`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if the transaction could not be committed; it stays open.
+    fn commit_edits(&mut self) -> Result<(), KitError> {
+        self.execute_batch("COMMIT")
+    }
+
+    /// Rolls back the transaction started by `begin_edits`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), KitError>` - An error if the transaction could not be rolled back.
+    fn rollback_edits(&mut self) -> Result<(), KitError> {
+        // SQLite may already have rolled back after an error such as a full disk
+        if self.conn.is_autocommit() {
+            return Ok(());
+        }
+        self.execute_batch("ROLLBACK")
+    }
+
+    /// Opens a read-only connection to the same database file.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Box<dyn DbManagerTrait + Send>>, ConnectionError>` - The new connection,
+    ///   or `None` for an in-memory or temporary database, which cannot be opened twice.
+    fn open_reader(&self) -> Result<Option<Box<dyn DbManagerTrait + Send>>, ConnectionError> {
+        match self.conn.path().and_then(Path::to_str) {
+            Some(path) if !path.is_empty() => {
+                Ok(Some(Box::new(NativeDbManager::open_read_only(path)?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Inserts rows into a table inside one savepoint, with a single prepared statement.
+    /// A row that fails, e.g. on a constraint, only undoes its own insertion.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `columns` - The columns the values are given for.
+    /// * `rows` - The rows to insert, with one value per column.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<(usize, KitError)>, KitError>` - The index and error of each row that failed,
+    ///   or an error if the statement could not be prepared or the savepoint could not be released.
+    fn insert_rows(
+        &mut self,
+        table_name: &str,
+        columns: &[String],
+        rows: &[Vec<SerializableValue>],
+    ) -> Result<Vec<(usize, KitError)>, KitError> {
+        let statement = sql::insert_row(
+            None,
+            table_name,
+            columns,
+            vec![SerializableValue::Null; columns.len()],
+        )?;
+        self.execute_batch("SAVEPOINT kit_insert_rows")?;
+        let mut failed = Vec::new();
+        let inserted = self.conn.prepare(&statement.sql).map(|mut stmt| {
+            for (index, row) in rows.iter().enumerate() {
+                if let Err(e) = stmt.execute(params_from_iter(row.iter())) {
+                    failed.push((index, query_error(e, &statement.sql)));
+                }
+            }
+        });
+        if let Err(e) = inserted {
+            let _ = self.execute_batch("ROLLBACK TO kit_insert_rows; RELEASE kit_insert_rows");
+            return Err(query_error(e, &statement.sql));
+        }
+        self.execute_batch("RELEASE kit_insert_rows")?;
+        Ok(failed)
+    }
 
-                    println!(
-                        "Total rows in table from query: {:?}",
-                        total_rows_in_table_from_query
-                    );
+    /// Returns the number of rows inserted, updated or deleted on the connection since it was
+    /// opened. Schema changes are not counted; other connections see them in their
+    /// `PRAGMA data_version` once they are committed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u64, KitError>` - The `total_changes()` of the connection.
+    fn total_changes(&mut self) -> Result<u64, KitError> {
+        let sql = "SELECT total_changes()";
+        self.conn
+            .query_row(sql, [], |row| row.get::<_, i64>(0))
+            .map(|changes| changes as u64)
+            .map_err(|e| query_error(e, sql))
+    }
 
-                    Ok(TableRequest {
-                        column_names,
-                        rows: rows.clone(),
-                        row_count: total_rows_in_table_from_query,
-                    })
+    /// Reports the rows each transaction changes once it commits, with SQLite's update, commit
+    /// and rollback hooks. Changes that are rolled back, also to a savepoint, are not reported.
+    /// SQLite does not report the rows of `WITHOUT ROWID` tables, nor of its internal tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Called with the changed rows once the statement that committed them returns.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Always `true`.
+    fn watch_changes(&mut self, listener: CommitListener) -> bool {
+        let tracker = Arc::new(Mutex::new(ChangeTracker::new(listener)));
+        let updated = tracker.clone();
+        self.conn.update_hook(Some(
+            move |action: Action, schema: &str, table_name: &str, rowid: i64| {
+                let operation = match action {
+                    Action::SQLITE_INSERT => ChangeOperation::Insert,
+                    Action::SQLITE_UPDATE => ChangeOperation::Update,
+                    Action::SQLITE_DELETE => ChangeOperation::Delete,
+                    _ => return,
+                };
+                if let Ok(mut tracker) = updated.lock() {
+                    tracker.record(schema, table_name, operation, rowid);
                 }
-                None => Ok(TableRequest {
-                    column_names: vec![],
-                    rows: vec![],
-                    row_count: 0,
-                }),
             },
-            Err(e) => Err(e.to_string()),
-        }
+        ));
+        let rolled_back = tracker.clone();
+        self.conn.rollback_hook(Some(move || {
+            if let Ok(mut tracker) = rolled_back.lock() {
+                tracker.rollback();
+            }
+        }));
+        let committed = tracker.clone();
+        self.conn.commit_hook(Some(move || {
+            if let Ok(mut tracker) = committed.lock() {
+                tracker.commit();
+            }
+            // returning `true` would turn the commit into a rollback
+            false
+        }));
+        self.changes = Some(tracker);
+        true
     }
 }
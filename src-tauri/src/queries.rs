@@ -0,0 +1,154 @@
+/// The `KitError` enum and the types it is built from.
+use crate::error::{Backend, KitError, SqlError};
+/// The `HashMap` type, which holds the running queries by id.
+use std::collections::HashMap;
+/// The `AtomicBool` type, which records that a query was cancelled.
+use std::sync::atomic::{AtomicBool, Ordering};
+/// The `Arc` and `Mutex` types, which share a query's control with `cancel_query`.
+use std::sync::{Arc, Mutex};
+/// The `Duration` and `Instant` types, used for timeouts.
+use std::time::{Duration, Instant};
+
+/// `QueryId` identifies a running query. It is chosen by the frontend, so the query can be
+/// cancelled while the command that runs it has not returned yet.
+pub type QueryId = String;
+
+/// `Interrupt` is the callback that interrupts a running query right away.
+type Interrupt = Box<dyn Fn() + Send>;
+
+/// `QueryControl` is a struct that lets a running query be cancelled or timed out.
+///
+/// It is cheap to clone; every clone controls the same query. Backends poll `should_stop`
+/// while the query runs and may register a callback that interrupts it right away.
+#[derive(Clone, Default)]
+pub struct QueryControl {
+    cancelled: Arc<AtomicBool>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    interrupt: Arc<Mutex<Option<Interrupt>>>,
+}
+
+/// `QueryControl` implementation.
+impl QueryControl {
+    /// Creates the control of a query that starts now and times out after `timeout`, if given.
+    pub fn new(timeout: Option<Duration>) -> Self {
+        QueryControl {
+            timeout,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            ..QueryControl::default()
+        }
+    }
+
+    /// Sets the callback that interrupts the query when it is cancelled.
+    /// It is called right away if the query was cancelled before it started.
+    pub fn on_cancel(&self, interrupt: impl Fn() + Send + 'static) {
+        if self.is_cancelled() {
+            interrupt();
+        }
+        *self.interrupt.lock().unwrap() = Some(Box::new(interrupt));
+    }
+
+    /// Cancels the query.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Some(interrupt) = self.interrupt.lock().unwrap().as_ref() {
+            interrupt();
+        }
+    }
+
+    /// Returns whether the query was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the query ran past its timeout.
+    pub fn is_timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns whether the query should stop, because it was cancelled or timed out.
+    pub fn should_stop(&self) -> bool {
+        self.is_cancelled() || self.is_timed_out()
+    }
+
+    /// Builds the `Interrupted` error of a query that stopped.
+    pub fn interrupted(&self, backend: Backend, sql: &str) -> KitError {
+        KitError::Interrupted(SqlError {
+            backend,
+            extended_code: None,
+            message: self.stop_reason(),
+            sql: Some(sql.to_string()),
+            offset: None,
+        })
+    }
+
+    /// Replaces the message of an `Interrupted` error with the reason the query was stopped.
+    /// Other errors are returned unchanged.
+    pub fn explain(&self, error: KitError) -> KitError {
+        match error {
+            KitError::Interrupted(mut error) if self.should_stop() => {
+                error.message = self.stop_reason();
+                KitError::Interrupted(error)
+            }
+            error => error,
+        }
+    }
+
+    /// Returns why the query was stopped.
+    fn stop_reason(&self) -> String {
+        match self.timeout {
+            Some(timeout) if !self.is_cancelled() => {
+                format!("the query timed out after {} ms", timeout.as_millis())
+            }
+            _ => "the query was cancelled".to_string(),
+        }
+    }
+}
+
+/// `RunningQueries` is a struct that holds the control of every query that can be cancelled, by id.
+#[derive(Default)]
+pub struct RunningQueries {
+    queries: Mutex<HashMap<QueryId, QueryControl>>,
+}
+
+/// `RunningQueries` implementation.
+impl RunningQueries {
+    /// Registers a query until the returned guard is dropped.
+    /// Queries without an id cannot be cancelled, but still time out.
+    pub fn start(&self, id: Option<QueryId>, control: &QueryControl) -> RunningQuery<'_> {
+        if let Some(id) = &id {
+            self.queries
+                .lock()
+                .unwrap()
+                .insert(id.clone(), control.clone());
+        }
+        RunningQuery { queries: self, id }
+    }
+
+    /// Cancels a running query. Returns whether there was a query with that id.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.queries.lock().unwrap().get(id) {
+            Some(control) => {
+                control.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// `RunningQuery` is a guard that unregisters a query when it is dropped.
+pub struct RunningQuery<'a> {
+    queries: &'a RunningQueries,
+    id: Option<QueryId>,
+}
+
+/// This implementation unregisters the query once it has finished.
+impl Drop for RunningQuery<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            self.queries.queries.lock().unwrap().remove(id);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/// The `Serialize` derive macro.
+use serde::Serialize;
+/// The `HashMap` and `HashSet` types, which hold the cached and running counts by statement.
+use std::collections::{HashMap, HashSet};
+
+/// `RowCountKind` is an enum that tells how far the `row_count` of a page can be trusted.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RowCountKind {
+    /// The number of matching rows.
+    Exact,
+    /// An estimate of the number of rows of the table, read from `sqlite_stat1` or the largest rowid.
+    Estimate,
+    /// The number of rows seen so far, when nothing better is known yet.
+    #[default]
+    AtLeast,
+}
+
+/// `CountSource` is an enum that names the connection the cache is checked on. Each connection
+/// numbers its own `PRAGMA data_version`, so the versions are compared per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CountSource {
+    /// The connection pages are read on.
+    Reader,
+    /// The connection the counts are run on.
+    Counter,
+}
+
+/// `RowCounts` is a struct that caches the exact row counts of a connection, keyed by the
+/// statement that counts them.
+///
+/// The cache is cleared when a change is detected: a job of the writer that changed rows, or a
+/// new `PRAGMA data_version` on a connection the cache is checked on, which moves whenever
+/// another connection or process commits. Each clear starts a new generation, so a count that
+/// was started before the change is not stored after it. The statements being counted are
+/// tracked too, so a count is not started again while it runs.
+#[derive(Debug, Default)]
+pub struct RowCounts {
+    generation: u64,
+    data_versions: HashMap<CountSource, Option<i64>>,
+    counts: HashMap<String, i64>,
+    running: HashSet<String>,
+}
+
+/// `RowCounts` implementation.
+impl RowCounts {
+    /// Clears the cache, because the rows may have changed.
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+        self.counts.clear();
+    }
+
+    /// Clears the cache if `data_version` differs from the one `source` was last checked at.
+    pub fn check(&mut self, source: CountSource, data_version: Option<i64>) {
+        if self.data_versions.insert(source, data_version) != Some(data_version) {
+            self.invalidate();
+        }
+    }
+
+    /// Returns the current generation, to be passed back to `store`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the cached count of a statement.
+    pub fn get(&self, key: &str) -> Option<i64> {
+        self.counts.get(key).copied()
+    }
+
+    /// Caches the count of a statement, unless the cache was cleared since `generation`.
+    pub fn store(&mut self, generation: u64, key: String, count: i64) {
+        if generation == self.generation {
+            self.counts.insert(key, count);
+        }
+    }
+
+    /// Marks a statement as being counted. Returns `false` if it already is, so the count is
+    /// not started twice.
+    pub fn start(&mut self, key: String) -> bool {
+        self.running.insert(key)
+    }
+
+    /// Marks a statement as no longer being counted, whether its count succeeded or not.
+    pub fn finish(&mut self, key: &str) {
+        self.running.remove(key);
+    }
+}
@@ -0,0 +1,321 @@
+/// The `QueryParams` enum.
+use crate::db_manager::QueryParams;
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `query_error` helper, which converts SQLite errors.
+use crate::native::query_error;
+/// The `now_ms` helper, which timestamps saved queries.
+use crate::store::now_ms;
+/// The `params` macro, the `OptionalExtension` trait and the `Connection` and `Row` types from the `rusqlite` crate.
+use rusqlite::{params, Connection, OptionalExtension, Row};
+/// The `Deserialize` and `Serialize` derive macros.
+use serde::{Deserialize, Serialize};
+/// The `HashSet` type, which keeps the names of exported files apart.
+use std::collections::HashSet;
+/// The `fs` module, used to read and write `.sql` folders.
+use std::fs;
+/// The `Path` type.
+use std::path::Path;
+
+/// The columns of a saved query, in the order `saved_query_from_row` reads them.
+const SAVED_QUERY_COLUMNS: &str =
+    "id, name, sql, description, params, location, created_at, updated_at";
+
+/// The comment that starts each header line of an exported `.sql` file, e.g. `-- name: Slow tables`.
+const HEADER_PREFIX: &str = "-- ";
+
+/// `SavedQuery` is a struct that holds a query kept in the library.
+#[derive(Serialize, Debug, Clone)]
+pub struct SavedQuery {
+    pub id: i64,
+    pub name: String,
+    pub sql: String,
+    pub description: String,
+    /// The values the parameters of the query default to.
+    pub params: Option<QueryParams>,
+    /// The database file path or libsql URL the query belongs to, or `None` for every database.
+    pub location: Option<String>,
+    /// When the query was saved, in milliseconds since the Unix epoch.
+    pub created_at: i64,
+    /// When the query was last changed, in milliseconds since the Unix epoch.
+    pub updated_at: i64,
+}
+
+/// `SavedQueryInput` is a struct that holds what is saved when a query is created or updated.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SavedQueryInput {
+    /// The name of the query, unique in the library.
+    pub name: String,
+    pub sql: String,
+    pub description: Option<String>,
+    pub params: Option<QueryParams>,
+    pub location: Option<String>,
+}
+
+/// Lists saved queries by name. With a `location`, only the queries bound to that database
+/// and the ones bound to none are listed.
+pub fn list(conn: &Connection, location: Option<&str>) -> Result<Vec<SavedQuery>, KitError> {
+    let sql = format!(
+        "SELECT {} FROM saved_queries WHERE ?1 IS NULL OR location IS NULL OR location = ?1
+        ORDER BY name COLLATE NOCASE, id",
+        SAVED_QUERY_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| query_error(e, &sql))?;
+    let queries = stmt
+        .query_map([location], saved_query_from_row)
+        .and_then(|rows| rows.collect());
+    queries.map_err(|e| query_error(e, &sql))
+}
+
+/// Returns a saved query.
+pub fn get(conn: &Connection, id: i64) -> Result<SavedQuery, KitError> {
+    let sql = format!(
+        "SELECT {} FROM saved_queries WHERE id = ?",
+        SAVED_QUERY_COLUMNS
+    );
+    conn.query_row(&sql, [id], saved_query_from_row)
+        .optional()
+        .map_err(|e| query_error(e, &sql))?
+        .ok_or_else(|| KitError::invalid_argument(format!("there is no saved query {}", id)))
+}
+
+/// Saves a new query. Fails with a `Constraint` error if the name is taken.
+pub fn create(conn: &Connection, input: &SavedQueryInput) -> Result<SavedQuery, KitError> {
+    let params_json = validate(input)?;
+    let now = now_ms();
+    let sql = "INSERT INTO saved_queries (name, sql, description, params, location, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)";
+    conn.execute(
+        sql,
+        params![
+            input.name.trim(),
+            input.sql,
+            input.description.as_deref().unwrap_or_default(),
+            params_json,
+            input.location,
+            now,
+            now
+        ],
+    )
+    .map_err(|e| query_error(e, sql))?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// Replaces what is saved for a query. Fails with a `Constraint` error if the name is taken
+/// by another query.
+pub fn update(conn: &Connection, id: i64, input: &SavedQueryInput) -> Result<SavedQuery, KitError> {
+    let params_json = validate(input)?;
+    let sql = "UPDATE saved_queries
+        SET name = ?, sql = ?, description = ?, params = ?, location = ?, updated_at = ?
+        WHERE id = ?";
+    let changed = conn
+        .execute(
+            sql,
+            params![
+                input.name.trim(),
+                input.sql,
+                input.description.as_deref().unwrap_or_default(),
+                params_json,
+                input.location,
+                now_ms(),
+                id
+            ],
+        )
+        .map_err(|e| query_error(e, sql))?;
+    if changed == 0 {
+        return Err(KitError::invalid_argument(format!(
+            "there is no saved query {}",
+            id
+        )));
+    }
+    get(conn, id)
+}
+
+/// Deletes a saved query. Returns whether there was a query with that id.
+pub fn delete(conn: &Connection, id: i64) -> Result<bool, KitError> {
+    let sql = "DELETE FROM saved_queries WHERE id = ?";
+    conn.execute(sql, [id])
+        .map(|changed| changed > 0)
+        .map_err(|e| query_error(e, sql))
+}
+
+/// Writes every saved query to `dir` as a `.sql` file, creating the folder if needed.
+///
+/// Each file holds the query after a header of `-- key: value` comments for its name,
+/// description, database and parameter defaults, so the folder can be shared and read
+/// back with `import_folder`. Files are named after the queries; other files are left alone.
+///
+/// Returns the number of files written.
+pub fn export_folder(conn: &Connection, dir: &Path) -> Result<usize, KitError> {
+    let queries = list(conn, None)?;
+    fs::create_dir_all(dir).map_err(|e| KitError::io(dir, e))?;
+    let mut file_names = HashSet::new();
+    for query in &queries {
+        let stem = file_stem(&query.name);
+        let mut file_name = format!("{}.sql", stem);
+        let mut n = 1;
+        while !file_names.insert(file_name.to_lowercase()) {
+            n += 1;
+            file_name = format!("{} ({}).sql", stem, n);
+        }
+        let path = dir.join(file_name);
+        fs::write(&path, to_sql_file(query)).map_err(|e| KitError::io(&path, e))?;
+    }
+    Ok(queries.len())
+}
+
+/// Saves the queries of the `.sql` files in `dir`, in file name order.
+///
+/// The header comments written by `export_folder` are read back; a file without a `name`
+/// is named after the file. A query with the name of one already saved replaces it.
+///
+/// Returns the saved queries that were imported.
+pub fn import_folder(conn: &mut Connection, dir: &Path) -> Result<Vec<SavedQuery>, KitError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| KitError::io(dir, e))? {
+        let path = entry.map_err(|e| KitError::io(dir, e))?.path();
+        let is_sql = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("sql"));
+        if is_sql && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut inputs = Vec::new();
+    for path in &paths {
+        let text = fs::read_to_string(path).map_err(|e| KitError::io(path, e))?;
+        let default_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let input = from_sql_file(&text, &default_name).map_err(|message| {
+            KitError::invalid_argument(format!("{}: {}", path.display(), message))
+        })?;
+        inputs.push(input);
+    }
+    let tx = conn.transaction().map_err(|e| query_error(e, "BEGIN"))?;
+    let mut imported = Vec::new();
+    for input in &inputs {
+        let existing = tx
+            .query_row(
+                "SELECT id FROM saved_queries WHERE name = ?",
+                [input.name.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| query_error(e, "SELECT id FROM saved_queries"))?;
+        let saved = match existing {
+            Some(id) => update(&tx, id, input)?,
+            None => create(&tx, input)?,
+        };
+        imported.push(saved);
+    }
+    tx.commit().map_err(|e| query_error(e, "COMMIT"))?;
+    Ok(imported)
+}
+
+/// Checks that a query can be saved, and returns its parameter defaults as JSON.
+fn validate(input: &SavedQueryInput) -> Result<Option<String>, KitError> {
+    if input.name.trim().is_empty() {
+        return Err(KitError::invalid_argument("a saved query needs a name"));
+    }
+    input
+        .params
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| KitError::invalid_argument(e.to_string()))
+}
+
+/// Returns a file name for a query name, without the characters file systems reject.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let stem = stem.trim().trim_matches('.');
+    match stem.is_empty() {
+        true => "query".to_string(),
+        false => stem.to_string(),
+    }
+}
+
+/// Writes a saved query as the contents of a `.sql` file.
+fn to_sql_file(query: &SavedQuery) -> String {
+    let mut text = format!("{}name: {}\n", HEADER_PREFIX, query.name);
+    for line in query.description.lines() {
+        text.push_str(&format!("{}description: {}\n", HEADER_PREFIX, line));
+    }
+    if let Some(location) = &query.location {
+        text.push_str(&format!("{}connection: {}\n", HEADER_PREFIX, location));
+    }
+    if let Some(params) = &query.params {
+        let params = serde_json::to_string(params).unwrap_or_default();
+        text.push_str(&format!("{}params: {}\n", HEADER_PREFIX, params));
+    }
+    text.push('\n');
+    text.push_str(query.sql.trim_end());
+    text.push('\n');
+    text
+}
+
+/// Reads a saved query from the contents of a `.sql` file. The header ends at the first line
+/// that is not a `-- key: value` comment with a known key; the rest is the query.
+fn from_sql_file(text: &str, default_name: &str) -> Result<SavedQueryInput, String> {
+    let mut input = SavedQueryInput {
+        name: default_name.to_string(),
+        sql: String::new(),
+        description: None,
+        params: None,
+        location: None,
+    };
+    let mut description: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(line) = rest.lines().next() {
+        let header = line
+            .strip_prefix(HEADER_PREFIX)
+            .and_then(|header| header.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim()));
+        match header {
+            Some(("name", value)) => input.name = value.to_string(),
+            Some(("description", value)) => description.push(value),
+            Some(("connection", value)) => input.location = Some(value.to_string()),
+            Some(("params", value)) => {
+                let params = serde_json::from_str(value)
+                    .map_err(|e| format!("invalid parameter defaults: {}", e))?;
+                input.params = Some(params);
+            }
+            _ => break,
+        }
+        rest = rest[line.len()..]
+            .strip_prefix("\r\n")
+            .or_else(|| rest[line.len()..].strip_prefix('\n'))
+            .unwrap_or_default();
+    }
+    if !description.is_empty() {
+        input.description = Some(description.join("\n"));
+    }
+    input.sql = rest.trim().to_string();
+    Ok(input)
+}
+
+/// Reads a saved query from a row of `SAVED_QUERY_COLUMNS`. Parameter defaults that cannot
+/// be read are dropped rather than hiding the query.
+fn saved_query_from_row(row: &Row) -> rusqlite::Result<SavedQuery> {
+    let params: Option<String> = row.get(4)?;
+    Ok(SavedQuery {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        sql: row.get(2)?,
+        description: row.get(3)?,
+        params: params.and_then(|params| serde_json::from_str(&params).ok()),
+        location: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
@@ -0,0 +1,500 @@
+/// The `DbManagerTrait` trait, the `KeyShape` enum, the `quote_identifier` helper and the SQL lexer from the `db_manager` module.
+use crate::db_manager::{
+    lexer::{self, Token, TokenKind},
+    sql::quote_identifier,
+    DbManagerTrait, KeyShape,
+};
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `SerializableValue` enum.
+use crate::SerializableValue;
+/// The `Serialize` derive macro.
+use serde::Serialize;
+
+/// `ColumnSchema` is a struct that describes a column as declared in the table definition.
+#[derive(Serialize, Debug, Clone)]
+pub struct ColumnSchema {
+    /// The position of the column in the table.
+    pub cid: i64,
+    pub name: String,
+    /// The type as written in `CREATE TABLE`, empty when no type was given.
+    pub declared_type: String,
+    /// The type affinity SQLite derives from the declared type.
+    pub affinity: String,
+    pub not_null: bool,
+    /// The default expression as written in `CREATE TABLE`.
+    pub default_value: Option<String>,
+    /// The 1-based position of the column in the primary key, `0` if it is not part of it.
+    pub primary_key: i64,
+    /// Whether the column is a hidden column of a virtual table.
+    pub hidden: bool,
+    /// `"virtual"` or `"stored"` for generated columns.
+    pub generated: Option<String>,
+    /// The collating sequence of the column, `BINARY` unless declared otherwise.
+    pub collation: String,
+}
+
+/// `ForeignKey` is a struct that describes one column mapping of a foreign key constraint.
+#[derive(Serialize, Debug, Clone)]
+pub struct ForeignKey {
+    /// The id of the constraint; composite keys have one entry per column with the same id.
+    pub id: i64,
+    pub seq: i64,
+    pub table: String,
+    pub from: String,
+    /// The referenced column, `None` when the constraint refers to the parent's primary key.
+    pub to: Option<String>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+/// `IndexSummary` is a struct that describes an index on a table.
+#[derive(Serialize, Debug, Clone)]
+pub struct IndexSummary {
+    pub name: String,
+    pub unique: bool,
+    /// `"c"` for `CREATE INDEX`, `"u"` for a `UNIQUE` constraint and `"pk"` for the primary key.
+    pub origin: String,
+    pub partial: bool,
+}
+
+/// `SchemaObject` is a struct that describes an entry of a schema's `sqlite_master` table.
+#[derive(Serialize, Debug, Clone)]
+pub struct SchemaObject {
+    /// The schema the object lives in, e.g. `main`, `temp` or the name of an attached database.
+    pub schema: String,
+    pub name: String,
+    /// `"table"`, `"virtual table"`, `"view"`, `"index"` or `"trigger"`.
+    pub object_type: String,
+    /// The table or view an index or trigger belongs to; the object itself for tables and views.
+    pub table_name: String,
+    /// The `CREATE` statement of the object, `None` for automatically created indexes.
+    pub sql: Option<String>,
+}
+
+/// `SchemaObject` implementation.
+impl SchemaObject {
+    /// Returns whether the object can be browsed like a table.
+    pub fn is_browsable(&self) -> bool {
+        matches!(
+            self.object_type.as_str(),
+            "table" | "virtual table" | "view"
+        )
+    }
+}
+
+/// `TableSchema` is a struct that holds the introspected definition of a table.
+#[derive(Serialize, Debug, Clone)]
+pub struct TableSchema {
+    pub schema: Option<String>,
+    pub table_name: String,
+    /// Whether the rows have a rowid, which is not the case for views and `WITHOUT ROWID` tables.
+    pub has_rowid: bool,
+    pub columns: Vec<ColumnSchema>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<IndexSummary>,
+}
+
+/// `TableSchema` implementation.
+impl TableSchema {
+    /// Returns how the rows of the table are identified, as `load_key_shape` does.
+    pub fn key_shape(&self) -> Option<KeyShape> {
+        let mut key_columns: Vec<&ColumnSchema> = self
+            .columns
+            .iter()
+            .filter(|column| column.primary_key > 0)
+            .collect();
+        key_columns.sort_by_key(|column| column.primary_key);
+        key_shape(
+            self.has_rowid,
+            key_columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+            self.columns.iter().map(|column| column.name.as_str()),
+        )
+    }
+
+    /// Returns the names of the columns a row can be inserted with, i.e. every column that is
+    /// neither hidden nor generated, in table order.
+    pub fn writable_columns(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|column| !column.hidden && column.generated.is_none())
+            .map(|column| column.name.clone())
+            .collect()
+    }
+}
+
+/// Returns the table-valued function call for a table pragma and the arguments to bind to it.
+///
+/// The table-valued form lets the table name be bound as a parameter, which works the same
+/// on native and libsql connections. Without a schema SQLite searches every attached schema.
+pub fn pragma_call(
+    pragma: &str,
+    schema: Option<&str>,
+    table_name: &str,
+) -> (String, Vec<SerializableValue>) {
+    let mut args = vec![SerializableValue::Text(table_name.to_string())];
+    match schema {
+        Some(schema) => {
+            args.push(SerializableValue::Text(schema.to_string()));
+            (format!("pragma_{}(?, ?)", pragma), args)
+        }
+        None => (format!("pragma_{}(?)", pragma), args),
+    }
+}
+
+/// Loads every table, view, index and trigger of every schema, including `temp` and attached databases.
+///
+/// Virtual tables are reported with the type `"virtual table"`; internal objects such as
+/// `sqlite_sequence` are included so nothing in the database is hidden.
+///
+/// # Arguments
+///
+/// * `db` - The database manager to query.
+///
+/// # Returns
+///
+/// * `Result<Vec<SchemaObject>, KitError>` - The objects ordered by schema, type and name.
+pub fn load_schema_objects(db: &mut dyn DbManagerTrait) -> Result<Vec<SchemaObject>, KitError> {
+    let (_, schema_rows) = db.query_rows("SELECT name FROM pragma_database_list", &[])?;
+    let mut objects = Vec::new();
+    for schema in schema_rows.iter().filter_map(|row| text(&row[0])) {
+        let (_, rows) = db.query_rows(
+            &format!(
+                "SELECT type, name, tbl_name, sql FROM {}.sqlite_master \
+                 ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'view' THEN 1 \
+                 WHEN 'index' THEN 2 ELSE 3 END, name",
+                quote_identifier(&schema)
+            ),
+            &[],
+        )?;
+        for row in rows {
+            let sql = text(&row[3]);
+            let object_type = text(&row[0]).unwrap_or_default();
+            let is_virtual = object_type == "table"
+                && sql.as_ref().is_some_and(|sql| {
+                    sql.trim_start()
+                        .to_uppercase()
+                        .starts_with("CREATE VIRTUAL TABLE")
+                });
+            objects.push(SchemaObject {
+                schema: schema.clone(),
+                name: text(&row[1]).unwrap_or_default(),
+                object_type: if is_virtual {
+                    "virtual table".to_string()
+                } else {
+                    object_type
+                },
+                table_name: text(&row[2]).unwrap_or_default(),
+                sql,
+            });
+        }
+    }
+    Ok(objects)
+}
+
+/// Loads the schema of a table using `PRAGMA table_xinfo`, `foreign_key_list` and `index_list`.
+///
+/// # Arguments
+///
+/// * `db` - The database manager to run the pragmas on.
+/// * `schema` - The attached schema of the table, `None` to search every schema.
+/// * `table_name` - The name of the table.
+///
+/// # Returns
+///
+/// * `Result<TableSchema, KitError>` - The schema of the table, or `TableNotFound` if the table does not exist.
+pub fn load_table_schema(
+    db: &mut dyn DbManagerTrait,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<TableSchema, KitError> {
+    let create_sql = load_create_sql(db, schema, table_name)?;
+
+    let (table_info, args) = pragma_call("table_xinfo", schema, table_name);
+    let (_, column_rows) = db.query_rows(
+        &format!(
+            "SELECT cid, name, type, \"notnull\", dflt_value, pk, hidden FROM {}",
+            table_info
+        ),
+        &args,
+    )?;
+    if column_rows.is_empty() {
+        return Err(KitError::TableNotFound {
+            schema: schema.map(|schema| schema.to_string()),
+            table_name: table_name.to_string(),
+        });
+    }
+    let columns = column_rows
+        .iter()
+        .map(|row| {
+            let name = text(&row[1]).unwrap_or_default();
+            let declared_type = text(&row[2]).unwrap_or_default();
+            let hidden = integer(&row[6]);
+            ColumnSchema {
+                cid: integer(&row[0]),
+                affinity: affinity(&declared_type).to_string(),
+                collation: declared_collation(&create_sql, &name)
+                    .unwrap_or_else(|| "BINARY".to_string()),
+                name,
+                declared_type,
+                not_null: integer(&row[3]) != 0,
+                default_value: text(&row[4]),
+                primary_key: integer(&row[5]),
+                hidden: hidden == 1,
+                generated: match hidden {
+                    2 => Some("virtual".to_string()),
+                    3 => Some("stored".to_string()),
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+
+    let (foreign_key_list, args) = pragma_call("foreign_key_list", schema, table_name);
+    let (_, foreign_key_rows) = db.query_rows(
+        &format!(
+            "SELECT id, seq, \"table\", \"from\", \"to\", on_update, on_delete FROM {}",
+            foreign_key_list
+        ),
+        &args,
+    )?;
+    let foreign_keys = foreign_key_rows
+        .iter()
+        .map(|row| ForeignKey {
+            id: integer(&row[0]),
+            seq: integer(&row[1]),
+            table: text(&row[2]).unwrap_or_default(),
+            from: text(&row[3]).unwrap_or_default(),
+            to: text(&row[4]),
+            on_update: text(&row[5]).unwrap_or_default(),
+            on_delete: text(&row[6]).unwrap_or_default(),
+        })
+        .collect();
+
+    let (index_list, args) = pragma_call("index_list", schema, table_name);
+    let (_, index_rows) = db.query_rows(
+        &format!(
+            "SELECT name, \"unique\", origin, partial FROM {}",
+            index_list
+        ),
+        &args,
+    )?;
+    let indexes = index_rows
+        .iter()
+        .map(|row| IndexSummary {
+            name: text(&row[0]).unwrap_or_default(),
+            unique: integer(&row[1]) != 0,
+            origin: text(&row[2]).unwrap_or_default(),
+            partial: integer(&row[3]) != 0,
+        })
+        .collect();
+
+    Ok(TableSchema {
+        schema: schema.map(|schema| schema.to_string()),
+        table_name: table_name.to_string(),
+        has_rowid: has_rowid(&create_sql),
+        columns,
+        foreign_keys,
+        indexes,
+    })
+}
+
+/// Loads the declared schema of a table, or `None` if there is no such table.
+///
+/// # Arguments
+///
+/// * `db` - The database manager to query.
+/// * `schema` - The attached schema of the table, `None` to search every schema.
+/// * `table_name` - The name of the table.
+///
+/// # Returns
+///
+/// * `Result<Option<TableSchema>, KitError>` - The schema of the table, or `None` if the table does not exist.
+pub fn load_table_schema_if_exists(
+    db: &mut dyn DbManagerTrait,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<Option<TableSchema>, KitError> {
+    match load_table_schema(db, schema, table_name) {
+        Ok(schema) => Ok(Some(schema)),
+        Err(KitError::TableNotFound { .. }) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Loads how the rows of a table are identified.
+///
+/// The declared primary key is preferred, because rowids of tables without an `INTEGER PRIMARY KEY`
+/// may change when the database is vacuumed. Tables without a primary key fall back to the rowid,
+/// unless a column shadows the `rowid` name.
+///
+/// # Arguments
+///
+/// * `db` - The database manager to query.
+/// * `schema` - The attached schema of the table, `None` to search every schema.
+/// * `table_name` - The name of the table.
+///
+/// # Returns
+///
+/// * `Result<Option<KeyShape>, KitError>` - The shape of the key, or `None` for views and other rows that cannot be identified.
+pub fn load_key_shape(
+    db: &mut dyn DbManagerTrait,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<Option<KeyShape>, KitError> {
+    let create_sql = load_create_sql(db, schema, table_name)?;
+    let (table_info, args) = pragma_call("table_xinfo", schema, table_name);
+    let (_, column_rows) = db.query_rows(
+        &format!("SELECT name, pk FROM {} ORDER BY pk", table_info),
+        &args,
+    )?;
+    let key_columns: Vec<String> = column_rows
+        .iter()
+        .filter(|row| integer(&row[1]) > 0)
+        .filter_map(|row| text(&row[0]))
+        .collect();
+    let names: Vec<String> = column_rows.iter().filter_map(|row| text(&row[0])).collect();
+    Ok(key_shape(
+        has_rowid(&create_sql),
+        key_columns,
+        names.iter().map(|name| name.as_str()),
+    ))
+}
+
+/// Returns the key shape of a table given its primary key columns, in key order, and the
+/// names of all its columns.
+fn key_shape<'a>(
+    has_rowid: bool,
+    key_columns: Vec<String>,
+    mut column_names: impl Iterator<Item = &'a str>,
+) -> Option<KeyShape> {
+    if !key_columns.is_empty() {
+        Some(KeyShape::PrimaryKey(key_columns))
+    } else if has_rowid && !column_names.any(|name| name.eq_ignore_ascii_case("rowid")) {
+        Some(KeyShape::Rowid)
+    } else {
+        None
+    }
+}
+
+/// Loads the `CREATE TABLE` statement of a table, or an empty string if there is no such table.
+fn load_create_sql(
+    db: &mut dyn DbManagerTrait,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<String, KitError> {
+    let (master_sql, master_args) = match schema {
+        Some(schema) => (
+            format!(
+                "SELECT sql FROM {}.sqlite_master WHERE type = 'table' AND name = ?",
+                quote_identifier(schema)
+            ),
+            vec![SerializableValue::Text(table_name.to_string())],
+        ),
+        None => (
+            "SELECT sql FROM sqlite_temp_master WHERE type = 'table' AND name = ?1 \
+             UNION ALL SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1"
+                .to_string(),
+            vec![SerializableValue::Text(table_name.to_string())],
+        ),
+    };
+    let (_, sql_rows) = db.query_rows(&master_sql, &master_args)?;
+    Ok(match sql_rows.first().and_then(|row| row.first()) {
+        Some(SerializableValue::Text(sql)) => sql.clone(),
+        _ => String::new(),
+    })
+}
+
+/// Returns whether a table created by `create_sql` has a rowid.
+/// Views have no `CREATE TABLE` statement, so they never do.
+fn has_rowid(create_sql: &str) -> bool {
+    !create_sql.is_empty()
+        && !significant_tokens(create_sql).windows(2).any(|pair| {
+            pair[0].text.eq_ignore_ascii_case("WITHOUT")
+                && pair[1].text.eq_ignore_ascii_case("ROWID")
+        })
+}
+
+/// Returns the type affinity SQLite assigns to a declared column type.
+///
+/// The rules are applied in the order given in section 3.1 of the SQLite datatype documentation.
+pub fn affinity(declared_type: &str) -> &'static str {
+    let declared_type = declared_type.to_uppercase();
+    if declared_type.contains("INT") {
+        "INTEGER"
+    } else if declared_type.contains("CHAR")
+        || declared_type.contains("CLOB")
+        || declared_type.contains("TEXT")
+    {
+        "TEXT"
+    } else if declared_type.contains("BLOB") || declared_type.is_empty() {
+        "BLOB"
+    } else if declared_type.contains("REAL")
+        || declared_type.contains("FLOA")
+        || declared_type.contains("DOUB")
+    {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
+
+/// Finds the `COLLATE` clause of a column in a `CREATE TABLE` statement.
+///
+/// SQLite does not report column collations through any pragma, so the column definitions
+/// are split out of the statement while skipping quoted names, string literals and comments.
+fn declared_collation(create_sql: &str, column_name: &str) -> Option<String> {
+    let body_start = create_sql.find('(')? + 1;
+    let mut definitions = vec![Vec::new()];
+    let mut depth = 0;
+    for token in significant_tokens(&create_sql[body_start..]) {
+        match token.text {
+            "(" => depth += 1,
+            ")" if depth == 0 => break,
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                definitions.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        definitions.last_mut()?.push(token);
+    }
+    definitions
+        .iter()
+        .find(|tokens| {
+            tokens
+                .first()
+                .is_some_and(|name| lexer::unquote(name.text).eq_ignore_ascii_case(column_name))
+        })?
+        .windows(2)
+        .find(|pair| pair[0].text.eq_ignore_ascii_case("COLLATE"))
+        .map(|pair| lexer::unquote(pair[1].text).to_uppercase())
+}
+
+/// Returns the tokens of a piece of SQL, without whitespace and comments.
+fn significant_tokens(sql: &str) -> Vec<Token<'_>> {
+    lexer::tokenize(sql)
+        .into_iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .collect()
+}
+
+/// Returns the text of a value, or `None` if it is not text.
+fn text(value: &SerializableValue) -> Option<String> {
+    match value {
+        SerializableValue::Text(text) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the integer of a value, or `0` if it is not an integer.
+fn integer(value: &SerializableValue) -> i64 {
+    match value {
+        SerializableValue::Integer(int) => *int,
+        _ => 0,
+    }
+}
@@ -0,0 +1,122 @@
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `query_error` helper, which converts SQLite errors.
+use crate::native::query_error;
+/// The `Connection` type from the `rusqlite` crate.
+use rusqlite::Connection;
+/// The `Path` type.
+use std::path::Path;
+/// The `Mutex` type, which serializes access to the store.
+use std::sync::Mutex;
+
+/// The name of the store file in the app-data directory.
+pub const STORE_FILE: &str = "kit.db";
+
+/// The migrations of the store schema, in order. `PRAGMA user_version` holds how many have
+/// been applied, so new migrations are only ever appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: query history, with a full-text index over the statements
+    "CREATE TABLE history (
+        id INTEGER PRIMARY KEY,
+        backend TEXT NOT NULL,
+        location TEXT NOT NULL,
+        sql TEXT NOT NULL,
+        executed_at INTEGER NOT NULL,
+        elapsed_ms REAL,
+        row_count INTEGER,
+        error TEXT,
+        starred INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX history_executed_at ON history (executed_at);
+    CREATE VIRTUAL TABLE history_fts USING fts5 (sql, content = 'history', content_rowid = 'id');
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, sql) VALUES (new.id, new.sql);
+    END;
+    CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, sql) VALUES ('delete', old.id, old.sql);
+    END;",
+    // 2: saved queries
+    "CREATE TABLE saved_queries (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        sql TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        params TEXT,
+        location TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );",
+];
+
+/// Returns the current time in milliseconds since the Unix epoch, as timestamps are kept in the store.
+pub fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// `AppStore` is a struct that holds the application's own SQLite database, kept in the
+/// app-data directory, where data that outlives a session is stored.
+pub struct AppStore {
+    conn: Mutex<Connection>,
+}
+
+/// `AppStore` implementation.
+impl AppStore {
+    /// Opens the store in `dir`, creating it if needed. If there is no app-data directory, or the
+    /// store cannot be opened there, an in-memory store is used so the application still starts;
+    /// nothing is kept after a restart then.
+    pub fn open(dir: Option<&Path>) -> Self {
+        let opened = match dir {
+            Some(dir) => std::fs::create_dir_all(dir)
+                .map_err(|e| KitError::invalid_state(e.to_string()))
+                .and_then(|_| AppStore::open_file(&dir.join(STORE_FILE))),
+            None => Err(KitError::invalid_state("there is no app-data directory")),
+        };
+        opened.unwrap_or_else(|e| {
+            println!("Keeping the app store in memory: {}", e);
+            let conn = Connection::open_in_memory().expect("an in-memory database always opens");
+            AppStore::migrate(&conn).expect("the store migrations are valid");
+            AppStore {
+                conn: Mutex::new(conn),
+            }
+        })
+    }
+
+    /// Opens the store file at `path` and brings its schema up to date.
+    pub fn open_file(path: &Path) -> Result<Self, KitError> {
+        let conn = Connection::open(path).map_err(|e| query_error(e, "open"))?;
+        AppStore::migrate(&conn)?;
+        Ok(AppStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Applies the migrations that have not been applied yet, each in its own transaction.
+    fn migrate(conn: &Connection) -> Result<(), KitError> {
+        let applied: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| query_error(e, "PRAGMA user_version"))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let sql = format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            );
+            if let Err(e) = conn.execute_batch(&sql) {
+                let _ = conn.execute_batch("ROLLBACK");
+                return Err(query_error(e, migration));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on the store connection.
+    pub fn run<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, KitError>,
+    ) -> Result<T, KitError> {
+        f(&mut self.conn.lock().unwrap())
+    }
+}
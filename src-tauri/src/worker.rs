@@ -0,0 +1,81 @@
+/// The `ConnectionError` enum and the `DbManager` struct from the `db_manager` module.
+use crate::db_manager::{ConnectionError, DbManager};
+/// The `KitError` enum.
+use crate::error::KitError;
+/// The `catch_unwind` function, which keeps a worker alive when a job panics.
+use std::panic::{catch_unwind, AssertUnwindSafe};
+/// The channel types, used to send jobs to a worker.
+use std::sync::mpsc;
+/// The `thread` module, used to start workers.
+use std::thread;
+/// The `oneshot` channel, used to send the result of a job back to the command awaiting it.
+use tokio::sync::oneshot;
+
+/// `Job` is a unit of work run by a worker on its database manager.
+type Job = Box<dyn FnOnce(&mut DbManager) + Send>;
+
+/// `Worker` is a struct that owns a database manager on a dedicated thread.
+///
+/// Commands never touch the database manager directly: they send it a job and await the result,
+/// so a slow query only holds up the jobs sent to the same worker. Jobs run one at a time,
+/// in the order they were sent. The thread stops once every clone of the worker is dropped.
+#[derive(Clone)]
+pub struct Worker {
+    jobs: mpsc::Sender<Job>,
+}
+
+/// `Worker` implementation.
+impl Worker {
+    /// Starts a worker thread that owns `db_manager`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the thread, shown by debuggers and in errors. It must not hold an
+    ///   auth token.
+    /// * `db_manager` - The database manager the jobs run on.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Worker, ConnectionError>` - The new worker, or an error if the thread could not be started.
+    pub fn spawn(name: String, mut db_manager: DbManager) -> Result<Self, ConnectionError> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || {
+                for job in receiver {
+                    // a panicking job drops its result sender, which the command reports as an error
+                    let _ = catch_unwind(AssertUnwindSafe(|| job(&mut db_manager)));
+                }
+            })
+            .map_err(|e| ConnectionError::Failed {
+                location: name,
+                message: e.to_string(),
+            })?;
+        Ok(Worker { jobs })
+    }
+
+    /// Runs a job on the worker's database manager and waits for its result without blocking.
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - The work to run.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<T, KitError>` - The result of the job, or `InvalidState` if the worker stopped before it finished.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut DbManager) -> Result<T, KitError> + Send + 'static,
+    ) -> Result<T, KitError> {
+        let (sender, receiver) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |db_manager| {
+                // the command may have gone away, in which case nobody needs the result
+                let _ = sender.send(job(db_manager));
+            }))
+            .map_err(|_| KitError::invalid_state("the connection was closed"))?;
+        receiver.await.map_err(|_| {
+            KitError::invalid_state("the connection stopped before the job finished")
+        })?
+    }
+}